@@ -71,6 +71,64 @@ impl Algorithm {
 			}
 		}
 	}
+
+	/// Whether the algorithm can make keyed digests with a `HashKey`: BLAKE3 in
+	/// keyed mode, SHA2 and SHA3 as HMACs.
+	pub fn is_keyable(&self) -> bool {
+		matches!(
+			*self,
+			Algorithm::SHA2224
+				| Algorithm::SHA2256
+				| Algorithm::SHA2384
+				| Algorithm::SHA2512
+				| Algorithm::SHA3224
+				| Algorithm::SHA3256
+				| Algorithm::SHA3384
+				| Algorithm::SHA3512
+				| Algorithm::BLAKE3
+		)
+	}
+
+	/// Whether files with the same digest can be taken to hold the same bytes,
+	/// unlike with checksums like CRC32 and xxHash.
+	pub fn is_cryptographic(&self) -> bool {
+		!matches!(
+			*self,
+			Algorithm::CRC32 | Algorithm::XXH32 | Algorithm::XXH64 | Algorithm::XXH3
+		)
+	}
+
+	/// Name of the algorithm in BSD-style tagged manifests, as written by
+	/// `shasum --tag`, `b2sum --tag` and the like.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// assert_eq!(quickdash::Algorithm::SHA2256.tag(), "SHA256");
+	/// assert_eq!(quickdash::Algorithm::SHA3256.tag(), "SHA3-256");
+	/// ```
+	pub fn tag(&self) -> &'static str {
+		match *self {
+			Algorithm::SHA1 => "SHA1",
+			Algorithm::SHA2224 => "SHA224",
+			Algorithm::SHA2256 => "SHA256",
+			Algorithm::SHA2384 => "SHA384",
+			Algorithm::SHA2512 => "SHA512",
+			Algorithm::SHA3224 => "SHA3-224",
+			Algorithm::SHA3256 => "SHA3-256",
+			Algorithm::SHA3384 => "SHA3-384",
+			Algorithm::SHA3512 => "SHA3-512",
+			Algorithm::XXH32 => "XXH32",
+			Algorithm::XXH64 => "XXH64",
+			Algorithm::XXH3 => "XXH3",
+			Algorithm::CRC32 => "CRC32",
+			Algorithm::MD5 => "MD5",
+			Algorithm::WhirlPool => "WHIRLPOOL",
+			Algorithm::BLAKE2B => "BLAKE2b",
+			Algorithm::BLAKE2S => "BLAKE2s",
+			Algorithm::BLAKE3 => "BLAKE3",
+		}
+	}
 }
 
 impl FromStr for Algorithm {
@@ -79,10 +137,10 @@ impl FromStr for Algorithm {
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		match &s.replace('_', "-").to_lowercase()[..] {
 			"sha-1" | "sha1" => Ok(Algorithm::SHA1),
-			"sha2224" | "sha-224" | "sha-2-224" => Ok(Algorithm::SHA2224),
-			"sha2256" | "sha-256" | "sha-2-256" => Ok(Algorithm::SHA2256),
-			"sha2384" | "sha-384" | "sha-2-384" => Ok(Algorithm::SHA2384),
-			"sha2512" | "sha-512" | "sha-2-512" => Ok(Algorithm::SHA2512),
+			"sha2224" | "sha224" | "sha-224" | "sha-2-224" => Ok(Algorithm::SHA2224),
+			"sha2256" | "sha256" | "sha-256" | "sha-2-256" => Ok(Algorithm::SHA2256),
+			"sha2384" | "sha384" | "sha-384" | "sha-2-384" => Ok(Algorithm::SHA2384),
+			"sha2512" | "sha512" | "sha-512" | "sha-2-512" => Ok(Algorithm::SHA2512),
 			"sha3224" | "sha3-224" | "sha-3-224" => Ok(Algorithm::SHA3224),
 			"sha3256" | "sha3-256" | "sha-3-256" => Ok(Algorithm::SHA3256),
 			"sha3384" | "sha3-384" | "sha-3-384" => Ok(Algorithm::SHA3384),
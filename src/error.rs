@@ -13,42 +13,252 @@
  * limitations under the License.
  */
 
+use std::{error, fmt, io, path::PathBuf};
+
+use crate::Algorithm;
+
 /// Enum representing each way the appication can fail.
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum Error {
-	/// No errors occured, everything executed correctly.
-	NoError,
 	/// Parsing of command-line options failed.
 	OptionParsingError,
 	/// Selected and saved hash lengths differ.
 	HashLengthDiffers,
 	/// Parsing the hashes file failed.
-	HashesFileParsingFailure,
+	HashesFileParsingFailure(ParseError),
 	/// The specified amount of files do not match.
 	NFilesDiffer(i32),
+	/// Some files couldn't be read.
+	FilesUnreadable,
+	/// The manifest to create already exists.
+	OutputExists(PathBuf),
+	/// No manifest was specified, and the directory has no name to name one
+	/// after.
+	NoDefaultManifest(PathBuf),
+	/// None of the digests to compare were made with the selected algorithms,
+	/// or with the same ones on both sides.
+	NoCommonAlgorithms,
+	/// Duplicates can't be replaced or deleted on the strength of digests made
+	/// with the specified algorithm.
+	NotCryptographic(Algorithm),
+	/// The specified duplicate, or the file it duplicates, changed since they
+	/// were compared.
+	DuplicateChanged(PathBuf),
+	/// Reading or writing the specified file failed.
+	Io { path: PathBuf, source: io::Error },
+	/// Walking the specified directory failed.
+	Walk {
+		path: PathBuf,
+		source: walkdir::Error,
+	},
+	/// The specified key or signature is unusable, or the signature doesn't
+	/// validate.
+	Signature { path: PathBuf, reason: String },
+	/// The key for keyed digests is missing, unusable, or not the one the
+	/// manifest was made with.
+	Key(String),
+	/// An ignore or include pattern is invalid.
+	Pattern(ParseError),
+	/// The hashing threads couldn't be started.
+	ThreadPool(rayon::ThreadPoolBuildError),
+	/// Writing the results failed.
+	Output(io::Error),
 }
 
 impl Error {
 	/// Get the executable exit value from an `Error` instance.
 	pub fn exit_value(&self) -> i32 {
 		match *self {
-			Error::NoError => 0,
 			Error::OptionParsingError => 1,
 			Error::HashLengthDiffers => 2,
-			Error::HashesFileParsingFailure => 3,
-			Error::NFilesDiffer(i) => i + 3,
+			Error::HashesFileParsingFailure(_) => 3,
+			// Kept below `FilesUnreadable`'s, so it doesn't wrap around to 0
+			Error::NFilesDiffer(i) => (i + 3).min(254),
+			Error::FilesUnreadable => 255,
+			Error::OutputExists(_)
+			| Error::NoDefaultManifest(_)
+			| Error::NoCommonAlgorithms
+			| Error::NotCryptographic(_)
+			| Error::DuplicateChanged(_)
+			| Error::Io { .. }
+			| Error::Walk { .. }
+			| Error::Signature { .. }
+			| Error::Key(_)
+			| Error::Pattern(_)
+			| Error::ThreadPool(_)
+			| Error::Output(_) => 1,
+		}
+	}
+
+	/// Describe what's wrong with the key or signature at `path`.
+	pub fn signature(path: impl Into<PathBuf>, reason: impl Into<String>) -> Error {
+		Error::Signature {
+			path: path.into(),
+			reason: reason.into(),
+		}
+	}
+
+	/// Wrap an I/O error with the path it occurred on.
+	pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+		Error::Io {
+			path: path.into(),
+			source,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::OptionParsingError => write!(f, "invalid options"),
+			Error::HashLengthDiffers => write!(f, "selected and saved hash lengths differ"),
+			Error::HashesFileParsingFailure(e) => write!(f, "failed to parse hashes file: {}", e),
+			Error::NFilesDiffer(1) => write!(f, "1 file doesn't match"),
+			Error::NFilesDiffer(n) => write!(f, "{} files don't match", n),
+			Error::FilesUnreadable => write!(f, "some files couldn't be read"),
+			Error::OutputExists(path) => write!(
+				f,
+				"{}: file already exists, use --force to overwrite it",
+				path.display()
+			),
+			Error::NoDefaultManifest(path) => write!(
+				f,
+				"{}: no directory name to name the manifest after, specify it with --file",
+				path.display()
+			),
+			Error::NoCommonAlgorithms => {
+				write!(
+					f,
+					"no digests to compare were made with the selected algorithms"
+				)
+			}
+			Error::NotCryptographic(algo) => write!(
+				f,
+				"{} digests can't tell duplicates apart safely, select another algorithm to apply \
+				 an action",
+				algo.tag()
+			),
+			Error::DuplicateChanged(path) => write!(
+				f,
+				"{}: changed since the duplicates were found, look for them again",
+				path.display()
+			),
+			Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+			Error::Walk { path, source } => {
+				write!(f, "failed to walk {}: {}", path.display(), source)
+			}
+			Error::Signature { path, reason } => write!(f, "{}: {}", path.display(), reason),
+			Error::Key(reason) => write!(f, "{}", reason),
+			Error::Pattern(e) => write!(f, "invalid pattern: {}", e),
+			Error::ThreadPool(e) => write!(f, "failed to start hashing threads: {}", e),
+			Error::Output(e) => write!(f, "failed to write results: {}", e),
 		}
 	}
 }
 
-impl From<i32> for Error {
-	fn from(i: i32) -> Self {
-		match i {
-			0 => Error::NoError,
-			1 => Error::OptionParsingError,
-			2 => Error::HashLengthDiffers,
-			3 => Error::HashesFileParsingFailure,
-			i => Error::NFilesDiffer(i - 3),
+impl error::Error for Error {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match self {
+			Error::HashesFileParsingFailure(e) | Error::Pattern(e) => Some(e),
+			Error::Io { source, .. } => Some(source),
+			Error::Walk { source, .. } => Some(source),
+			Error::ThreadPool(e) => Some(e),
+			Error::Output(e) => Some(e),
+			_ => None,
 		}
 	}
 }
+
+impl From<ParseError> for Error {
+	fn from(e: ParseError) -> Error {
+		Error::HashesFileParsingFailure(e)
+	}
+}
+
+/// A manifest line that couldn't be parsed.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ParseError {
+	/// The manifest. Empty until the error leaves the parser.
+	pub file: PathBuf,
+	/// 1-based number of the offending line, if a numbered line is at fault.
+	pub line: Option<usize>,
+	/// The offending line, empty if no line is at fault.
+	pub text: String,
+	/// What's wrong with it.
+	pub reason: String,
+}
+
+impl ParseError {
+	/// Describe a problem with the 1-based `line` of a manifest.
+	pub fn new(line: usize, text: &str, reason: impl Into<String>) -> ParseError {
+		ParseError {
+			file: PathBuf::new(),
+			line: Some(line),
+			text: text.to_string(),
+			reason: reason.into(),
+		}
+	}
+
+	/// Describe a problem with a line that has no number, like a pattern given
+	/// on the command line, or with no line at all if `text` is empty.
+	pub fn unnumbered(text: &str, reason: impl Into<String>) -> ParseError {
+		ParseError {
+			line: None,
+			..ParseError::new(0, text, reason)
+		}
+	}
+
+	/// Attach the manifest's path.
+	pub fn in_file(self, file: impl Into<PathBuf>) -> ParseError {
+		ParseError {
+			file: file.into(),
+			..self
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match (self.file.as_os_str().is_empty(), self.line) {
+			(true, None) => write!(f, "{}", self.reason)?,
+			(true, Some(line)) => write!(f, "line {}: {}", line, self.reason)?,
+			(false, None) => write!(f, "{}: {}", self.file.display(), self.reason)?,
+			(false, Some(line)) => write!(f, "{}:{}: {}", self.file.display(), line, self.reason)?,
+		}
+		match self.text.is_empty() {
+			true => Ok(()),
+			false => write!(f, ": {:?}", self.text),
+		}
+	}
+}
+
+impl error::Error for ParseError {}
+
+/// Failure to read a single file, e.g. for lack of permissions or because it
+/// was deleted while the tree was walked.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileError {
+	/// Name of the file, relative to the hashed directory.
+	pub file: String,
+	pub kind: io::ErrorKind,
+	/// Description of the underlying I/O error.
+	pub message: String,
+}
+
+impl FileError {
+	pub fn new(file: String, error: &io::Error) -> FileError {
+		FileError {
+			file,
+			kind: error.kind(),
+			message: error.to_string(),
+		}
+	}
+}
+
+impl fmt::Display for FileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.file, self.message)
+	}
+}
+
+impl error::Error for FileError {}
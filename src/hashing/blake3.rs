@@ -21,3 +21,13 @@ hash_func!(
 	},
 	|blake: blake3::Hasher| hash_string(blake.finalize().as_bytes())
 );
+
+pub(super) fn keyed_hasher(key: &[u8; 32]) -> Box<dyn super::HashState> {
+	Box::new(super::StreamingHash {
+		ctx: blake3::Hasher::new_keyed(key),
+		update: |blake: &mut blake3::Hasher, buffer: &[u8]| {
+			blake.update(buffer);
+		},
+		convert: |blake: blake3::Hasher| hash_string(blake.finalize().as_bytes()),
+	})
+}
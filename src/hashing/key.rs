@@ -0,0 +1,102 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{env, fmt, fs, path::Path};
+
+use crate::{hash_string, Error};
+
+const BLAKE3_CONTEXT: &str = "QuickDash 2022-04-20 BLAKE3 key";
+const KEY_ID_CONTEXT: &str = "QuickDash 2022-04-20 key ID";
+
+/// A secret key to make keyed digests with, so that whoever can change the
+/// files can't make matching digests without it.
+///
+/// BLAKE3 digests are made in keyed mode, SHA2 and SHA3 ones as HMACs. BLAKE3
+/// uses a key of exactly 32 bytes as is, like `b3sum --keyed` does, and one
+/// derived from it otherwise.
+///
+/// # Examples
+///
+/// ```
+/// # use quickdash::{hash_reader_keyed, Algorithm, HashKey};
+/// let key = HashKey::new(*b"key");
+/// let mut data = &b"The quick brown fox jumps over the lazy dog"[..];
+/// assert_eq!(
+/// 	hash_reader_keyed(Algorithm::SHA2256, &key, &mut data).unwrap(),
+/// 	"F7BC83F430538424B13298E6AA6FB143EF4D59A14946175997479DBC2D1A3CD8"
+/// );
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct HashKey {
+	key: Vec<u8>,
+}
+
+impl HashKey {
+	/// Use the specified bytes as a key.
+	pub fn new(key: impl Into<Vec<u8>>) -> HashKey {
+		HashKey { key: key.into() }
+	}
+
+	/// Read a key from the specified file, byte for byte.
+	pub fn from_file(path: &Path) -> Result<HashKey, Error> {
+		match fs::read(path).map_err(|e| Error::io(path, e))? {
+			key if key.is_empty() => Err(Error::Key(format!("{} is empty", path.display()))),
+			key => Ok(HashKey::new(key)),
+		}
+	}
+
+	/// Read a key from the specified environment variable, byte for byte on
+	/// Unix. Elsewhere, it has to be valid Unicode.
+	pub fn from_env(var: &str) -> Result<HashKey, Error> {
+		let key = env::var_os(var)
+			.filter(|key| !key.is_empty())
+			.ok_or_else(|| Error::Key(format!("${} is unset or empty", var)))?;
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::ffi::OsStrExt;
+			Ok(HashKey::new(key.as_bytes()))
+		}
+		#[cfg(not(unix))]
+		{
+			key.to_str()
+				.map(|key| HashKey::new(key.as_bytes()))
+				.ok_or_else(|| Error::Key(format!("${} isn't valid Unicode", var)))
+		}
+	}
+
+	/// An ID telling keys apart without giving them away, recorded in keyed
+	/// manifests.
+	pub fn id(&self) -> String {
+		hash_string(&blake3::derive_key(KEY_ID_CONTEXT, &self.key)[..8])
+	}
+
+	pub(super) fn bytes(&self) -> &[u8] {
+		&self.key
+	}
+
+	pub(super) fn blake3_key(&self) -> [u8; 32] {
+		match self.key[..].try_into() {
+			Ok(key) => key,
+			Err(_) => blake3::derive_key(BLAKE3_CONTEXT, &self.key),
+		}
+	}
+}
+
+impl fmt::Debug for HashKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("HashKey").field("id", &self.id()).finish()
+	}
+}
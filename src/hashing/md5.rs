@@ -17,4 +17,8 @@ use md5::{Digest, Md5};
 
 use crate::hash_string;
 
-hash_func_write!(Md5::new(), |ctx: Md5| hash_string(&*ctx.finalize()));
+hash_func!(
+	Md5::new(),
+	|md5: &mut Md5, buffer: &[u8]| md5.update(buffer),
+	|md5: Md5| hash_string(&md5.finalize())
+);
@@ -15,40 +15,38 @@
 
 macro_rules! hash_func {
 	($ctx:expr, $update:expr, $convert:expr) => {
-		use std::io::Read;
-
-		pub fn hash<R: Read>(reader: &mut R) -> String {
-			let mut buffer = vec![0; 4096];
-
-			let mut ctx = $ctx;
-			loop {
-				let read = reader.read(&mut buffer[..]).unwrap();
-
-				if read == 0 {
-					break;
-				}
-
-				$update(&mut ctx, &buffer[..read]);
-			}
-
-			$convert(ctx)
+		pub(super) fn hasher() -> Box<dyn super::HashState> {
+			Box::new(super::StreamingHash {
+				ctx: $ctx,
+				update: $update,
+				convert: $convert,
+			})
 		}
 	};
 }
 
-macro_rules! hash_func_write {
-	($ctx:expr, $convert:expr) => {
-		use std::io::{self, Read};
+macro_rules! hmac_func {
+	($digest:ty) => {
+		pub(super) fn keyed_hasher(key: &[u8]) -> Box<dyn super::HashState> {
+			use hmac::{Hmac, Mac};
 
-		pub fn hash<R: Read>(reader: &mut R) -> String {
-			let mut ctx = $ctx;
-			io::copy(reader, &mut ctx).unwrap();
-			$convert(ctx)
+			Box::new(super::StreamingHash {
+				// HMAC takes keys of any length
+				ctx: <Hmac<$digest> as Mac>::new_from_slice(key).unwrap(),
+				update: |mac: &mut Hmac<$digest>, buffer: &[u8]| mac.update(buffer),
+				convert: |mac: Hmac<$digest>| hash_string(&mac.finalize().into_bytes()),
+			})
 		}
 	};
 }
 
-use std::{fmt::Write, fs::File, io::Read, path::Path};
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	fs::File,
+	io::{self, Read},
+	path::Path,
+};
 
 use super::Algorithm;
 
@@ -56,6 +54,7 @@ mod blake2b;
 mod blake2s;
 mod blake3;
 mod crc32;
+mod key;
 mod md5;
 mod sha1;
 mod sha2_224;
@@ -71,33 +70,163 @@ mod xxh3;
 mod xxh32;
 mod xxh64;
 
+pub use self::key::HashKey;
+
+/// Digests of a single file, by the algorithm that produced them.
+pub type Digests = BTreeMap<Algorithm, String>;
+
+/// An in-progress hash computation.
+trait HashState {
+	fn update(&mut self, buffer: &[u8]);
+	fn finish(self: Box<Self>) -> String;
+}
+
+struct StreamingHash<C, U, F> {
+	ctx: C,
+	update: U,
+	convert: F,
+}
+
+impl<C, U, F> HashState for StreamingHash<C, U, F>
+where
+	U: Fn(&mut C, &[u8]),
+	F: Fn(C) -> String,
+{
+	fn update(&mut self, buffer: &[u8]) {
+		(self.update)(&mut self.ctx, buffer)
+	}
+
+	fn finish(self: Box<Self>) -> String {
+		let StreamingHash { ctx, convert, .. } = *self;
+		convert(ctx)
+	}
+}
+
+fn hasher(algo: Algorithm) -> Box<dyn HashState> {
+	match algo {
+		Algorithm::CRC32 => crc32::hasher(),
+		Algorithm::SHA1 => sha1::hasher(),
+		Algorithm::SHA2224 => sha2_224::hasher(),
+		Algorithm::SHA2256 => sha2_256::hasher(),
+		Algorithm::SHA2384 => sha2_384::hasher(),
+		Algorithm::SHA2512 => sha2_512::hasher(),
+		Algorithm::SHA3224 => sha3_224::hasher(),
+		Algorithm::SHA3256 => sha3_256::hasher(),
+		Algorithm::SHA3384 => sha3_384::hasher(),
+		Algorithm::SHA3512 => sha3_512::hasher(),
+		Algorithm::MD5 => md5::hasher(),
+		Algorithm::XXH64 => xxh64::hasher(),
+		Algorithm::XXH32 => xxh32::hasher(),
+		Algorithm::XXH3 => xxh3::hasher(),
+		Algorithm::BLAKE2B => blake2b::hasher(),
+		Algorithm::BLAKE2S => blake2s::hasher(),
+		Algorithm::BLAKE3 => blake3::hasher(),
+		Algorithm::WhirlPool => whirlpool::hasher(),
+	}
+}
+
+/// Make keyed digests with the specified key, if the algorithm supports it.
+fn keyed_hasher(algo: Algorithm, key: &HashKey) -> Option<Box<dyn HashState>> {
+	Some(match algo {
+		Algorithm::SHA2224 => sha2_224::keyed_hasher(key.bytes()),
+		Algorithm::SHA2256 => sha2_256::keyed_hasher(key.bytes()),
+		Algorithm::SHA2384 => sha2_384::keyed_hasher(key.bytes()),
+		Algorithm::SHA2512 => sha2_512::keyed_hasher(key.bytes()),
+		Algorithm::SHA3224 => sha3_224::keyed_hasher(key.bytes()),
+		Algorithm::SHA3256 => sha3_256::keyed_hasher(key.bytes()),
+		Algorithm::SHA3384 => sha3_384::keyed_hasher(key.bytes()),
+		Algorithm::SHA3512 => sha3_512::keyed_hasher(key.bytes()),
+		Algorithm::BLAKE3 => blake3::keyed_hasher(&key.blake3_key()),
+		_ => return None,
+	})
+}
+
 /// Hash the specified file using the specified hashing algorithm.
-pub fn hash_file(algo: Algorithm, path: &Path) -> String {
-	hash_reader(algo, &mut File::open(path).unwrap())
+pub fn hash_file(algo: Algorithm, path: &Path) -> io::Result<String> {
+	hash_reader(algo, &mut File::open(path)?)
+}
+
+/// Hash the specified file using all of the specified hashing algorithms in a
+/// single pass.
+pub fn hash_file_multi(algos: &[Algorithm], path: &Path) -> io::Result<Digests> {
+	hash_reader_multi(algos, &mut File::open(path)?)
+}
+
+/// Like `hash_file_multi()`, but make keyed digests if a key is specified.
+pub fn hash_file_multi_keyed(
+	algos: &[Algorithm],
+	key: Option<&HashKey>,
+	path: &Path,
+) -> io::Result<Digests> {
+	hash_reader_multi_keyed(algos, key, &mut File::open(path)?)
 }
 
 /// Hash the specified byte stream using the specified hashing algorithm.
-pub fn hash_reader<R: Read>(algo: Algorithm, data: &mut R) -> String {
-	match algo {
-		Algorithm::CRC32 => crc32::hash(data),
-		Algorithm::SHA1 => sha1::hash(data),
-		Algorithm::SHA2224 => sha2_224::hash(data),
-		Algorithm::SHA2256 => sha2_256::hash(data),
-		Algorithm::SHA2384 => sha2_384::hash(data),
-		Algorithm::SHA2512 => sha2_512::hash(data),
-		Algorithm::SHA3224 => sha3_224::hash(data),
-		Algorithm::SHA3256 => sha3_256::hash(data),
-		Algorithm::SHA3384 => sha3_384::hash(data),
-		Algorithm::SHA3512 => sha3_512::hash(data),
-		Algorithm::MD5 => md5::hash(data),
-		Algorithm::XXH64 => xxh64::hash(data),
-		Algorithm::XXH32 => xxh32::hash(data),
-		Algorithm::XXH3 => xxh3::hash(data),
-		Algorithm::BLAKE2B => blake2b::hash(data),
-		Algorithm::BLAKE2S => blake2s::hash(data),
-		Algorithm::BLAKE3 => blake3::hash(data),
-		Algorithm::WhirlPool => whirlpool::hash(data),
+pub fn hash_reader<R: Read>(algo: Algorithm, data: &mut R) -> io::Result<String> {
+	Ok(hash_reader_multi(&[algo], data)?.remove(&algo).unwrap())
+}
+
+/// Make a keyed digest of the specified byte stream using the specified
+/// algorithm, which has to be one of the `Algorithm::is_keyable()` ones.
+pub fn hash_reader_keyed<R: Read>(
+	algo: Algorithm,
+	key: &HashKey,
+	data: &mut R,
+) -> io::Result<String> {
+	Ok(hash_reader_multi_keyed(&[algo], Some(key), data)?
+		.remove(&algo)
+		.unwrap())
+}
+
+/// Hash the specified byte stream using all of the specified hashing
+/// algorithms, feeding each of them from the same read buffer.
+///
+/// Fails with the first read error other than `Interrupted`.
+pub fn hash_reader_multi<R: Read>(algos: &[Algorithm], data: &mut R) -> io::Result<Digests> {
+	hash_reader_multi_keyed(algos, None, data)
+}
+
+/// Like `hash_reader_multi()`, but make keyed digests if a key is specified.
+///
+/// Fails with `InvalidInput` if one of the algorithms can't make keyed
+/// digests.
+pub fn hash_reader_multi_keyed<R: Read>(
+	algos: &[Algorithm],
+	key: Option<&HashKey>,
+	data: &mut R,
+) -> io::Result<Digests> {
+	let mut buffer = vec![0; 4096];
+
+	let mut states = Vec::with_capacity(algos.len());
+	for &algo in algos {
+		let state = match key {
+			Some(key) => keyed_hasher(algo, key).ok_or_else(|| {
+				io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("{} can't make keyed digests", algo.tag()),
+				)
+			})?,
+			None => hasher(algo),
+		};
+		states.push((algo, state));
 	}
+	loop {
+		let read = match data.read(&mut buffer[..]) {
+			Ok(0) => break,
+			Ok(read) => read,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		};
+
+		for (_, state) in &mut states {
+			state.update(&buffer[..read]);
+		}
+	}
+
+	Ok(states
+		.into_iter()
+		.map(|(algo, state)| (algo, state.finish()))
+		.collect())
 }
 
 /// Create a hash string out of its raw bytes.
@@ -22,3 +22,5 @@ hash_func!(
 	|sha224: &mut Sha224, buffer: &[u8]| sha224.update(buffer),
 	|sha224: Sha224| { hash_string(&sha224.finalize()) }
 );
+
+hmac_func!(Sha224);
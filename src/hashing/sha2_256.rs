@@ -22,3 +22,5 @@ hash_func!(
 	|sha256: &mut Sha256, buffer: &[u8]| sha256.update(buffer),
 	|sha256: Sha256| { hash_string(&sha256.finalize()) }
 );
+
+hmac_func!(Sha256);
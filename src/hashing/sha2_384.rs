@@ -22,3 +22,5 @@ hash_func!(
 	|sha348: &mut Sha384, buffer: &[u8]| sha348.update(buffer),
 	|sha348: Sha384| { hash_string(&sha348.finalize()) }
 );
+
+hmac_func!(Sha384);
@@ -22,3 +22,5 @@ hash_func!(
 	|sha512: &mut Sha512, buffer: &[u8]| sha512.update(buffer),
 	|sha512: Sha512| { hash_string(&sha512.finalize()) }
 );
+
+hmac_func!(Sha512);
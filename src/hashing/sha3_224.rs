@@ -22,3 +22,5 @@ hash_func!(
 	|sha3224: &mut Sha3_224, buffer: &[u8]| sha3224.update(buffer),
 	|sha3224: Sha3_224| hash_string(&sha3224.finalize())
 );
+
+hmac_func!(Sha3_224);
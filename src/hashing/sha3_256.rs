@@ -22,3 +22,5 @@ hash_func!(
 	|sha3256: &mut Sha3_256, buffer: &[u8]| sha3256.update(buffer),
 	|sha3256: Sha3_256| hash_string(&sha3256.finalize())
 );
+
+hmac_func!(Sha3_256);
@@ -22,3 +22,5 @@ hash_func!(
 	|sha3384: &mut Sha3_384, buffer: &[u8]| sha3384.update(buffer),
 	|sha3384: Sha3_384| hash_string(&sha3384.finalize())
 );
+
+hmac_func!(Sha3_384);
@@ -22,3 +22,5 @@ hash_func!(
 	|sha3512: &mut Sha3_512, buffer: &[u8]| sha3512.update(buffer),
 	|sha3512: Sha3_512| hash_string(&sha3512.finalize())
 );
+
+hmac_func!(Sha3_512);
@@ -45,12 +45,20 @@
 //! Exit values and possible errors:
 //!
 //! ```text
-//! 1   - option parsing error
+//! 1   - option parsing error, or the manifest, cache or directory
+//!       couldn't be read or written
 //! 2   - hash lengths differ between selected and saved
 //! 3   - failed to parse hashes file
-//! N+3 - N files didn't match
+//! N+3 - N files didn't match, up to 254
+//! 255 - some files couldn't be read
 //! ```
 //!
+//! Files that can't be read (for lack of permissions, I/O errors, files deleted
+//! while the tree is walked, ...) don't stop the run. They're reported as such
+//! and listed again at the end. So are files and directories whose names
+//! aren't valid UTF-8, which manifests can't hold. Manifest parsing errors name
+//! the manifest, the number of the offending line and its text.
+//!
 //! ## SYNOPSIS
 //!
 //! [`QuickDash`](https://github.com/AndreVuillemot160/QuickDash) [OPTIONS] [DIRECTORY]
@@ -63,12 +71,18 @@
 //!
 //! ## OPTIONS
 //!
-//! -a --algorithm &lt;algorithm&gt;
+//! -a --algorithm &lt;algorithm[,algorithm2]...&gt;...
 //!
 //! ```text
 //! Quite simple, select the hash you want. Case-insensitive.
 //!
 //! Supported algorithms: SHA{1,2-,3-{224,256,384,512}, CRC32, MD5, BLAKE{2B,2S,3}, XXH3, XXHASH64
+//!
+//! Can be used multiple times. With several algorithms every file is still
+//! read only once, and each manifest line holds one digest per algorithm.
+//!
+//! Default: BLAKE3 when creating. When verifying, the algorithms recorded in
+//! the manifest's header; selecting some narrows verification down to them.
 //! ```
 //!
 //! -c --create
@@ -118,22 +132,107 @@
 //! Don't recurse down symlinks.
 //! ```
 //!
-//! -i --ignore &lt;filename[,filename2][,filename3][,filenameN]...&gt;...
+//! -i --ignore &lt;pattern&gt;...
 //!
 //! ```text
-//! Add filename(s) to ignored files list. Default: not used.
+//! Add a pattern to the ignored files list. Default: not used.
+//!
+//! Patterns are gitignore-style globs matched against names relative to
+//! DIRECTORY: `*` doesn't match `/`, `**` matches any number of directories, a
+//! pattern without a `/` other than a trailing one matches at any depth, a
+//! trailing `/` only matches directories, and `!` re-includes what an earlier
+//! pattern ignored. `re:REGEX` patterns are regular expressions the whole name
+//! has to match. The last matching pattern wins.
 //!
-//! The program marks the files that will be ignored. The specified ignored files will not come to the output file.
+//! The program marks the files that will be ignored. Ignored directories aren't
+//! descended into. The patterns are recorded in the manifest's header.
+//!
+//! Patterns in `.quickdashignore` files, one per line, apply to the directory
+//! they're in and below, relative to it, deeper files first. Blank lines and
+//! lines starting with `#` are skipped. The specified patterns take precedence
+//! over them.
 //!
 //! Can be used multiple times.
 //! ```
 //!
+//! --include &lt;pattern&gt;...
+//!
+//! ```text
+//! Only hash the files matching a pattern, themselves or by their directories.
+//! The others are left out of the manifest altogether. Default: all files.
+//!
+//! Same patterns as `--ignore`, recorded in the manifest's header too. Can be
+//! used multiple times.
+//! ```
+//!
+//! --respect-gitignore
+//!
+//! ```text
+//! Leave out the files git ignores, so that the manifest only covers tracked
+//! content. The `.gitignore` files in DIRECTORY apply like `.quickdashignore`
+//! files, after them. Those of DIRECTORY's parents up to the root of its
+//! repository, the repository's `.git/info/exclude` and the global excludes
+//! file (`core.excludesFile`, `~/.config/git/ignore` by default) apply after
+//! them, in that order. As in git, `re:` starts no regular expression in those
+//! files. Recorded in the manifest's header.
+//! ```
+//!
+//! --skip-vcs
+//!
+//! ```text
+//! Leave out `.git`, `.hg` and `.svn` directories. Recorded in the manifest's
+//! header.
+//! ```
+//!
+//! --min-size &lt;size&gt; --max-size &lt;size&gt;
+//! --newer &lt;time&gt; --older &lt;time&gt;
+//! --newer-than-file &lt;file&gt; --older-than-file &lt;file&gt;
+//! --owner &lt;user&gt; --group &lt;group&gt; --perm &lt;mode&gt;
+//! --extension &lt;ext[,ext2]...&gt;...
+//!
+//! ```text
+//! Only select the files meeting all of these find-like predicates. Default:
+//! all files.
+//!
+//! Sizes are in bytes, optionally followed by a binary K, M, G or T. Times are
+//! UTC `YYYY-MM-DD[THH:MM[:SS]]` dates, `@UNIX_TIMESTAMP`s, ages before now like
+//! `90s`, `15m`, `12h`, `7d` or `2w`; the `-file` variants compare against a
+//! file's modification time instead. Users and groups are names or IDs.
+//! `--perm` files have all of the octal mode bits set. Extensions are compared
+//! case-insensitively.
+//!
+//! The size, owner, group, mode and extension predicates are recorded in the
+//! manifest's header and applied again when verifying. Time predicates aren't:
+//! they select the files of a single run. When verifying, only the selected
+//! files are compared, e.g. those modified in the last week with `--newer 7d`.
+//! Files that are gone are still reported as removed.
+//! ```
+//!
 //! --force
 //!
 //! ```text
 //! Rewrite the output file in `--create` mode.
 //! ```
 //!
+//! --stream
+//!
+//! ```text
+//! Only in `create`. Append each file's digests to `outfile.partial` as soon
+//! as it's hashed, then sort them into `outfile` and remove the journal, so
+//! that memory use doesn't grow with the tree and an interrupted run leaves the
+//! digests made so far behind. The manifest is the same as without it.
+//! ```
+//!
+//! --resume
+//!
+//! ```text
+//! Only in `create`. Like `--stream`, but carry on with the `outfile.partial`
+//! journal an interrupted run left behind, if any. Files whose size and
+//! modification time didn't change since they were journaled aren't hashed
+//! again, and the manifest is the same as an uninterrupted run's. The options
+//! must select the same algorithms as the interrupted run did.
+//! ```
+//!
 //! -j --jobs [jobs]
 //!
 //! ```text
@@ -146,12 +245,247 @@
 //!                                   
 //! ```
 //!
+//! --format &lt;hash|sfv|coreutils|bsd|hashdeep&gt;
+//!
+//! ```text
+//! Manifest format. Default: picked by the manifest's name, `hash` otherwise.
+//!
+//! `sfv` reads and writes Simple File Verification files (`name CRC32` lines,
+//! `;` comments), with a QuickSFV-style comment block listing file sizes and
+//! modification times. Only CRC32 is supported, and it's the default algorithm.
+//!
+//! `coreutils` reads and writes the format of `sha256sum`, `b2sum`, `md5sum`
+//! etc., byte for byte, including the `*` binary mode marker and backslash
+//! escaped names. Used for `*SUMS`, `.md5`, `.sha256`, ... files. One algorithm
+//! only, by default the one the manifest's name suggests.
+//!
+//! `bsd` reads and writes tagged `SHA256 (name) = digest` lines, as written by
+//! `sha256sum --tag`, BSD `md5` or `cksum -a`. Lines for several algorithms may
+//! be mixed in one file, and coreutils manifests using tags are read as such.
+//!
+//! `hashdeep` reads and writes hashdeep/md5deep audit files: a
+//! `%%%% HASHDEEP-1.0` header and `size,md5,sha256,filename` rows. MD5, SHA1,
+//! SHA2-256 and WhirlPool only, MD5 and SHA2-256 by default. Files starting
+//! with that header are read as hashdeep whatever their name. Make them with
+//! `hashdeep -l -r .` to verify them, the `./` prefix of names is dropped. The
+//! sizes are written, but not checked when verifying: only the digests are.
+//! ```
+//!
+//! --cache &lt;file&gt;
+//!
+//! ```text
+//! Keep digests in `file` together with each file's size, mtime and inode.
+//! Files that didn't change since the last run aren't hashed again.
+//!
+//! Digests are cached separately for each algorithm. Those of the files of a
+//! hashed directory that weren't hashed again are dropped, while other
+//! directories' are kept. Runs that hash nothing leave the cache as it is.
+//! ```
+//!
+//! --rehash-all
+//!
+//! ```text
+//! Hash every file even if the cache says it's unchanged, refreshing the cache.
+//!
+//! Only with `--cache`.
+//! ```
+//!
+//! --key-file &lt;file&gt;
+//!
+//! ```text
+//! Make keyed digests with the secret key held in `file`, byte for byte, so
+//! that whoever can change the files can't make matching digests without it.
+//! BLAKE3 digests are made in keyed mode, SHA2 and SHA3 ones as HMACs, and
+//! other algorithms can't be keyed. A 32-byte key is used by BLAKE3 as is, like
+//! `b3sum --keyed` does.
+//!
+//! Only the `hash` format records the ID of the key in its header. Verifying a
+//! keyed manifest fails up front without the key it was made with, rather than
+//! reporting every file as changed. Keyed digests aren't cached.
+//! ```
+//!
+//! --key-env &lt;variable&gt;
+//!
+//! ```text
+//! Like `--key-file`, but read the key from the specified environment
+//! variable.
+//! ```
+//!
 //! [DIRECTORY]
 //!
 //! ```text
 //! Directory to create/verify hash for. By default is current workdir.
 //! ```
 //!
+//! --check &lt;all|any&gt;
+//!
+//! ```text
+//! Only in `verify`. Whether all or any of a file's digests have to match
+//! when several algorithms are used. Default: all.
+//! ```
+//!
+//! --audit
+//!
+//! ```text
+//! Only in `verify`. Audit the directory like `hashdeep -a -k`: files are
+//! matched by content instead of by name, and moved, copied, new and missing
+//! files are listed, followed by a summary:
+//!   Audit failed
+//!             Files matched: 41
+//!   Files partially matched: 0
+//!               Files moved: 1
+//!              Files copied: 0
+//!           New files found: 0
+//!     Known files not found: 0
+//!          Unreadable files: 0
+//!
+//! The audit passes only if every file matched under its own name. Otherwise
+//! the exit value is 255 if some files couldn't be read, and N+3 else, N
+//! being the number of moved, copied, new, missing and partially matched
+//! files.
+//! ```
+//!
+//! --report-format &lt;text|json|ndjson|junit|tap&gt;
+//!
+//! ```text
+//! Only in `verify`. How to report the results. Default: text.
+//!
+//! `json` writes one document with a `results` array and a `summary` object,
+//! `ndjson` one object per line, the summary last. Each result has the file's
+//! `path`, its `status` (matches, differs, added, removed, ignored, moved,
+//! copied or unreadable), its loaded (`expected`) and current (`actual`)
+//! digests, if any, the `algorithm`(s) compared, the name moved and copied
+//! files were loaded `from` and the `error` unreadable ones failed with:
+//!   {"type": "result", "path": "a", "status": "differs", "expected": "8C7E..",
+//!    "actual": "0B5A..", "algorithm": "BLAKE3", "from": null, "error": null}
+//!   {"type": "summary", "files": 1, "matches": 0, "differs": 1, "added": 0,
+//!    "removed": 0, "ignored": 0, "moved": 0, "copied": 0, "unreadable": 0,
+//!    "signer": null, "exit_code": 4}
+//!
+//! `junit` (JUnit XML) and `tap` (TAP version 13) report every file as a test
+//! case: matching files pass, differing ones fail with the was/is digests in
+//! the failure, ignored ones are skipped and unreadable ones are errors. Like
+//! the exit value, added, removed, moved and copied files are errors with
+//! `--audit`, and otherwise pass with a note of what became of them.
+//!
+//! The banner isn't printed, and exit values are the same as with `text`.
+//! ```
+//!
+//! --lenient
+//!
+//! ```text
+//! Only in `verify`. Verify the files listed on the manifest lines that can be
+//! parsed, and list the others instead of failing on the first. The exit value
+//! is 3 if lines were skipped and nothing else failed.
+//!
+//! Blank lines, `#` and `;` comments and CRLF line endings are accepted either
+//! way.
+//! ```
+//!
+//! --sign &lt;secret key&gt;
+//!
+//! ```text
+//! Only in `create`. Sign the manifest with a minisign secret key, asking for
+//! its password if it has one, and write the signature to `outfile.minisig`.
+//! `minisign -Vm outfile -p key.pub` checks it like any minisign signature.
+//! ```
+//!
+//! --public-key &lt;public key&gt;
+//!
+//! ```text
+//! Only in `verify`. A minisign public key file, or the key itself as printed
+//! by `minisign -G` (`RW...`). Nothing is verified unless the manifest's
+//! signature validates against it, and the key ID and trusted comment of the
+//! signature are reported along with the results.
+//! ```
+//!
+//! --signature &lt;file&gt;
+//!
+//! ```text
+//! Only in `verify`, with `--public-key`. Signature of the manifest. Default:
+//! `infile.minisig`.
+//! ```
+//!
+//! --files-from &lt;list&gt; [-0 --null]
+//!
+//! ```text
+//! Only in `create`. Hash exactly the files named in `list`, one per line, or
+//! NUL-separated with `--null` (like `find -print0` output), instead of walking
+//! DIRECTORY. `-` reads the list from stdin. Names are relative to DIRECTORY, or
+//! absolute paths inside it. Listed files are selected like walking would:
+//! `--depth`, patterns and predicates apply, and symlinks are only followed
+//! with `--follow-symlinks`, never out of DIRECTORY. Listed files that can't be
+//! read, aren't regular files or aren't inside DIRECTORY are reported as
+//! unreadable. Not with `--stream` or `--resume`.
+//! ```
+//!
+//! --tree
+//!
+//! ```text
+//! In `create`, print the Merkle tree root of the digests and record it in the
+//! header as `# tree-root: ALGORITHM ROOT`. Not with `--stream` or `--resume`.
+//!
+//! In `verify`, compare the tree roots of the manifest and the directory
+//! instead of every file. If they differ, only the directories whose digests
+//! differ are descended into, and the differing files, subtrees and added or
+//! removed files and directories are listed:
+//!   Tree root doesn't match
+//!     Was: 22D8EFB9..
+//!     Is : 8E71D672..
+//!
+//!   Subtree "a/" doesn't match
+//!   File "a/y" doesn't match
+//!
+//! The exit value is N+3, N being the number of differing, added and removed
+//! entries. Not with `--audit` or `--report-format`.
+//!
+//! A directory's digest is made with the first of the algorithms over its
+//! entries' names and digests, so the root fingerprints the names and contents
+//! of the whole tree. Ignored files and empty directories are left out.
+//! ```
+//!
+//! tree [DIRECTORY] [--file &lt;manifest&gt;] [--subtrees]
+//!
+//! ```text
+//! Print the Merkle tree root of the directory, leaving its default manifest
+//! out, or of the digests in `manifest` without touching the directory. With
+//! `--subtrees`, print the digest of every directory as `DIGEST  path/` lines.
+//! Two copies of a tree can be compared by their roots alone.
+//! ```
+//!
+//! diff [OPTIONS] &lt;old&gt; &lt;new&gt;
+//!
+//! ```text
+//! Compare two manifests, e.g. yesterday's and today's, or a primary's and a
+//! replica's, without reading the files they list. Files only in `new` are
+//! added, files only in `old` removed, and the others match or differ, the way
+//! `verify` reports them, with the same exit values. `--check`, `--audit` and
+//! `--report-format` work as in `verify`.
+//!
+//! Only digests made with algorithms both manifests hold are compared.
+//! Headerless manifests' algorithms are detected from their digests' length and
+//! their names alone. Each manifest's entry for itself is left out.
+//! ```
+//!
+//! dupes [DIRECTORY] [--action &lt;hardlink|reflink|delete&gt;] [--apply]
+//!
+//! ```text
+//! List the groups of files with the same content, walking the directory the
+//! way `create` does, and how much space the redundant copies take up. Only
+//! files of the same size are hashed, their first 4 KiB first, then whole if
+//! those match. Empty files are left out, and hard links to the same file count
+//! once. The digests are made with the first selected algorithm, BLAKE3 by
+//! default.
+//!
+//! With `--action`, list what would be done with the copies of the first file
+//! of each group, by name: replacing them with hard links to it, with
+//! copy-on-write clones of it (on filesystems that support them), or deleting
+//! them. Nothing is touched unless `--apply` is given too. Each copy is then
+//! compared with the file it duplicates byte for byte first, and left alone,
+//! stopping there, if either changed since they were hashed. Actions can't be
+//! applied to duplicates found with CRC32 or xxHash digests.
+//! ```
+//!
 //! ## EXAMPLES
 //!
 //! `quickdash` [`-v`] [`-f` *infile*]
@@ -172,6 +506,14 @@
 //!   File "changed_file" doesn't match
 //!     Was: foo
 //!     Is : bar
+//!
+//! Added and removed files with the same content are reported as moved, and
+//! further added files with the content of a known file as copied:
+//!   File moved: "new_name"
+//!     From: "old_name"
+//!   File copied: "second_copy"
+//!     From: "old_name"
+//! Neither counts towards the exit value.
 //! ```
 //!
 //! `examples` `-c` [`-f` *outfile*] [`--force`]
@@ -184,15 +526,25 @@
 //! Use `--force` to override *outfile*.
 //!
 //! *outfile* contents:
+//!   # QuickDash manifest v1
+//!   # tool-version: 0.6.1
+//!   # algorithms: BLAKE3
+//!   # depth: infinite
+//!   # follow-symlinks: false
 //!   F013BF0B163785CBB3BE52DE981E069E2B64E1CAC863815AC7BEED63E1734BAE  Cargo.toml
 //!   E84E380AEBDA3D98E96267201D61784C3D6FFB128C4D669E6C1D994C7D7BF32B  Cross.toml
+//!
+//! The header records how the manifest was created. `verify` picks the
+//! algorithms, depth, symlink policy and ignored files up from it.
+//!
+//! For manifests without a header (e.g. `SHA256SUMS` or `.md5` files) and no
+//! `-a`, `verify` detects the algorithm from the digest length, the manifest's
+//! name, or by hashing a few of the listed files, and reports its choice.
 //! ```
 
 #![deny(
 	anonymous_parameters,
 	clippy::all,
-	const_err,
-	illegal_floating_point_literal_pattern,
 	late_bound_lifetime_arguments,
 	path_statements,
 	patterns_in_fns_without_body,
@@ -214,7 +566,7 @@ pub mod utilities;
 
 pub use crate::{
 	algorithms::Algorithm,
-	error::Error,
+	error::{Error, FileError, ParseError},
 	hashing::*,
 	options::{Commands, Mode},
 };
@@ -14,14 +14,37 @@
  */
 
 use std::{
-	fs::remove_file,
-	io::{stderr, stdout},
+	collections::{BTreeMap, BTreeSet},
+	fs::{self, remove_file, File},
+	io::{stderr, stdin, stdout, BufReader, Write},
 	path::{Path, PathBuf},
 	process::exit,
 };
 
 use clap::Parser;
-use quickdash::{Commands, Mode};
+use quickdash::{
+	operations::{
+		algorithm_from_name,
+		signature_path,
+		HashCache,
+		ManifestFormat,
+		ManifestHeader,
+		ManifestWriter,
+		MerkleTree,
+		PathFilter,
+		PublicKey,
+		ReportDigests,
+		ReportFormat,
+		SecretKey,
+	},
+	Algorithm,
+	Commands,
+	Digests,
+	Error,
+	HashKey,
+	Mode,
+	ParseError,
+};
 
 const BANNER: [&str; 13] = [
 	"",
@@ -48,60 +71,679 @@ fn main() {
 fn actual_main() -> i32 {
 	let opts = Commands::parse();
 
-	BANNER.iter().for_each(|line| println!("{}", line));
+	// Keep machine-readable reports and tree roots parseable
+	if !matches!(
+		opts.command,
+		Mode::Verify {
+			report_format: ReportFormat::Json
+				| ReportFormat::Ndjson
+				| ReportFormat::Junit
+				| ReportFormat::Tap,
+			..
+		} | Mode::Diff {
+			report_format: ReportFormat::Json
+				| ReportFormat::Ndjson
+				| ReportFormat::Junit
+				| ReportFormat::Tap,
+			..
+		} | Mode::Tree { .. }
+	) {
+		BANNER.iter().for_each(|line| println!("{}", line));
+	}
+
+	let mut cache = match opts.cache.as_deref().map(HashCache::open).transpose() {
+		Ok(cache) => cache,
+		Err(e) => {
+			eprintln!("Failed to load cache: {}", e);
+			return e.exit_value();
+		}
+	};
+	if let Some(cache) = cache.as_mut() {
+		cache.set_rehash_all(opts.rehash_all);
+	}
+
+	let key = match (&opts.key_file, &opts.key_env) {
+		(Some(file), _) => HashKey::from_file(file).map(Some),
+		(_, Some(var)) => HashKey::from_env(var).map(Some),
+		(None, None) => Ok(None),
+	};
+	let key = match key {
+		Ok(key) => key,
+		Err(e) => {
+			eprintln!("{}", e);
+			return e.exit_value();
+		}
+	};
+
+	let predicates = opts.predicates();
+	let filter = PathFilter::new(&opts.ignored_files, &opts.included_files).map(|filter| {
+		filter
+			.respect_gitignore(opts.respect_gitignore)
+			.skip_vcs(opts.skip_vcs)
+			.select(predicates.clone())
+	});
 
-	match opts.command {
-		Mode::Create { path, file, force } => {
-			let file = file.unwrap_or_else(|| default_file(&path));
+	let result = match opts.command {
+		Mode::Create {
+			path,
+			file,
+			force,
+			stream,
+			resume,
+			sign,
+			tree,
+			files_from,
+			null,
+		} => {
+			let file = match file.map_or_else(|| default_file(&path, opts.format), Ok) {
+				Ok(file) => file,
+				Err(e) => {
+					eprintln!("{}", e);
+					return e.exit_value();
+				}
+			};
+			let format = opts
+				.format
+				.unwrap_or_else(|| ManifestFormat::from_path(&file));
 			match (force, Path::new(&file).exists()) {
 				(true, _) | (_, false) => {
 					// if this fails, it probably didn't exist
 					let _ = remove_file(&file);
 
-					let hashes = quickdash::operations::create_hashes(
-						&path,
-						opts.ignored_files,
-						opts.algorithm,
+					let algorithms = match (opts.algorithm.is_empty(), format) {
+						(true, ManifestFormat::Sfv) => vec![Algorithm::CRC32],
+						(true, ManifestFormat::Hashdeep) => {
+							vec![Algorithm::MD5, Algorithm::SHA2256]
+						}
+						(true, ManifestFormat::Coreutils) => {
+							vec![algorithm_from_name(&file).unwrap_or(Algorithm::BLAKE3)]
+						}
+						(true, _) => vec![Algorithm::BLAKE3],
+						(false, _) => opts.algorithm,
+					};
+					if !format.supports(&algorithms) {
+						eprintln!(
+							"The {:?} format can't hold digests made with the selected algorithms.",
+							format
+						);
+						return 1;
+					}
+					if key.is_some() && format != ManifestFormat::Hash {
+						eprintln!("Only the hash format can record that the digests are keyed.");
+						return 1;
+					}
+					// Before hashing, so that a wrong password doesn't waste the run
+					let secret_key = match sign.as_deref().map(SecretKey::open).transpose() {
+						Ok(key) => key,
+						Err(e) => {
+							eprintln!("{}", e);
+							return e.exit_value();
+						}
+					};
+					let mut header = ManifestHeader::new(
+						algorithms,
 						opts.depth,
 						opts.follow_symlinks,
-						opts.jobs,
+						opts.ignored_files,
 					);
-					quickdash::operations::write_hashes(&file, opts.algorithm, hashes)
-				}
-				(false, true) => {
-					eprintln!("File already exists. Use --force to overwrite.");
-					1
+					header.included_files = opts.included_files;
+					header.respect_gitignore = opts.respect_gitignore;
+					header.skip_vcs = opts.skip_vcs;
+					header = header.select(&predicates);
+					if let Some(key) = &key {
+						header = header.with_key(key);
+					}
+					let filter = match header.path_filter() {
+						// The header leaves out the time predicates
+						Ok(filter) => filter.select(predicates.clone()),
+						Err(e) => {
+							eprintln!("{}", e);
+							return e.exit_value();
+						}
+					};
+					let errors = if stream || resume {
+						let writer = match resume {
+							true => ManifestWriter::resume(&file, &path, format, &header),
+							false => ManifestWriter::create(&file, &path, format, &header),
+						};
+						writer.and_then(|mut writer| {
+							let errors = quickdash::operations::create_hashes_streaming(
+								&path,
+								&header,
+								&predicates,
+								key.as_ref(),
+								opts.jobs,
+								cache.as_mut(),
+								&mut writer,
+							)?;
+							writer.finish()?;
+							Ok(errors)
+						})
+					} else {
+						let hashes = match &files_from {
+							Some(list) => read_list(list, null).and_then(|names| {
+								quickdash::operations::create_hashes_from_list(
+									&path,
+									&names,
+									&filter,
+									&header.algorithms,
+									key.as_ref(),
+									header.depth,
+									header.follow_symlinks,
+									opts.jobs,
+									cache.as_mut(),
+								)
+							}),
+							None => quickdash::operations::create_hashes(
+								&path,
+								&filter,
+								&header.algorithms,
+								key.as_ref(),
+								header.depth,
+								header.follow_symlinks,
+								opts.jobs,
+								cache.as_mut(),
+							),
+						};
+						hashes.and_then(|(hashes, errors)| {
+							let mut header = header.clone();
+							if tree {
+								let tree = MerkleTree::new(header.algorithms[0], &hashes);
+								println!("Tree root: {}", tree.root());
+								header.tree_root =
+									Some((tree.algorithm(), tree.root().to_string()));
+							}
+							quickdash::operations::write_hashes(
+								&file, &path, format, &header, hashes,
+							)?;
+							Ok(errors)
+						})
+					};
+					errors.and_then(|errors| {
+						if let Some(secret_key) = secret_key {
+							let signature = signature_path(&file);
+							quickdash::operations::sign_manifest(&file, &signature, &secret_key)?;
+							eprintln!(
+								"Signed with key ID {} into {}",
+								secret_key.public_key().key_id(),
+								signature.display()
+							);
+						}
+						quickdash::operations::write_file_errors(&mut stderr(), &errors)?;
+						match errors.is_empty() {
+							true => Ok(()),
+							false => Err(Error::FilesUnreadable),
+						}
+					})
 				}
+				(false, true) => Err(Error::OutputExists(file)),
 			}
 		}
-		Mode::Verify { path, file } => {
-			let hashes = quickdash::operations::create_hashes(
-				&path,
-				opts.ignored_files,
-				opts.algorithm,
-				opts.depth,
-				opts.follow_symlinks,
-				opts.jobs,
-			);
-			let file = file.unwrap_or_else(|| default_file(&path));
-			match quickdash::operations::read_hashes(&file) {
-				Ok(loaded_hashes) => {
-					let compare_result =
-						quickdash::operations::compare_hashes(&file, hashes, loaded_hashes);
-					quickdash::operations::write_hash_comparison_results(
+		Mode::Verify {
+			path,
+			file,
+			check,
+			audit,
+			report_format,
+			lenient,
+			public_key,
+			signature,
+			tree,
+		} => {
+			let file = match file.map_or_else(|| default_file(&path, opts.format), Ok) {
+				Ok(file) => file,
+				Err(e) => {
+					eprintln!("{}", e);
+					return e.exit_value();
+				}
+			};
+			// Nothing in the manifest can be trusted until its signature is checked,
+			// so a signed one is read once and what was checked is what's parsed
+			let (signer, contents) = match public_key.as_deref().map(PublicKey::open).transpose() {
+				Ok(Some(key)) => {
+					let signature = signature.unwrap_or_else(|| signature_path(&file));
+					let verified =
+						fs::read(&file)
+							.map_err(|e| Error::io(&file, e))
+							.and_then(|contents| {
+								quickdash::operations::verify_manifest(
+									&file, &contents, &signature, &key,
+								)
+								.map(|signer| (Some(signer), Some(contents)))
+							});
+					match verified {
+						Ok(verified) => verified,
+						Err(e) => {
+							eprintln!("{}", e);
+							return e.exit_value();
+						}
+					}
+				}
+				Ok(None) => (None, None),
+				Err(e) => {
+					eprintln!("{}", e);
+					return e.exit_value();
+				}
+			};
+			let format = opts
+				.format
+				.unwrap_or_else(|| ManifestFormat::from_path(&file));
+			let algorithms = if opts.algorithm.is_empty() {
+				let detection = match &contents {
+					Some(contents) => quickdash::operations::detect_algorithm_in(
+						&file,
+						contents,
+						format,
+						Some(&path),
+					),
+					None => quickdash::operations::detect_algorithm(&file, format, Some(&path)),
+				};
+				match detection {
+					Ok(Some(detection)) => {
+						eprintln!("Detected algorithm: {}", detection);
+						vec![detection.algorithm]
+					}
+					Ok(None) => vec![Algorithm::BLAKE3],
+					Err(e) => {
+						eprintln!("{}", e);
+						return e.exit_value();
+					}
+				}
+			} else {
+				opts.algorithm.clone()
+			};
+			let loaded = match (lenient, &contents) {
+				(true, Some(contents)) => quickdash::operations::parse_hashes_lenient(
+					&file,
+					contents,
+					format,
+					&algorithms,
+				),
+				(true, None) => {
+					quickdash::operations::read_hashes_lenient(&file, format, &algorithms)
+				}
+				(false, Some(contents)) => {
+					quickdash::operations::parse_hashes(&file, contents, format, &algorithms)
+						.map(|(header, hashes)| (header, hashes, vec![]))
+				}
+				(false, None) => quickdash::operations::read_hashes(&file, format, &algorithms)
+					.map(|(header, hashes)| (header, hashes, vec![])),
+			};
+			drop(contents);
+			loaded.and_then(|(header, loaded_hashes, rejected)| {
+				let mut depth = opts.depth;
+				let mut follow_symlinks = opts.follow_symlinks;
+				let mut ignored_files = opts.ignored_files;
+				let mut included_files = opts.included_files;
+				let mut respect_gitignore = opts.respect_gitignore;
+				let mut skip_vcs = opts.skip_vcs;
+				let mut predicates = predicates;
+
+				// Keyed digests can't be told from changed files without the right key
+				match &header {
+					Some(header) => header.check_key(key.as_ref())?,
+					None if key.is_some() => {
+						return Err(Error::Key(
+							"the manifest has no header recording a key".to_string(),
+						));
+					}
+					None => {}
+				}
+
+				let tree_root = header.as_ref().and_then(|header| header.tree_root.clone());
+
+				// Walk the tree the same way it was walked when the manifest was created
+				if let Some(header) = header {
+					depth = depth.or(header.depth);
+					follow_symlinks |= header.follow_symlinks;
+					ignored_files.extend(header.ignored_files);
+					included_files.extend(header.included_files);
+					respect_gitignore |= header.respect_gitignore;
+					skip_vcs |= header.skip_vcs;
+					predicates.extend(header.predicates);
+				}
+				let filter = PathFilter::new(&ignored_files, &included_files)?
+					.respect_gitignore(respect_gitignore)
+					.skip_vcs(skip_vcs)
+					.select(predicates);
+
+				let loaded_algorithms: BTreeSet<_> = loaded_hashes
+					.values()
+					.flat_map(|digests| digests.keys().copied())
+					.collect();
+				let algorithms: Vec<_> = if opts.algorithm.is_empty() {
+					loaded_algorithms.into_iter().collect()
+				} else {
+					opts.algorithm
+						.into_iter()
+						.filter(|algo| loaded_algorithms.contains(algo))
+						.collect()
+				};
+				if algorithms.is_empty() {
+					return Err(Error::NoCommonAlgorithms);
+				}
+
+				let (hashes, errors) = quickdash::operations::create_hashes(
+					&path,
+					&filter,
+					&algorithms,
+					key.as_ref(),
+					depth,
+					follow_symlinks,
+					opts.jobs,
+					cache.as_mut(),
+				)?;
+				if tree
+					&& tree_root.as_ref().is_some_and(|(recorded, root)| {
+						*recorded == algorithms[0]
+							&& *root != MerkleTree::new(*recorded, &loaded_hashes).root()
+					}) {
+					return Err(ParseError::unnumbered(
+						"",
+						"the entries don't add up to the tree root in the header",
+					)
+					.in_file(&file)
+					.into());
+				}
+				// Only the selected files are compared
+				let mut loaded_hashes = loaded_hashes;
+				filter.deselect(&path, &mut loaded_hashes);
+				// The manifest isn't among its own entries
+				let mut hashes = hashes;
+				hashes.remove(&manifest_name(&path, &file));
+				if tree {
+					let algorithm = algorithms[0];
+					let loaded_tree = MerkleTree::new(algorithm, &loaded_hashes);
+					let result = quickdash::operations::write_tree_comparison(
 						&mut stdout(),
-						&mut stderr(),
-						compare_result,
+						&MerkleTree::new(algorithm, &hashes),
+						&loaded_tree,
+					);
+					quickdash::operations::write_file_errors(&mut stderr(), &errors)?;
+					quickdash::operations::write_rejected_lines(&mut stderr(), &rejected)?;
+					return match (result, errors.is_empty(), rejected.into_iter().next()) {
+						(_, false, _) => Err(Error::FilesUnreadable),
+						(Ok(()), true, Some(rejected)) => Err(rejected.into()),
+						(result, ..) => result,
+					};
+				}
+
+				let digests = report_digests(report_format, &hashes, &loaded_hashes);
+				let mut results = if audit {
+					Ok(quickdash::operations::audit_hashes(
+						&file,
+						hashes,
+						loaded_hashes,
+					))
+				} else {
+					quickdash::operations::compare_hashes(&file, hashes, loaded_hashes, check)
+				};
+				if let Ok((results, _)) = &mut results {
+					quickdash::operations::mark_unreadable(results, &errors);
+				}
+				let result = quickdash::operations::write_report(
+					&mut stdout(),
+					report_format,
+					&algorithms,
+					results,
+					&digests,
+					audit,
+					signer.as_ref(),
+				);
+				quickdash::operations::write_file_errors(&mut stderr(), &errors)?;
+				quickdash::operations::write_rejected_lines(&mut stderr(), &rejected)?;
+				match (result, rejected.into_iter().next()) {
+					(Ok(()), Some(rejected)) => Err(rejected.into()),
+					(result, _) => result,
+				}
+			})
+		}
+		Mode::Diff {
+			old,
+			new,
+			check,
+			audit,
+			report_format,
+		} => {
+			let load = |file: &Path| {
+				let format = opts
+					.format
+					.unwrap_or_else(|| ManifestFormat::from_path(file));
+				// Only the manifests are read, so there's nothing to sample
+				let algorithms = if opts.algorithm.is_empty() {
+					match quickdash::operations::detect_algorithm(file, format, None)? {
+						Some(detection) => vec![detection.algorithm],
+						None => vec![Algorithm::BLAKE3],
+					}
+				} else {
+					opts.algorithm.clone()
+				};
+				let (_, mut hashes) =
+					quickdash::operations::read_hashes(file, format, &algorithms)?;
+				quickdash::operations::remove_manifest_entry(file, &mut hashes);
+				Ok(hashes)
+			};
+			load(&old)
+				.and_then(|old_hashes| Ok((old_hashes, load(&new)?)))
+				.and_then(|(mut old_hashes, mut new_hashes)| {
+					let algorithms_of = |hashes: &BTreeMap<String, Digests>| -> BTreeSet<_> {
+						hashes
+							.values()
+							.flat_map(|digests| digests.keys().copied())
+							.collect()
+					};
+					let algorithms: Vec<_> = algorithms_of(&old_hashes)
+						.intersection(&algorithms_of(&new_hashes))
+						.copied()
+						.filter(|algo| opts.algorithm.is_empty() || opts.algorithm.contains(algo))
+						.collect();
+					if algorithms.is_empty() {
+						return Err(Error::NoCommonAlgorithms);
+					}
+					for digests in old_hashes.values_mut().chain(new_hashes.values_mut()) {
+						digests.retain(|algo, _| algorithms.contains(algo));
+					}
+
+					let digests = report_digests(report_format, &new_hashes, &old_hashes);
+					// No file is named like nothing, so nothing is left out
+					let results = if audit {
+						Ok(quickdash::operations::audit_hashes(
+							Path::new(""),
+							new_hashes,
+							old_hashes,
+						))
+					} else {
+						quickdash::operations::compare_hashes(
+							Path::new(""),
+							new_hashes,
+							old_hashes,
+							check,
+						)
+					};
+					quickdash::operations::write_report(
+						&mut stdout(),
+						report_format,
+						&algorithms,
+						results,
+						&digests,
+						audit,
+						None,
 					)
+				})
+		}
+		Mode::Tree {
+			path,
+			file,
+			subtrees,
+		} => {
+			let loaded = match file {
+				Some(file) => {
+					let format = opts
+						.format
+						.unwrap_or_else(|| ManifestFormat::from_path(&file));
+					quickdash::operations::read_hashes(&file, format, &opts.algorithm)
+						.map(|(_, hashes)| (hashes, vec![]))
+				}
+				None => {
+					let algorithms = match opts.algorithm.is_empty() {
+						true => vec![Algorithm::BLAKE3],
+						false => opts.algorithm.clone(),
+					};
+					filter
+						.and_then(|filter| {
+							quickdash::operations::create_hashes(
+								&path,
+								&filter,
+								&algorithms,
+								key.as_ref(),
+								opts.depth,
+								opts.follow_symlinks,
+								opts.jobs,
+								cache.as_mut(),
+							)
+						})
+						.map(|(mut hashes, errors)| {
+							// Left out of the root recorded in it
+							if let Ok(file) = default_file(&path, opts.format) {
+								hashes.remove(&manifest_name(&path, &file));
+							}
+							(hashes, errors)
+						})
+				}
+			};
+			loaded.and_then(|(hashes, errors)| {
+				let algorithm = opts.algorithm.iter().min().copied().or_else(|| {
+					hashes
+						.values()
+						.flat_map(|digests| digests.keys().copied())
+						.min()
+				});
+				let tree = MerkleTree::new(algorithm.unwrap_or(Algorithm::BLAKE3), &hashes);
+				let mut output = stdout();
+				match subtrees {
+					true => tree
+						.subtrees()
+						.into_iter()
+						.try_for_each(|(path, digest)| writeln!(output, "{}  {}", digest, path)),
+					false => writeln!(output, "{}", tree.root()),
+				}
+				.map_err(Error::Output)?;
+				quickdash::operations::write_file_errors(&mut stderr(), &errors)?;
+				match errors.is_empty() {
+					true => Ok(()),
+					false => Err(Error::FilesUnreadable),
 				}
-				Err(rval) => rval,
+			})
+		}
+		Mode::Dupes {
+			path,
+			action,
+			apply,
+		} => {
+			let algorithm = opts.algorithm.iter().min().copied();
+			filter
+				.and_then(|filter| {
+					quickdash::operations::find_duplicates(
+						&path,
+						&filter,
+						algorithm.unwrap_or(Algorithm::BLAKE3),
+						opts.depth,
+						opts.follow_symlinks,
+						opts.jobs,
+					)
+				})
+				.and_then(|(groups, errors)| {
+					let mut output = stdout();
+					quickdash::operations::write_duplicates(&mut output, &groups)?;
+					if let Some(action) = action {
+						writeln!(output).map_err(Error::Output)?;
+						quickdash::operations::apply_duplicate_action(
+							&mut output,
+							&path,
+							&groups,
+							action,
+							apply,
+						)?;
+					}
+					quickdash::operations::write_file_errors(&mut stderr(), &errors)?;
+					match errors.is_empty() {
+						true => Ok(()),
+						false => Err(Error::FilesUnreadable),
+					}
+				})
+		}
+	};
+	let result = match result {
+		Ok(()) => 0,
+		Err(e) => {
+			// The others were reported along with the results
+			if !matches!(
+				e,
+				Error::OptionParsingError
+					| Error::HashLengthDiffers
+					| Error::NFilesDiffer(_)
+					| Error::FilesUnreadable
+			) {
+				eprintln!("{}", e);
 			}
-			.exit_value()
+			e.exit_value()
 		}
+	};
+
+	if let Some(Err(e)) = cache.as_ref().map(HashCache::save) {
+		eprintln!("Failed to save cache: {}", e);
+	}
+
+	result
+}
+
+/// The digests to report along with the results, unless the text report,
+/// which doesn't list them, is written.
+fn report_digests(
+	format: ReportFormat,
+	current: &BTreeMap<String, Digests>,
+	loaded: &BTreeMap<String, Digests>,
+) -> ReportDigests {
+	match format {
+		ReportFormat::Text => ReportDigests::default(),
+		_ => ReportDigests {
+			current: current.clone(),
+			loaded: loaded.clone(),
+		},
 	}
 }
 
-fn default_file(path: &Path) -> PathBuf {
-	let parent = path.file_stem().expect("Could not get directory name");
-	path.join(parent).with_extension("hash")
+/// The name the manifest has among the hashes of `path`, if it's in there.
+fn manifest_name(path: &Path, file: &Path) -> String {
+	match file.starts_with(path) {
+		true => quickdash::utilities::relative_name(path, file),
+		false => String::new(),
+	}
+}
+
+/// Read the names in the `--files-from` list, `-` being stdin.
+fn read_list(list: &Path, null: bool) -> Result<Vec<String>, Error> {
+	let names = match list == Path::new("-") {
+		true => quickdash::operations::read_file_list(stdin().lock(), null),
+		false => File::open(list)
+			.and_then(|file| quickdash::operations::read_file_list(BufReader::new(file), null)),
+	};
+	names.map_err(|e| Error::io(list, e))
+}
+
+/// The manifest of the directory at `path` if none is specified: one named
+/// after the directory inside it, e.g. `dir/dir.hash`.
+fn default_file(path: &Path, format: Option<ManifestFormat>) -> Result<PathBuf, Error> {
+	// `.` and `..` have no name of their own
+	let parent = match path.file_stem() {
+		Some(parent) => parent.to_os_string(),
+		None => path
+			.canonicalize()
+			.map_err(|e| Error::io(path, e))?
+			.file_stem()
+			.ok_or_else(|| Error::NoDefaultManifest(path.to_path_buf()))?
+			.to_os_string(),
+	};
+	let format = format.unwrap_or(ManifestFormat::Hash);
+	Ok(path.join(parent).with_extension(format.extension()))
 }
@@ -0,0 +1,207 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs::{File, Metadata},
+	io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+	path::{Path, PathBuf},
+	str::FromStr,
+	time::UNIX_EPOCH,
+};
+
+use crate::{Algorithm, Error};
+
+static CACHE_HEADER: &str = "# quickdash cache v1";
+
+/// The metadata a cached digest is valid for.
+///
+/// A cached digest is reused only while the file's size, modification time and
+/// inode all stay the same.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct FileFingerprint {
+	pub size: u64,
+	pub mtime_secs: u64,
+	pub mtime_nanos: u32,
+	pub inode: u64,
+}
+
+impl FileFingerprint {
+	/// Build a fingerprint out of a file's metadata.
+	pub fn from_metadata(meta: &Metadata) -> FileFingerprint {
+		let mtime = meta
+			.modified()
+			.ok()
+			.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+			.unwrap_or_default();
+
+		FileFingerprint {
+			size: meta.len(),
+			mtime_secs: mtime.as_secs(),
+			mtime_nanos: mtime.subsec_nanos(),
+			inode: inode(meta),
+		}
+	}
+}
+
+#[cfg(unix)]
+fn inode(meta: &Metadata) -> u64 {
+	use std::os::unix::fs::MetadataExt;
+	meta.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &Metadata) -> u64 {
+	0
+}
+
+/// On-disk cache of file digests, used to skip rehashing unchanged files.
+///
+/// Entries are keyed on the algorithm and the file's path, so switching
+/// algorithms never reuses a digest made by a different one. Entries of the
+/// trees walked since the cache was opened that weren't stored again aren't
+/// saved, so that those of deleted files don't pile up. Those of other trees
+/// are kept as they are.
+#[derive(Debug, Clone)]
+pub struct HashCache {
+	file: PathBuf,
+	entries: BTreeMap<(Algorithm, String), (FileFingerprint, String)>,
+	seen: BTreeSet<(Algorithm, String)>,
+	walked: BTreeSet<PathBuf>,
+	rehash_all: bool,
+}
+
+impl HashCache {
+	/// Load the cache stored in the specified file, or start an empty one if
+	/// it doesn't exist yet.
+	///
+	/// Lines that can't be parsed are dropped.
+	pub fn open(file: &Path) -> Result<HashCache, Error> {
+		let mut cache = HashCache {
+			file: file.to_path_buf(),
+			entries: BTreeMap::new(),
+			seen: BTreeSet::new(),
+			walked: BTreeSet::new(),
+			rehash_all: false,
+		};
+
+		let in_file = match File::open(file) {
+			Ok(f) => BufReader::new(f),
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(cache),
+			Err(e) => return Err(Error::io(file, e)),
+		};
+		for line in in_file.lines() {
+			let line = line.map_err(|e| Error::io(file, e))?;
+			if line.starts_with('#') {
+				continue;
+			}
+			if let Some((algo, path, fingerprint, digest)) = parse_line(&line) {
+				cache.entries.insert((algo, path), (fingerprint, digest));
+			}
+		}
+
+		Ok(cache)
+	}
+
+	/// Ignore all cached digests, while still recording fresh ones.
+	pub fn set_rehash_all(&mut self, rehash_all: bool) {
+		self.rehash_all = rehash_all;
+	}
+
+	/// Get the cached digest of the specified file, if its metadata didn't
+	/// change since it was stored.
+	pub fn get(&self, algo: Algorithm, path: &str, fingerprint: &FileFingerprint) -> Option<&str> {
+		if self.rehash_all {
+			return None;
+		}
+
+		match self.entries.get(&(algo, path.to_string())) {
+			Some((cached, digest)) if cached == fingerprint => Some(digest),
+			_ => None,
+		}
+	}
+
+	/// Store the digest of the specified file, keeping it when saving.
+	pub fn insert(
+		&mut self,
+		algo: Algorithm,
+		path: String,
+		fingerprint: FileFingerprint,
+		digest: String,
+	) {
+		self.seen.insert((algo, path.clone()));
+		self.entries.insert((algo, path), (fingerprint, digest));
+	}
+
+	/// Record that the whole tree at the specified canonical path was hashed,
+	/// so that its entries that weren't stored again are dropped when saving.
+	pub fn walked(&mut self, root: &Path) {
+		self.walked.insert(root.to_path_buf());
+	}
+
+	/// Write the cache back to the file it was opened from, dropping the
+	/// entries of the walked trees that weren't stored again since it was
+	/// opened. Does nothing if nothing was hashed since.
+	pub fn save(&self) -> Result<(), Error> {
+		if self.seen.is_empty() && self.walked.is_empty() {
+			return Ok(());
+		}
+		self.write().map_err(|e| Error::io(&self.file, e))
+	}
+
+	fn write(&self) -> io::Result<()> {
+		let mut out = BufWriter::new(File::create(&self.file)?);
+
+		writeln!(out, "{}", CACHE_HEADER)?;
+		for ((algo, path), (fp, digest)) in self.entries.iter().filter(|(key, _)| {
+			self.seen.contains(*key)
+				|| !self
+					.walked
+					.iter()
+					.any(|root| Path::new(&key.1).starts_with(root))
+		}) {
+			writeln!(
+				out,
+				"{:?}\t{}\t{}.{:09}\t{}\t{}\t{}",
+				algo, fp.size, fp.mtime_secs, fp.mtime_nanos, fp.inode, digest, path
+			)?;
+		}
+
+		out.flush()
+	}
+}
+
+fn parse_line(line: &str) -> Option<(Algorithm, String, FileFingerprint, String)> {
+	let mut fields = line.splitn(6, '\t');
+
+	let algo = Algorithm::from_str(fields.next()?).ok()?;
+	let size = fields.next()?.parse().ok()?;
+	let (mtime_secs, mtime_nanos) = fields.next()?.split_once('.')?;
+	let inode = fields.next()?.parse().ok()?;
+	let digest = fields.next()?.to_string();
+	let path = fields.next()?.to_string();
+
+	Some((
+		algo,
+		path,
+		FileFingerprint {
+			size,
+			mtime_secs: mtime_secs.parse().ok()?,
+			mtime_nanos: mtime_nanos.parse().ok()?,
+			inode,
+		},
+		digest,
+	))
+}
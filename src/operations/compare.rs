@@ -13,15 +13,33 @@
  * limitations under the License.
  */
 
-use std::{collections::BTreeMap, path::Path};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io,
+	path::{Component, Path, PathBuf},
+};
 
-use crate::utilities::{mul_str, vec_merge};
+use clap::ArgEnum;
+
+use crate::{hash_reader, utilities::vec_merge, Algorithm, Digests, FileError};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CompareResult {
 	FileAdded(String),
 	FileRemoved(String),
 	FileIgnored(String),
+	/// A file's content was found under another name in the loaded hashes.
+	FileMoved {
+		from: String,
+		to: String,
+	},
+	/// A new file has the content of another one, still there or moved.
+	FileCopied {
+		from: String,
+		to: String,
+	},
+	/// A file or directory couldn't be read.
+	FileUnreadable(FileError),
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -34,6 +52,15 @@ pub enum CompareFileResult {
 	},
 }
 
+/// Which of a file's digests have to match for the file to match.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, ArgEnum)]
+pub enum MatchPolicy {
+	/// Every digest computed with an algorithm present in both sets.
+	All,
+	/// At least one of them.
+	Any,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Copy)]
 pub enum CompareError {
 	HashLengthDiffers {
@@ -43,27 +70,51 @@ pub enum CompareError {
 }
 
 /// Compare two provided hashes
+///
+/// Only digests made with algorithms present on both sides are compared;
+/// `policy` decides whether all or any of them have to match.
+///
+/// Files that are only in one set are paired by content: a removed file whose
+/// digests are found under an added name was moved there, and further added
+/// files with its content, or with the content of a file that's still there,
+/// are copies.
 pub fn compare_hashes(
 	out_file: &Path,
-	mut current_hashes: BTreeMap<String, String>,
-	mut loaded_hashes: BTreeMap<String, String>,
+	mut current_hashes: BTreeMap<String, Digests>,
+	mut loaded_hashes: BTreeMap<String, Digests>,
+	policy: MatchPolicy,
 ) -> Result<(Vec<CompareResult>, Vec<CompareFileResult>), CompareError> {
-	let current_hashes_value_len = current_hashes.iter().next().unwrap().1.len();
-	let loaded_hashes_value_len = loaded_hashes.iter().next().unwrap().1.len();
-	if current_hashes_value_len != loaded_hashes_value_len {
-		return Err(CompareError::HashLengthDiffers {
-			previous_len: loaded_hashes_value_len,
-			current_len: current_hashes_value_len,
-		});
+	if let (Some((_, current_digests)), Some((_, loaded_digests))) =
+		(current_hashes.iter().next(), loaded_hashes.iter().next())
+	{
+		for (algo, loaded_digest) in loaded_digests {
+			if let Some(current_digest) = current_digests.get(algo) {
+				if current_digest.len() != loaded_digest.len() {
+					return Err(CompareError::HashLengthDiffers {
+						previous_len: loaded_digest.len(),
+						current_len: current_digest.len(),
+					});
+				}
+			}
+		}
 	}
-	let placeholder_value = mul_str("-", current_hashes_value_len);
 	let mut file_compare_results = Vec::new();
 
 	let key = out_file.to_string_lossy().to_string();
 	current_hashes.remove(&key);
 	loaded_hashes.remove(&key);
 
-	let remove_results = process_ignores(
+	let only_in = |hashes: &BTreeMap<String, Digests>, other: &BTreeMap<String, Digests>| {
+		hashes
+			.iter()
+			.filter(|(key, _)| !other.contains_key(*key))
+			.map(|(key, digests)| (key.clone(), digests.clone()))
+			.collect::<BTreeMap<_, _>>()
+	};
+	let added = only_in(&current_hashes, &loaded_hashes);
+	let removed = only_in(&loaded_hashes, &current_hashes);
+
+	let mut remove_results = process_ignores(
 		|key, _, other| !other.contains_key(key),
 		CompareResult::FileAdded,
 		CompareResult::FileRemoved,
@@ -71,7 +122,11 @@ pub fn compare_hashes(
 		&mut loaded_hashes,
 	);
 	let ignore_results = process_ignores(
-		|_, value, _| *value == placeholder_value,
+		|_, value, _| {
+			value
+				.values()
+				.all(|digest| digest.bytes().all(|b| b == b'-'))
+		},
 		CompareResult::FileIgnored,
 		CompareResult::FileIgnored,
 		&mut current_hashes,
@@ -80,17 +135,28 @@ pub fn compare_hashes(
 
 	// By this point both hashes have the same keysets
 	assert_eq!(current_hashes.len(), loaded_hashes.len());
+	detect_moves(&mut remove_results, &added, &removed, &loaded_hashes);
 
 	if !current_hashes.is_empty() {
 		for (key, loaded_value) in loaded_hashes {
 			let current_value = &current_hashes[&key];
-			if *current_value == loaded_value {
+			let (matched, differing): (Vec<_>, Vec<_>) = loaded_value
+				.iter()
+				.filter_map(|(algo, digest)| current_value.get(algo).map(|c| (algo, digest, c)))
+				.partition(|(_, loaded, current)| loaded == current);
+
+			let matches = match policy {
+				MatchPolicy::All => differing.is_empty() && !matched.is_empty(),
+				MatchPolicy::Any => !matched.is_empty(),
+			};
+			if matches {
 				file_compare_results.push(CompareFileResult::FileMatches(key));
 			} else {
+				let common: Vec<_> = matched.into_iter().chain(differing).collect();
 				file_compare_results.push(CompareFileResult::FileDiffers {
 					file: key,
-					was_hash: loaded_value,
-					new_hash: current_value.clone(),
+					was_hash: digests_string(common.iter().map(|(a, l, _)| (**a, l.as_str()))),
+					new_hash: digests_string(common.iter().map(|(a, _, c)| (**a, c.as_str()))),
 				});
 			}
 		}
@@ -102,20 +168,308 @@ pub fn compare_hashes(
 	))
 }
 
+/// Turn the `FileAdded` and `FileRemoved` results of the `added` and
+/// `removed` files into `FileMoved` and `FileCopied` ones where their contents
+/// match, or match those of the `kept` files.
+///
+/// Contents are compared over the algorithms both sides have digests for.
+/// Empty files all have the same content, so they're never paired.
+fn detect_moves(
+	results: &mut Vec<CompareResult>,
+	added: &BTreeMap<String, Digests>,
+	removed: &BTreeMap<String, Digests>,
+	kept: &BTreeMap<String, Digests>,
+) {
+	let algos_of = |hashes: &BTreeMap<String, Digests>| -> BTreeSet<Algorithm> {
+		hashes.values().flat_map(|d| d.keys().copied()).collect()
+	};
+	let algos: BTreeSet<_> = algos_of(added)
+		.intersection(&algos_of(removed).union(&algos_of(kept)).copied().collect())
+		.copied()
+		.collect();
+	let empty: BTreeSet<_> = algos
+		.iter()
+		.filter_map(|algo| Some((*algo, hash_reader(*algo, &mut io::empty()).ok()?)))
+		.collect();
+	// Placeholders and files without common digests have no content to match
+	let content = |digests: &Digests| -> Option<Vec<(Algorithm, String)>> {
+		let content: Vec<_> = digests
+			.iter()
+			.filter(|(algo, _)| algos.contains(algo))
+			.map(|(algo, digest)| (*algo, digest.to_uppercase()))
+			.collect();
+		let unmatchable = content.iter().all(|(_, d)| d.bytes().all(|b| b == b'-'))
+			|| content.iter().any(|digest| empty.contains(digest));
+		match unmatchable {
+			true => None,
+			false => Some(content),
+		}
+	};
+
+	let mut removed_by_content: BTreeMap<_, Vec<&String>> = BTreeMap::new();
+	for (fname, digests) in removed.iter().rev() {
+		if let Some(key) = content(digests) {
+			removed_by_content.entry(key).or_default().push(fname);
+		}
+	}
+	let mut copy_sources = BTreeMap::new();
+	for (fname, digests) in kept {
+		if let Some(key) = content(digests) {
+			copy_sources.entry(key).or_insert(fname);
+		}
+	}
+
+	let mut paired = BTreeSet::new();
+	let mut pairs = Vec::new();
+	for (fname, digests) in added {
+		let key = match content(digests) {
+			Some(key) => key,
+			None => continue,
+		};
+		if let Some(from) = removed_by_content.get_mut(&key).and_then(Vec::pop) {
+			paired.insert(from);
+			copy_sources.entry(key).or_insert(from);
+			pairs.push(CompareResult::FileMoved {
+				from: from.clone(),
+				to: fname.clone(),
+			});
+		} else if let Some(from) = copy_sources.get(&key) {
+			pairs.push(CompareResult::FileCopied {
+				from: from.to_string(),
+				to: fname.clone(),
+			});
+		} else {
+			continue;
+		}
+		paired.insert(fname);
+	}
+
+	results.retain(|res| match res {
+		CompareResult::FileAdded(fname) | CompareResult::FileRemoved(fname) => {
+			!paired.contains(fname)
+		}
+		_ => true,
+	});
+	results.extend(pairs);
+}
+
+/// Remove the placeholder entry the manifest at `file` holds for itself from
+/// its loaded hashes, so that it's left out when comparing them with another
+/// manifest's, wherever either was created from.
+///
+/// The entry is named after the path the manifest was written to, so it's the
+/// placeholder whose whole name `file` ends with. Ignored files that only share
+/// the manifest's file name are kept.
+pub fn remove_manifest_entry(file: &Path, loaded_hashes: &mut BTreeMap<String, Digests>) {
+	let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+	loaded_hashes.retain(|name, digests| {
+		let is_placeholder = digests
+			.values()
+			.all(|digest| digest.bytes().all(|b| b == b'-'));
+		let name: PathBuf = Path::new(name)
+			.components()
+			.filter(|component| *component != Component::CurDir)
+			.collect();
+		!is_placeholder || name.as_os_str().is_empty() || !file.ends_with(name)
+	});
+}
+
+/// Report files `create_hashes()` couldn't read as unreadable, rather than as
+/// removed, along with the loaded files inside unreadable directories.
+pub fn mark_unreadable(results: &mut Vec<CompareResult>, errors: &[FileError]) {
+	let unreadable = |file: &str| {
+		errors.iter().any(|e| {
+			e.file.is_empty()
+				|| file == e.file
+				|| file
+					.strip_prefix(&e.file)
+					.is_some_and(|rest| rest.starts_with('/'))
+		})
+	};
+	results.retain(|res| !matches!(res, CompareResult::FileRemoved(file) if unreadable(file)));
+	results.extend(errors.iter().cloned().map(CompareResult::FileUnreadable));
+}
+
+/// Counts of an audit's outcomes, as in hashdeep's audit summary.
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+pub struct AuditSummary {
+	pub matched: usize,
+	pub partially_matched: usize,
+	pub moved: usize,
+	pub copied: usize,
+	pub new: usize,
+	pub missing: usize,
+	pub unreadable: usize,
+}
+
+impl AuditSummary {
+	/// Tally the results of `audit_hashes()`.
+	pub fn new(results: &[CompareResult], file_results: &[CompareFileResult]) -> AuditSummary {
+		let mut summary = AuditSummary::default();
+		for res in results {
+			match res {
+				CompareResult::FileAdded(_) => summary.new += 1,
+				CompareResult::FileRemoved(_) => summary.missing += 1,
+				CompareResult::FileMoved { .. } => summary.moved += 1,
+				CompareResult::FileCopied { .. } => summary.copied += 1,
+				CompareResult::FileUnreadable(_) => summary.unreadable += 1,
+				CompareResult::FileIgnored(_) => {}
+			}
+		}
+		for res in file_results {
+			match res {
+				CompareFileResult::FileMatches(_) => summary.matched += 1,
+				CompareFileResult::FileDiffers { .. } => summary.partially_matched += 1,
+			}
+		}
+		summary
+	}
+
+	/// How many files kept the audit from passing.
+	pub fn failures(&self) -> usize {
+		self.partially_matched
+			+ self.moved
+			+ self.copied
+			+ self.new
+			+ self.missing
+			+ self.unreadable
+	}
+
+	/// Whether every file was found, under its own name, with its content.
+	pub fn passed(&self) -> bool {
+		self.failures() == 0
+	}
+}
+
+/// Audit the current hashes against the loaded ones, like `hashdeep -a -k`.
+///
+/// Files are matched by content rather than by name: a current file whose
+/// digests are all loaded under its name matches, and under another name it
+/// was moved, unless the loaded file matched or moved elsewhere already, in
+/// which case it's a copy. One sharing only some digests with a loaded file
+/// partially matches it (reported as differing). Other current files are
+/// added, and loaded files none of the current ones were found as are removed.
+/// A file whose content changed is thus both added and removed.
+pub fn audit_hashes(
+	out_file: &Path,
+	mut current_hashes: BTreeMap<String, Digests>,
+	mut loaded_hashes: BTreeMap<String, Digests>,
+) -> (Vec<CompareResult>, Vec<CompareFileResult>) {
+	let key = out_file.to_string_lossy().to_string();
+	current_hashes.remove(&key);
+	loaded_hashes.remove(&key);
+
+	let mut results = process_ignores(
+		|_, value, _| {
+			value
+				.values()
+				.all(|digest| digest.bytes().all(|b| b == b'-'))
+		},
+		CompareResult::FileIgnored,
+		CompareResult::FileIgnored,
+		&mut current_hashes,
+		&mut loaded_hashes,
+	);
+	let mut file_results = Vec::new();
+
+	// Loaded files by their content, and by each of their digests
+	let algos: BTreeSet<_> = current_hashes.values().flat_map(|d| d.keys()).collect();
+	let content = |digests: &Digests| -> Vec<(Algorithm, String)> {
+		digests
+			.iter()
+			.filter(|(algo, _)| algos.contains(algo))
+			.map(|(algo, digest)| (*algo, digest.clone()))
+			.collect()
+	};
+	let mut by_content: BTreeMap<_, Vec<_>> = BTreeMap::new();
+	let mut by_digest = BTreeMap::new();
+	for (fname, digests) in &loaded_hashes {
+		by_content.entry(content(digests)).or_default().push(fname);
+		for (algo, digest) in digests {
+			by_digest.entry((*algo, digest.clone())).or_insert(fname);
+		}
+	}
+
+	// Files found in place first, so that they aren't taken for moved ones
+	let (in_place, elsewhere): (Vec<_>, Vec<_>) = current_hashes.iter().partition(|(fname, d)| {
+		loaded_hashes
+			.get(*fname)
+			.is_some_and(|l| content(l) == content(d))
+	});
+	let mut unused: BTreeSet<_> = loaded_hashes.keys().collect();
+	for (fname, _) in in_place {
+		unused.remove(fname);
+		file_results.push(CompareFileResult::FileMatches(fname.clone()));
+	}
+	for (fname, digests) in elsewhere {
+		let key = content(digests);
+		if let Some(known) = by_content.get(&key) {
+			match known.iter().find(|known| unused.contains(**known)) {
+				Some(from) => {
+					unused.remove(from);
+					results.push(CompareResult::FileMoved {
+						from: from.to_string(),
+						to: fname.clone(),
+					});
+				}
+				None => results.push(CompareResult::FileCopied {
+					from: known[0].to_string(),
+					to: fname.clone(),
+				}),
+			}
+		} else if let Some(known) = key.iter().find_map(|d| by_digest.get(d)) {
+			unused.remove(known);
+			let was = content(&loaded_hashes[*known]);
+			file_results.push(CompareFileResult::FileDiffers {
+				file: fname.clone(),
+				was_hash: digests_string(was.iter().map(|(a, d)| (*a, d.as_str()))),
+				new_hash: digests_string(key.iter().map(|(a, d)| (*a, d.as_str()))),
+			});
+		} else {
+			results.push(CompareResult::FileAdded(fname.clone()));
+		}
+	}
+	results.extend(
+		unused
+			.into_iter()
+			.map(|fname| CompareResult::FileRemoved(fname.clone())),
+	);
+
+	(results, file_results)
+}
+
+/// Format digests for display: a lone digest as-is, several tagged with their
+/// algorithm.
+pub(super) fn digests_string<'a, I>(digests: I) -> String
+where
+	I: IntoIterator<Item = (Algorithm, &'a str)>,
+{
+	let mut digests: Vec<_> = digests.into_iter().collect();
+	digests.sort();
+	match &digests[..] {
+		[(_, digest)] => digest.to_string(),
+		_ => digests
+			.iter()
+			.map(|(algo, digest)| format!("{:?}:{}", algo, digest))
+			.collect::<Vec<_>>()
+			.join(" "),
+	}
+}
+
 fn process_ignores<F, Rc, Rl>(
 	f: F,
 	cres: Rc,
 	lres: Rl,
-	ch: &mut BTreeMap<String, String>,
-	lh: &mut BTreeMap<String, String>,
+	ch: &mut BTreeMap<String, Digests>,
+	lh: &mut BTreeMap<String, Digests>,
 ) -> Vec<CompareResult>
 where
-	F: Fn(&str, &str, &BTreeMap<String, String>) -> bool,
+	F: Fn(&str, &Digests, &BTreeMap<String, Digests>) -> bool,
 	Rc: Fn(String) -> CompareResult,
 	Rl: Fn(String) -> CompareResult,
 {
 	let mut results = Vec::new();
-	let mut keys_to_remove = Vec::new();
+	let mut keys_to_remove = BTreeSet::new();
 
 	process_ignores_iter(&f, &cres, ch, lh, &mut keys_to_remove, &mut results);
 	process_ignores_iter(&f, &lres, lh, ch, &mut keys_to_remove, &mut results);
@@ -131,18 +485,18 @@ where
 fn process_ignores_iter<F, R>(
 	f: &F,
 	res: &R,
-	curr: &BTreeMap<String, String>,
-	other: &BTreeMap<String, String>,
-	keys_to_remove: &mut Vec<String>,
+	curr: &BTreeMap<String, Digests>,
+	other: &BTreeMap<String, Digests>,
+	keys_to_remove: &mut BTreeSet<String>,
 	results: &mut Vec<CompareResult>,
 ) where
-	F: Fn(&str, &str, &BTreeMap<String, String>) -> bool,
+	F: Fn(&str, &Digests, &BTreeMap<String, Digests>) -> bool,
 	R: Fn(String) -> CompareResult,
 {
 	for (key, value) in curr {
-		if f(key, value, other) {
+		// A key matched on both sides is only reported once
+		if f(key, value, other) && keys_to_remove.insert(key.clone()) {
 			results.push(res(key.clone()));
-			keys_to_remove.push(key.clone());
 		}
 	}
 }
@@ -0,0 +1,222 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{fmt, fs::metadata, path::Path};
+
+use clap::ArgEnum;
+
+use super::{read_rows, ManifestFormat};
+use crate::{hash_file_multi, Algorithm, Error};
+
+/// How many listed files are hashed at most to tell candidates apart.
+const SAMPLE_SIZE: usize = 4;
+
+/// Conventional manifest name tokens, checked in order.
+static NAME_HINTS: [(&str, Algorithm); 21] = [
+	("sha3-224", Algorithm::SHA3224),
+	("sha3-256", Algorithm::SHA3256),
+	("sha3-384", Algorithm::SHA3384),
+	("sha3-512", Algorithm::SHA3512),
+	("sha224", Algorithm::SHA2224),
+	("sha256", Algorithm::SHA2256),
+	("sha384", Algorithm::SHA2384),
+	("sha512", Algorithm::SHA2512),
+	("sha1", Algorithm::SHA1),
+	("md5", Algorithm::MD5),
+	("blake2s", Algorithm::BLAKE2S),
+	("blake2b", Algorithm::BLAKE2B),
+	("b2", Algorithm::BLAKE2B),
+	("blake3", Algorithm::BLAKE3),
+	("b3", Algorithm::BLAKE3),
+	("xxh32", Algorithm::XXH32),
+	("xxh64", Algorithm::XXH64),
+	("xxh3", Algorithm::XXH3),
+	("whirlpool", Algorithm::WhirlPool),
+	("crc32", Algorithm::CRC32),
+	("sfv", Algorithm::CRC32),
+];
+
+/// How an algorithm was picked for a headerless manifest.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum DetectionMethod {
+	/// The manifest's format only holds digests made with it.
+	Format,
+	/// It's the only algorithm with that digest length.
+	DigestLength,
+	/// The manifest's name follows the algorithm's naming convention.
+	FileName,
+	/// Its digests of some listed files matched the manifest's.
+	Sample,
+	/// Nothing told the candidates apart, so the first one was picked.
+	Guess,
+}
+
+/// The algorithm picked for a headerless manifest.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Detection {
+	pub algorithm: Algorithm,
+	pub method: DetectionMethod,
+	/// All algorithms producing digests of the manifest's length.
+	pub candidates: Vec<Algorithm>,
+}
+
+impl fmt::Display for Detection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let how = match self.method {
+			DetectionMethod::Format => "by manifest format",
+			DetectionMethod::DigestLength => "by digest length",
+			DetectionMethod::FileName => "by file name",
+			DetectionMethod::Sample => "by hashing sample files",
+			DetectionMethod::Guess => "guessed, could not tell candidates apart",
+		};
+		write!(f, "{:?} ({})", self.algorithm, how)
+	}
+}
+
+/// Pick the algorithm a headerless, single-digest manifest was most likely
+/// created with.
+///
+/// SFV files only hold CRC32 digests. For other formats, candidates are
+/// narrowed down by digest length, then by the manifest's name, then by
+/// hashing a few of the listed files under `root`, if any, with each of them.
+/// Returns `None` for manifests that have a header, list several or tagged
+/// digests per file, or whose digest length no algorithm produces.
+pub fn detect_algorithm(
+	file: &Path,
+	format: ManifestFormat,
+	root: Option<&Path>,
+) -> Result<Option<Detection>, Error> {
+	detect(file, None, format, root)
+}
+
+/// Like `detect_algorithm()`, but look at the `contents` of the manifest at
+/// `file`, already in memory.
+pub fn detect_algorithm_in(
+	file: &Path,
+	contents: &[u8],
+	format: ManifestFormat,
+	root: Option<&Path>,
+) -> Result<Option<Detection>, Error> {
+	detect(file, Some(contents), format, root)
+}
+
+fn detect(
+	file: &Path,
+	contents: Option<&[u8]>,
+	format: ManifestFormat,
+	root: Option<&Path>,
+) -> Result<Option<Detection>, Error> {
+	let (header, rows) = read_rows(file, contents, format)?;
+	if header.is_some()
+		|| rows
+			.iter()
+			.any(|row| row.columns.len() != 1 || row.columns[0].0.is_some())
+	{
+		return Ok(None);
+	}
+	if format == ManifestFormat::Sfv {
+		return Ok(Some(Detection {
+			algorithm: Algorithm::CRC32,
+			method: DetectionMethod::Format,
+			candidates: vec![Algorithm::CRC32],
+		}));
+	}
+
+	let digest_len = match rows
+		.iter()
+		.map(|row| &row.columns[0].1)
+		.find(|digest| !digest.bytes().all(|b| b == b'-'))
+	{
+		Some(digest) => digest.len(),
+		None => return Ok(None),
+	};
+
+	let candidates: Vec<_> = Algorithm::value_variants()
+		.iter()
+		.copied()
+		.filter(|algo| algo.hexlen() == digest_len)
+		.collect();
+	let detection = |algorithm, method| {
+		Some(Detection {
+			algorithm,
+			method,
+			candidates: candidates.clone(),
+		})
+	};
+
+	match candidates[..] {
+		[] => return Ok(None),
+		[algo] => return Ok(detection(algo, DetectionMethod::DigestLength)),
+		_ => {}
+	}
+
+	if let Some(algo) = algorithm_from_name(file).filter(|algo| candidates.contains(algo)) {
+		return Ok(detection(algo, DetectionMethod::FileName));
+	}
+
+	// Smallest files first, they're the cheapest to hash
+	let mut sample: Vec<_> = rows
+		.iter()
+		.filter_map(|row| {
+			let path = root?.join(&row.name);
+			let meta = metadata(&path).ok().filter(|m| m.is_file())?;
+			Some((meta.len(), path, &row.columns[0].1))
+		})
+		.collect();
+	sample.sort();
+
+	for (_, path, expected) in sample.into_iter().take(SAMPLE_SIZE) {
+		let digests = match hash_file_multi(&candidates, &path) {
+			Ok(digests) => digests,
+			Err(_) => continue,
+		};
+		if let Some((algo, _)) = digests.into_iter().find(|(_, digest)| digest == expected) {
+			return Ok(detection(algo, DetectionMethod::Sample));
+		}
+	}
+
+	Ok(detection(candidates[0], DetectionMethod::Guess))
+}
+
+/// Pick the algorithm conventionally used by manifests named like `file`, e.g.
+/// SHA2-256 for `SHA256SUMS`.
+///
+/// Hints only count as whole tokens of the name, delimited by its ends or
+/// non-alphanumeric characters, optionally followed by `sum` or `sums`:
+/// `B2SUMS` and `files.b2` are BLAKE2b manifests, `web2.hash` isn't.
+pub fn algorithm_from_name(file: &Path) -> Option<Algorithm> {
+	let name = file
+		.file_name()?
+		.to_string_lossy()
+		.replace('_', "-")
+		.to_lowercase();
+	NAME_HINTS
+		.iter()
+		.find(|(hint, _)| has_token(&name, hint))
+		.map(|(_, algo)| *algo)
+}
+
+/// Whether `token` is in `name` on its own, as `algorithm_from_name()` wants.
+fn has_token(name: &str, token: &str) -> bool {
+	let delimiter = |c: Option<char>| !c.is_some_and(|c| c.is_ascii_alphanumeric());
+	name.match_indices(token).any(|(i, _)| {
+		let rest = &name[i + token.len()..];
+		let rest = rest
+			.strip_prefix("sums")
+			.or_else(|| rest.strip_prefix("sum"))
+			.unwrap_or(rest);
+		delimiter(name[..i].chars().next_back()) && delimiter(rest.chars().next())
+	})
+}
@@ -0,0 +1,391 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs::{self, File, Metadata},
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+};
+
+use clap::ArgEnum;
+use indicatif::{HumanBytes, ParallelProgressIterator};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use walkdir::DirEntry;
+
+use super::{walk, FileFingerprint, PathFilter, TreeHasher, Walked};
+use crate::{hash_file, hash_reader, utilities::relative_name, Algorithm, Error, FileError};
+
+/// How many leading bytes of same-sized files are hashed to tell them apart
+/// before hashing them whole.
+const PARTIAL_LEN: u64 = 4096;
+
+/// How many bytes of a duplicate and the file it duplicates are compared at
+/// once before acting on it.
+const COMPARE_LEN: usize = 64 * 1024;
+
+/// Files with the same content.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct DuplicateGroup {
+	/// Size of each of the files, in bytes.
+	pub size: u64,
+	/// Digest of each of the files.
+	pub digest: String,
+	/// Algorithm the digest was made with.
+	pub algorithm: Algorithm,
+	/// The files' names relative to the directory searched, sorted. The
+	/// first one is kept by `DuplicateAction`s.
+	pub files: Vec<String>,
+	/// The files' metadata when they were hashed, in the order of `files`.
+	pub fingerprints: Vec<FileFingerprint>,
+}
+
+impl DuplicateGroup {
+	/// How many bytes removing all copies but one would free.
+	pub fn redundant_size(&self) -> u64 {
+		self.size * (self.files.len() as u64 - 1)
+	}
+}
+
+/// What to do with the duplicates of the first file of a group.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, ArgEnum)]
+pub enum DuplicateAction {
+	/// Replace them with hard links to it.
+	Hardlink,
+	/// Replace them with copy-on-write clones of it, where the filesystem
+	/// supports that.
+	Reflink,
+	/// Delete them.
+	Delete,
+}
+
+/// Find the files with the same content in the tree at `path`, walked the
+/// way `create_hashes()` walks it.
+///
+/// Files are grouped by size first, and only files of the same size are
+/// hashed: their first 4 KiB, then the ones that still collide whole. Empty
+/// files are left out, and hard links to the same file count once.
+///
+/// Files and directories that can't be read are left out and returned
+/// alongside the groups, sorted by name. Groups are sorted by decreasing size.
+pub fn find_duplicates(
+	path: &Path,
+	filter: &PathFilter,
+	algo: Algorithm,
+	depth: Option<usize>,
+	follow_symlinks: bool,
+	jobs: usize,
+) -> Result<(Vec<DuplicateGroup>, Vec<FileError>), Error> {
+	let algos = [algo];
+	let hasher = TreeHasher::new(path, &algos, None, jobs)?;
+	let mut errors = Vec::new();
+
+	hasher.pb.set_message("Finding files to compare...");
+	let mut by_size: BTreeMap<u64, Vec<DirEntry>> = BTreeMap::new();
+	let mut fingerprints = BTreeMap::new();
+	let mut inodes = BTreeSet::new();
+	for walked in walk(path, filter, depth, follow_symlinks, None) {
+		match walked? {
+			Walked::File(entry) => match entry.metadata() {
+				Ok(meta) if meta.len() == 0 => {}
+				// Hard links to a file already seen
+				Ok(meta) if inode(&meta).is_some_and(|id| !inodes.insert(id)) => {}
+				Ok(meta) => {
+					fingerprints.insert(
+						entry.path().to_path_buf(),
+						FileFingerprint::from_metadata(&meta),
+					);
+					by_size.entry(meta.len()).or_default().push(entry);
+				}
+				Err(e) => errors.push(FileError::new(relative_name(path, entry.path()), &e.into())),
+			},
+			Walked::Ignored(_) => {}
+			Walked::Unreadable(err) => errors.push(err),
+		}
+	}
+	let candidates: Vec<_> = by_size
+		.into_iter()
+		.filter(|(_, entries)| entries.len() > 1)
+		.flat_map(|(size, entries)| entries.into_iter().map(move |e| ((size, None), e)))
+		.collect();
+
+	hasher.pb.set_message("Hashing file beginnings...");
+	let partial = hasher.digest(candidates, &mut errors, |(size, _), entry| {
+		let mut file = File::open(entry.path())?;
+		let digest = hash_reader(algo, &mut (&mut file).take(PARTIAL_LEN))?;
+		Ok((size, Some(digest)))
+	});
+
+	// Files no longer than the partial digest are already hashed whole
+	hasher.pb.set_message("Hashing files...");
+	let full = hasher.digest(partial, &mut errors, |key, entry| match key {
+		(size, Some(digest)) if size <= PARTIAL_LEN => Ok((size, Some(digest))),
+		(size, _) => Ok((size, Some(hash_file(algo, entry.path())?))),
+	});
+	hasher.pb.finish_and_clear();
+
+	let mut groups: BTreeMap<_, Vec<(String, FileFingerprint)>> = BTreeMap::new();
+	for ((size, digest), entry) in full {
+		let name = relative_name(path, entry.path());
+		let fingerprint = fingerprints[entry.path()];
+		groups
+			.entry((size, digest))
+			.or_default()
+			.push((name, fingerprint));
+	}
+	let mut groups: Vec<_> = groups
+		.into_iter()
+		.filter(|(_, files)| files.len() > 1)
+		.map(|((size, digest), mut files)| {
+			files.sort_by(|(a, _), (b, _)| a.cmp(b));
+			let (files, fingerprints) = files.into_iter().unzip();
+			DuplicateGroup {
+				size,
+				digest: digest.unwrap_or_default(),
+				algorithm: algo,
+				files,
+				fingerprints,
+			}
+		})
+		.collect();
+	groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.files.cmp(&b.files)));
+
+	errors.sort();
+	Ok((groups, errors))
+}
+
+/// Key of files whose size and digest so far are the same.
+type DigestKey = (u64, Option<String>);
+
+impl TreeHasher<'_> {
+	/// Refine the keys of the specified files with `digest` and keep the ones
+	/// that still share theirs with another file. Files that can't be read are
+	/// added to `errors`.
+	fn digest<F>(
+		&self,
+		files: Vec<(DigestKey, DirEntry)>,
+		errors: &mut Vec<FileError>,
+		digest: F,
+	) -> Vec<(DigestKey, DirEntry)>
+	where
+		F: Fn(DigestKey, &DirEntry) -> io::Result<DigestKey> + Sync,
+	{
+		self.pb.reset();
+		self.pb.set_length(files.len() as u64);
+		let results: Vec<_> = self.pool.install(|| {
+			files
+				.into_par_iter()
+				.progress_with(self.pb.clone())
+				.map(|(key, entry)| match digest(key, &entry) {
+					Ok(key) => Ok((key, entry)),
+					Err(err) => Err(FileError::new(relative_name(self.path, entry.path()), &err)),
+				})
+				.collect()
+		});
+
+		let mut by_key: BTreeMap<DigestKey, Vec<DirEntry>> = BTreeMap::new();
+		for result in results {
+			match result {
+				Ok((key, entry)) => by_key.entry(key).or_default().push(entry),
+				Err(err) => errors.push(err),
+			}
+		}
+		by_key
+			.into_iter()
+			.filter(|(_, entries)| entries.len() > 1)
+			.flat_map(|(key, entries)| entries.into_iter().map(move |e| (key.clone(), e)))
+			.collect()
+	}
+}
+
+/// Write the duplicate groups in a human-consumable format, followed by how
+/// much space they take up needlessly.
+pub fn write_duplicates<W: Write>(output: &mut W, groups: &[DuplicateGroup]) -> Result<(), Error> {
+	let write = |output: &mut W| {
+		if groups.is_empty() {
+			return writeln!(output, "No duplicates found");
+		}
+		for group in groups {
+			writeln!(
+				output,
+				"{} files of {} each, digest {}:",
+				group.files.len(),
+				HumanBytes(group.size),
+				group.digest
+			)?;
+			for file in &group.files {
+				writeln!(output, "  \"{}\"", file)?;
+			}
+		}
+		writeln!(output)?;
+
+		let redundant: usize = groups.iter().map(|g| g.files.len() - 1).sum();
+		let redundant_size: u64 = groups.iter().map(DuplicateGroup::redundant_size).sum();
+		writeln!(
+			output,
+			"{} groups of duplicates, {} redundant files, {} to reclaim",
+			groups.len(),
+			redundant,
+			HumanBytes(redundant_size)
+		)
+	};
+	write(output)
+		.and_then(|_| output.flush())
+		.map_err(Error::Output)
+}
+
+/// Replace or delete the duplicates of the first file of each group, as
+/// `action` says, describing each step. Unless `apply` is set, only describe
+/// what would be done.
+///
+/// Before it's replaced or deleted, each duplicate is compared with the file
+/// it duplicates byte for byte, and both have to have the size and
+/// modification time they had when they were hashed. Groups found with
+/// non-cryptographic algorithms, like CRC32, are refused.
+///
+/// Stops at the first file that can't be replaced or deleted.
+pub fn apply_duplicate_action<W: Write>(
+	output: &mut W,
+	root: &Path,
+	groups: &[DuplicateGroup],
+	action: DuplicateAction,
+	apply: bool,
+) -> Result<(), Error> {
+	if let Some(group) = groups
+		.iter()
+		.find(|group| apply && !group.algorithm.is_cryptographic())
+	{
+		return Err(Error::NotCryptographic(group.algorithm));
+	}
+
+	for group in groups {
+		let keep = &group.files[0];
+		let files = group.files.iter().zip(&group.fingerprints).skip(1);
+		for (duplicate, fingerprint) in files {
+			let (would, did) = match action {
+				DuplicateAction::Hardlink => ("Would hard link", "Hard linked"),
+				DuplicateAction::Reflink => ("Would reflink", "Reflinked"),
+				DuplicateAction::Delete => ("Would delete", "Deleted"),
+			};
+			if apply {
+				let (keep, duplicate) = (root.join(keep), root.join(duplicate));
+				check_unchanged(&keep, &group.fingerprints[0])?;
+				check_unchanged(&duplicate, fingerprint)?;
+				if !same_contents(&keep, &duplicate)? {
+					return Err(Error::DuplicateChanged(duplicate));
+				}
+				action.apply(&keep, &duplicate)?;
+			}
+			writeln!(
+				output,
+				"{} \"{}\", a duplicate of \"{}\"",
+				if apply { did } else { would },
+				duplicate,
+				keep
+			)
+			.map_err(Error::Output)?;
+		}
+	}
+	output.flush().map_err(Error::Output)
+}
+
+impl DuplicateAction {
+	/// Replace or delete `duplicate`, a duplicate of `keep`.
+	///
+	/// Links are made under a temporary name first, then renamed over the
+	/// duplicate, so that it's never missing.
+	pub fn apply(&self, keep: &Path, duplicate: &Path) -> Result<(), Error> {
+		let replace = |link: fn(&Path, &Path) -> io::Result<()>| {
+			let mut temporary = duplicate.as_os_str().to_owned();
+			temporary.push(".quickdash-dupe");
+			let temporary = Path::new(&temporary);
+			link(keep, temporary)
+				.map_err(|e| Error::io(temporary, e))
+				.and_then(|_| {
+					fs::rename(temporary, duplicate).map_err(|e| {
+						let _ = fs::remove_file(temporary);
+						Error::io(duplicate, e)
+					})
+				})
+		};
+		match self {
+			DuplicateAction::Hardlink => replace(|keep, link| fs::hard_link(keep, link)),
+			DuplicateAction::Reflink => replace(|keep, link| reflink_copy::reflink(keep, link)),
+			DuplicateAction::Delete => {
+				fs::remove_file(duplicate).map_err(|e| Error::io(duplicate, e))
+			}
+		}
+	}
+}
+
+/// Fail unless the file at `path` has the size and modification time it had
+/// when `fingerprint` was taken.
+fn check_unchanged(path: &Path, fingerprint: &FileFingerprint) -> Result<(), Error> {
+	let meta = fs::metadata(path).map_err(|e| Error::io(path, e))?;
+	let now = FileFingerprint::from_metadata(&meta);
+	match (now.size, now.mtime_secs, now.mtime_nanos)
+		== (
+			fingerprint.size,
+			fingerprint.mtime_secs,
+			fingerprint.mtime_nanos,
+		) {
+		true => Ok(()),
+		false => Err(Error::DuplicateChanged(PathBuf::from(path))),
+	}
+}
+
+/// Whether the files at `a` and `b` hold the same bytes.
+fn same_contents(a: &Path, b: &Path) -> Result<bool, Error> {
+	let open = |path: &Path| File::open(path).map_err(|e| Error::io(path, e));
+	let (mut a_file, mut b_file) = (open(a)?, open(b)?);
+	let (mut a_buf, mut b_buf) = (vec![0; COMPARE_LEN], vec![0; COMPARE_LEN]);
+	loop {
+		let a_len = read_full(&mut a_file, &mut a_buf).map_err(|e| Error::io(a, e))?;
+		let b_len = read_full(&mut b_file, &mut b_buf).map_err(|e| Error::io(b, e))?;
+		if a_buf[..a_len] != b_buf[..b_len] {
+			return Ok(false);
+		}
+		if a_len == 0 {
+			return Ok(true);
+		}
+	}
+}
+
+/// Fill `buf` from `reader` unless it runs out first, and return how many
+/// bytes were read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+	let mut len = 0;
+	while len < buf.len() {
+		match reader.read(&mut buf[len..]) {
+			Ok(0) => break,
+			Ok(n) => len += n,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(len)
+}
+
+#[cfg(unix)]
+fn inode(meta: &Metadata) -> Option<(u64, u64)> {
+	use std::os::unix::fs::MetadataExt;
+	Some((meta.dev(), meta.ino()))
+}
+
+/// Without inodes, every file is its own.
+#[cfg(not(unix))]
+fn inode(_meta: &Metadata) -> Option<(u64, u64)> {
+	None
+}
@@ -0,0 +1,525 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::BTreeMap,
+	env,
+	fs::{self, Metadata},
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+use crate::{utilities::relative_name, Digests, Error, ParseError};
+
+/// Name of the files holding patterns of files to ignore in the directory
+/// they're in and below it, like `.gitignore` files.
+pub const IGNORE_FILE: &str = ".quickdashignore";
+
+/// gitignore-style patterns, matched against names relative to a directory.
+///
+/// A pattern is a glob where `*` doesn't match `/` and `**` matches any number
+/// of directories. It matches names at any depth unless it holds a `/` other
+/// than a trailing one, which anchors it to the directory, and a trailing `/`
+/// makes it only match directories. `!` negates a pattern, and `re:` makes it a
+/// regular expression the whole name has to match, except in git's files. The
+/// last matching pattern wins.
+///
+/// # Examples
+///
+/// ```
+/// # use quickdash::operations::Patterns;
+/// let patterns = Patterns::new(&["*.tmp", "!keep.tmp", "/build/", "re:v[0-9]+"]).unwrap();
+/// assert_eq!(patterns.matched("a/b.tmp", false), Some(true));
+/// assert_eq!(patterns.matched("a/keep.tmp", false), Some(false));
+/// assert_eq!(patterns.matched("build", true), Some(true));
+/// assert_eq!(patterns.matched("a/build", true), None);
+/// assert_eq!(patterns.matched("v12", false), Some(true));
+/// assert_eq!(patterns.matched("v12.txt", false), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Patterns {
+	rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+	negated: bool,
+	dir_only: bool,
+	matcher: Matcher,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+	Glob(GlobMatcher),
+	Regex(Regex),
+}
+
+impl Patterns {
+	/// Parse the specified patterns.
+	pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Patterns, Error> {
+		let rules = patterns
+			.iter()
+			.map(|pattern| {
+				let pattern = pattern.as_ref();
+				Rule::parse(pattern, true)
+					.map_err(|reason| Error::Pattern(ParseError::unnumbered(pattern, reason)))
+			})
+			.collect::<Result<_, _>>()?;
+		Ok(Patterns { rules })
+	}
+
+	/// Read the patterns in the specified file, one per line. Blank lines and
+	/// lines starting with `#` are skipped.
+	pub fn from_file(path: &Path) -> Result<Patterns, Error> {
+		Patterns::read(path, true)
+	}
+
+	/// Like `from_file()`, but read the patterns the way git does, so that
+	/// `re:` starts a glob like any other text.
+	pub fn from_gitignore(path: &Path) -> Result<Patterns, Error> {
+		Patterns::read(path, false)
+	}
+
+	fn read(path: &Path, regexes: bool) -> Result<Patterns, Error> {
+		let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+		let rules = text
+			.lines()
+			.enumerate()
+			.map(|(i, line)| (i, line.trim_end()))
+			.filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+			.map(|(i, line)| {
+				Rule::parse(line, regexes).map_err(|reason| {
+					Error::Pattern(ParseError::new(i + 1, line, reason).in_file(path))
+				})
+			})
+			.collect::<Result<_, _>>()?;
+		Ok(Patterns { rules })
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty()
+	}
+
+	/// Whether the last pattern matching `name` is a plain one, `Some(true)`,
+	/// or a negated one, `Some(false)`. `None` if none does.
+	pub fn matched(&self, name: &str, is_dir: bool) -> Option<bool> {
+		self.rules
+			.iter()
+			.rev()
+			.find(|rule| {
+				(is_dir || !rule.dir_only)
+					&& match &rule.matcher {
+						Matcher::Glob(glob) => glob.is_match(name),
+						Matcher::Regex(regex) => regex.is_match(name),
+					}
+			})
+			.map(|rule| !rule.negated)
+	}
+}
+
+impl Rule {
+	/// Parse a pattern, a regular expression if it starts with `re:` and
+	/// `regexes` are allowed.
+	fn parse(pattern: &str, regexes: bool) -> Result<Rule, String> {
+		let (negated, pattern) = match pattern.strip_prefix('!') {
+			Some(pattern) => (true, pattern),
+			None => (false, pattern),
+		};
+		if let Some(regex) = pattern.strip_prefix("re:").filter(|_| regexes) {
+			let regex = Regex::new(&format!("^(?:{})$", regex)).map_err(|e| e.to_string())?;
+			return Ok(Rule {
+				negated,
+				dir_only: false,
+				matcher: Matcher::Regex(regex),
+			});
+		}
+
+		let (dir_only, pattern) = match pattern.strip_suffix('/') {
+			Some(pattern) => (true, pattern),
+			None => (false, pattern),
+		};
+		let glob = match pattern.strip_prefix('/') {
+			Some(anchored) => anchored.to_string(),
+			None if pattern.contains('/') => pattern.to_string(),
+			None => format!("**/{}", pattern),
+		};
+		if glob.is_empty() {
+			return Err("empty pattern".to_string());
+		}
+		let glob = GlobBuilder::new(&glob)
+			.literal_separator(true)
+			.backslash_escape(true)
+			.build()
+			.map_err(|e| e.kind().to_string())?;
+		Ok(Rule {
+			negated,
+			dir_only,
+			matcher: Matcher::Glob(glob.compile_matcher()),
+		})
+	}
+}
+
+/// Which files of a tree are hashed, by their names relative to its root.
+///
+/// Files and directories matching the ignore patterns are ignored, unless a
+/// later negated pattern matches them. Ignored directories aren't descended
+/// into. The patterns of the `IGNORE_FILE`s found in the tree apply below the
+/// directory they're in, those of deeper ones first, after the specified ones.
+///
+/// If there are include patterns, only files that, or whose directories, match
+/// them are hashed. Files ignored by git, when respecting `.gitignore` files,
+/// version control metadata, when skipping it, and files that don't meet all
+/// the predicates are left out too.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+	ignored: Patterns,
+	included: Patterns,
+	respect_gitignore: bool,
+	skip_vcs: bool,
+	predicates: Vec<FilePredicate>,
+}
+
+/// A `find`-like condition on a file's metadata.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum FilePredicate {
+	/// At least this many bytes long.
+	MinSize(u64),
+	/// At most this many bytes long.
+	MaxSize(u64),
+	/// Modified after the specified time.
+	NewerThan(SystemTime),
+	/// Modified before the specified time.
+	OlderThan(SystemTime),
+	/// Owned by the specified user ID. Never met off Unix.
+	Owner(u32),
+	/// Owned by the specified group ID. Never met off Unix.
+	Group(u32),
+	/// With all of the specified permission bits set, like `find -perm -MODE`.
+	/// Never met off Unix.
+	Permissions(u32),
+	/// With one of the specified extensions, compared case-insensitively.
+	Extensions(Vec<String>),
+}
+
+impl FilePredicate {
+	/// Whether the specified file meets the predicate.
+	pub fn matches(&self, name: &str, meta: &Metadata) -> bool {
+		match self {
+			FilePredicate::MinSize(size) => meta.len() >= *size,
+			FilePredicate::MaxSize(size) => meta.len() <= *size,
+			FilePredicate::NewerThan(time) => meta.modified().is_ok_and(|m| m > *time),
+			FilePredicate::OlderThan(time) => meta.modified().is_ok_and(|m| m < *time),
+			FilePredicate::Owner(uid) => unix_ids(meta).is_some_and(|(id, ..)| id == *uid),
+			FilePredicate::Group(gid) => unix_ids(meta).is_some_and(|(_, id, _)| id == *gid),
+			FilePredicate::Permissions(bits) => {
+				unix_ids(meta).is_some_and(|(.., mode)| mode & bits == *bits)
+			}
+			FilePredicate::Extensions(extensions) => {
+				// Dotfiles' names aren't extensions
+				let basename = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+				extensions.iter().any(|extension| {
+					let extension = extension.trim_start_matches('.').to_lowercase();
+					basename
+						.strip_suffix(&extension)
+						.and_then(|stem| stem.strip_suffix('.'))
+						.is_some_and(|stem| !stem.is_empty())
+				})
+			}
+		}
+	}
+}
+
+/// The owner's user and group IDs and the permission bits of a file.
+#[cfg(unix)]
+fn unix_ids(meta: &Metadata) -> Option<(u32, u32, u32)> {
+	use std::os::unix::fs::MetadataExt;
+	Some((meta.uid(), meta.gid(), meta.mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn unix_ids(_meta: &Metadata) -> Option<(u32, u32, u32)> {
+	None
+}
+
+/// What a `PathFilter` makes of a file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Filtered {
+	Kept,
+	Ignored,
+	/// Left out altogether.
+	Excluded,
+}
+
+/// The patterns of the ignore files that apply to the part of a tree walked so
+/// far.
+#[derive(Debug, Default)]
+pub(crate) struct IgnoreFiles {
+	/// `IGNORE_FILE`s' patterns, by directory.
+	local: BTreeMap<String, Patterns>,
+	/// `.gitignore` files' patterns, by directory.
+	git: BTreeMap<String, Patterns>,
+	/// The patterns git applies to the tree from outside of it, in order, with
+	/// the path of the tree relative to where they apply.
+	outer: Vec<(String, Patterns)>,
+}
+
+impl PathFilter {
+	/// Parse the specified ignore and include patterns.
+	pub fn new<S: AsRef<str>>(ignored: &[S], included: &[S]) -> Result<PathFilter, Error> {
+		Ok(PathFilter {
+			ignored: Patterns::new(ignored)?,
+			included: Patterns::new(included)?,
+			respect_gitignore: false,
+			skip_vcs: false,
+			predicates: Vec::new(),
+		})
+	}
+
+	/// Leave out the files git ignores: those matching the `.gitignore` files
+	/// in the tree and in its parents up to the root of its repository, the
+	/// repository's `.git/info/exclude` and the user's global excludes file.
+	pub fn respect_gitignore(mut self, respect: bool) -> PathFilter {
+		self.respect_gitignore = respect;
+		self
+	}
+
+	/// Leave out the `VCS_DIRS`.
+	pub fn skip_vcs(mut self, skip: bool) -> PathFilter {
+		self.skip_vcs = skip;
+		self
+	}
+
+	/// Leave out the files that don't meet all the specified predicates.
+	pub fn select(mut self, predicates: Vec<FilePredicate>) -> PathFilter {
+		for predicate in predicates {
+			if !self.predicates.contains(&predicate) {
+				self.predicates.push(predicate);
+			}
+		}
+		self
+	}
+
+	/// Whether there are predicates files have to meet.
+	pub fn has_predicates(&self) -> bool {
+		!self.predicates.is_empty()
+	}
+
+	/// Whether the specified file meets all the predicates.
+	pub fn selects(&self, name: &str, meta: &Metadata) -> bool {
+		self.predicates
+			.iter()
+			.all(|predicate| predicate.matches(name, meta))
+	}
+
+	/// Remove the loaded digests of the files in the tree at `root` that don't
+	/// meet the predicates, so that only the selected files are compared.
+	/// Files that are gone are kept.
+	pub fn deselect(&self, root: &Path, hashes: &mut BTreeMap<String, Digests>) {
+		if self.has_predicates() {
+			hashes.retain(|name, _| {
+				fs::metadata(root.join(name)).map_or(true, |meta| self.selects(name, &meta))
+			});
+		}
+	}
+
+	/// Whether the specified file is included.
+	pub fn includes(&self, name: &str) -> bool {
+		if self.included.is_empty() {
+			return true;
+		}
+		let (mut name, mut is_dir) = (name, false);
+		loop {
+			if let Some(included) = self.included.matched(name, is_dir) {
+				return included;
+			}
+			match name.rsplit_once('/') {
+				Some((dir, _)) => (name, is_dir) = (dir, true),
+				None => return false,
+			}
+		}
+	}
+
+	/// What to make of the specified file or directory, given the ignore
+	/// files found so far.
+	pub(crate) fn filter(&self, files: &IgnoreFiles, name: &str, is_dir: bool) -> Filtered {
+		let basename = name.rsplit('/').next().unwrap_or(name);
+		if self.skip_vcs && VCS_DIRS.contains(&basename) {
+			return Filtered::Excluded;
+		}
+		let ignored = self
+			.ignored
+			.matched(name, is_dir)
+			.or_else(|| matched_below(&files.local, name, is_dir));
+		if ignored == Some(true) {
+			return Filtered::Ignored;
+		}
+		if self.respect_gitignore {
+			let ignored = matched_below(&files.git, name, is_dir).or_else(|| {
+				files.outer.iter().find_map(|(prefix, patterns)| {
+					patterns.matched(&format!("{}{}", prefix, name), is_dir)
+				})
+			});
+			if ignored == Some(true) {
+				return Filtered::Excluded;
+			}
+		}
+		Filtered::Kept
+	}
+
+	/// Load the ignore files of the specified directory of the tree at `root`,
+	/// and the patterns git applies from outside the tree if it's `root`.
+	///
+	/// Ignore files that can't be read are skipped, and reported when they
+	/// fail to be hashed.
+	pub(crate) fn enter(
+		&self,
+		files: &mut IgnoreFiles,
+		root: &Path,
+		dir: &Path,
+		name: String,
+	) -> Result<(), Error> {
+		if self.respect_gitignore {
+			if name.is_empty() {
+				files.outer = outer_git_patterns(root)?;
+			}
+			if let Some(patterns) = read_patterns(&dir.join(".gitignore"), true)? {
+				files.git.insert(name.clone(), patterns);
+			}
+		}
+		if let Some(patterns) = read_patterns(&dir.join(IGNORE_FILE), false)? {
+			files.local.insert(name, patterns);
+		}
+		Ok(())
+	}
+}
+
+/// Names of the metadata directories of version control systems.
+pub const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Match `name` against the patterns of the directories it's in, deepest
+/// first, relative to each.
+fn matched_below(patterns: &BTreeMap<String, Patterns>, name: &str, is_dir: bool) -> Option<bool> {
+	let mut dir = name;
+	while !dir.is_empty() {
+		dir = dir.rsplit_once('/').map_or("", |(dir, _)| dir);
+		if let Some(patterns) = patterns.get(dir) {
+			let rest = match dir.is_empty() {
+				true => name,
+				false => &name[dir.len() + 1..],
+			};
+			if let Some(matched) = patterns.matched(rest, is_dir) {
+				return Some(matched);
+			}
+		}
+	}
+	None
+}
+
+/// Read the patterns in the specified file, if it can be read, the way git
+/// does if it's one of git's.
+fn read_patterns(path: &Path, git: bool) -> Result<Option<Patterns>, Error> {
+	let patterns = match git {
+		true => Patterns::from_gitignore(path),
+		false => Patterns::from_file(path),
+	};
+	match patterns {
+		Ok(patterns) => Ok(Some(patterns)),
+		Err(Error::Io { .. }) => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// The patterns git applies to the tree at `root` from outside of it, in the
+/// order git applies them.
+fn outer_git_patterns(root: &Path) -> Result<Vec<(String, Patterns)>, Error> {
+	let root = root.canonicalize().map_err(|e| Error::io(root, e))?;
+	let mut outer = Vec::new();
+	let mut prefix_in = |dir: &Path, patterns: Option<Patterns>| {
+		if let Some(patterns) = patterns {
+			let prefix = match relative_name(dir, &root) {
+				name if name.is_empty() => name,
+				name => name + "/",
+			};
+			outer.push((prefix, patterns));
+		}
+	};
+
+	let repository = root.ancestors().find(|dir| dir.join(".git").exists());
+	if let Some(repository) = repository {
+		for dir in root
+			.ancestors()
+			.skip(1)
+			.take_while(|dir| dir.starts_with(repository))
+		{
+			prefix_in(dir, read_patterns(&dir.join(".gitignore"), true)?);
+		}
+		let exclude = repository.join(".git").join("info").join("exclude");
+		prefix_in(repository, read_patterns(&exclude, true)?);
+	}
+	if let Some(excludes) = global_excludes_file() {
+		prefix_in(repository.unwrap_or(&root), read_patterns(&excludes, true)?);
+	}
+	Ok(outer)
+}
+
+/// The user's global git excludes file: `core.excludesFile` in their git
+/// configuration, or `git/ignore` in their configuration directory.
+fn global_excludes_file() -> Option<PathBuf> {
+	let home = env::var_os("HOME").map(PathBuf::from);
+	let config_dir = env::var_os("XDG_CONFIG_HOME")
+		.map(PathBuf::from)
+		.or_else(|| Some(home.as_ref()?.join(".config")));
+
+	let configs = [
+		config_dir
+			.as_ref()
+			.map(|dir| dir.join("git").join("config")),
+		home.as_ref().map(|home| home.join(".gitconfig")),
+	];
+	// The home directory's configuration is read last, so its setting wins
+	let configured = configs
+		.iter()
+		.rev()
+		.flatten()
+		.filter_map(|config| fs::read_to_string(config).ok())
+		.find_map(|config| excludes_file_setting(&config));
+	match configured {
+		Some(file) => match (file.strip_prefix("~/"), &home) {
+			(Some(file), Some(home)) => Some(home.join(file)),
+			_ => Some(PathBuf::from(file)),
+		},
+		None => Some(config_dir?.join("git").join("ignore")),
+	}
+}
+
+/// The last `excludesFile` setting in the `[core]` section of a git
+/// configuration file.
+fn excludes_file_setting(config: &str) -> Option<String> {
+	let mut in_core = false;
+	let mut setting = None;
+	for line in config.lines().map(str::trim) {
+		if line.starts_with('[') {
+			in_core = line.to_ascii_lowercase().starts_with("[core]");
+		} else if let Some((key, value)) = line.split_once('=') {
+			if in_core && key.trim().eq_ignore_ascii_case("excludesfile") {
+				setting = Some(value.trim().trim_matches('"').to_string());
+			}
+		}
+	}
+	setting
+}
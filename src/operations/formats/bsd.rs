@@ -0,0 +1,88 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	io::{self, Write},
+	str::FromStr,
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::{
+	coreutils::{escape, unescape},
+	is_placeholder,
+	Columns,
+	Entries,
+};
+use crate::Algorithm;
+
+static LINE_RGX: Lazy<Regex> =
+	Lazy::new(|| Regex::new(r"^(\\?)([[:alnum:]/-]+) \((.*)\) = ([[:xdigit:]]+)$").unwrap());
+
+/// Whether the line looks like a tagged one.
+pub(super) fn is_tagged(line: &str) -> bool {
+	LINE_RGX.is_match(line)
+}
+
+/// Parses an `ALGO (name) = digest` line, mapping the tag through
+/// `Algorithm::from_str()`. A file may be listed once per algorithm.
+pub(super) fn parse_line(line: &str) -> Result<(String, Columns), &'static str> {
+	let captures = LINE_RGX
+		.captures(line)
+		.ok_or("expected `ALGO (name) = digest`")?;
+	let algo = Algorithm::from_str(&captures[2]).map_err(|_| "unknown algorithm")?;
+	let fname = match &captures[1] {
+		"" => captures[3].to_string(),
+		_ => unescape(&captures[3]).ok_or("invalid escape in name")?,
+	};
+	Ok((fname, vec![(Some(algo), captures[4].to_uppercase())]))
+}
+
+/// Writes one line per file and algorithm, with lowercase digests. Ignored
+/// files are left out.
+pub(super) fn write<W: Write>(
+	out: &mut W,
+	algos: &[Algorithm],
+	entries: Entries<'_>,
+) -> io::Result<()> {
+	for entry in entries {
+		let (fname, digests) = entry?;
+		for (algo, digest) in &digests {
+			if !algos.contains(algo) || is_placeholder(digest) {
+				continue;
+			}
+
+			if fname.contains(['\\', '\n', '\r']) {
+				writeln!(
+					out,
+					"\\{} ({}) = {}",
+					algo.tag(),
+					escape(&fname),
+					digest.to_lowercase()
+				)?;
+			} else {
+				writeln!(
+					out,
+					"{} ({}) = {}",
+					algo.tag(),
+					fname,
+					digest.to_lowercase()
+				)?;
+			}
+		}
+	}
+	Ok(())
+}
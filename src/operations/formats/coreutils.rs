@@ -0,0 +1,87 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::{self, Write};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::{is_placeholder, Columns, Entries};
+use crate::Algorithm;
+
+/// Parses a `digest  name` (text mode) or `digest *name` (binary mode) line.
+///
+/// Lines starting with a backslash have `\\`, `\n` and `\r` escapes in the
+/// name, as coreutils writes names containing those characters.
+pub(super) fn parse_line(line: &str) -> Result<(String, Columns), &'static str> {
+	static LINE_RGX: Lazy<Regex> =
+		Lazy::new(|| Regex::new(r"^(\\?)([[:xdigit:]]+) [ *](.+)$").unwrap());
+
+	let captures = LINE_RGX
+		.captures(line)
+		.ok_or("expected `digest  name` or `digest *name`")?;
+	let fname = match &captures[1] {
+		"" => captures[3].to_string(),
+		_ => unescape(&captures[3]).ok_or("invalid escape in name")?,
+	};
+	Ok((fname, vec![(None, captures[2].to_uppercase())]))
+}
+
+/// Writes text mode lines with lowercase digests, the same way `sha256sum`,
+/// `b2sum` etc. do. Ignored files are left out.
+pub(super) fn write<W: Write>(
+	out: &mut W,
+	algo: Algorithm,
+	entries: Entries<'_>,
+) -> io::Result<()> {
+	for entry in entries {
+		let (fname, digests) = entry?;
+		let digest = match digests.get(&algo) {
+			Some(digest) if !is_placeholder(digest) => digest.to_lowercase(),
+			_ => continue,
+		};
+
+		if fname.contains(['\\', '\n', '\r']) {
+			writeln!(out, "\\{}  {}", digest, escape(&fname))?;
+		} else {
+			writeln!(out, "{}  {}", digest, fname)?;
+		}
+	}
+	Ok(())
+}
+
+pub(crate) fn escape(fname: &str) -> String {
+	fname
+		.replace('\\', "\\\\")
+		.replace('\n', "\\n")
+		.replace('\r', "\\r")
+}
+
+pub(crate) fn unescape(fname: &str) -> Option<String> {
+	let mut result = String::with_capacity(fname.len());
+	let mut chars = fname.chars();
+	while let Some(c) = chars.next() {
+		result.push(match c {
+			'\\' => match chars.next()? {
+				'\\' => '\\',
+				'n' => '\n',
+				'r' => '\r',
+				_ => return None,
+			},
+			c => c,
+		});
+	}
+	Some(result)
+}
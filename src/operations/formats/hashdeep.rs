@@ -0,0 +1,153 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	env,
+	fs::metadata,
+	io::{self, Write},
+	path::Path,
+};
+
+use super::{is_placeholder, Columns, Entries};
+use crate::{Algorithm, ParseError};
+
+static HEADER_MAGIC: &str = "%%%% HASHDEEP-1.0";
+
+/// Hash columns hashdeep knows, in the order it writes them. Tiger has no
+/// QuickDash counterpart.
+static COLUMNS: [(&str, Option<Algorithm>); 5] = [
+	("md5", Some(Algorithm::MD5)),
+	("sha1", Some(Algorithm::SHA1)),
+	("sha256", Some(Algorithm::SHA2256)),
+	("tiger", None),
+	("whirlpool", Some(Algorithm::WhirlPool)),
+];
+
+/// Whether the line starts a hashdeep file.
+pub(super) fn is_header_start(line: &str) -> bool {
+	line.trim_end() == HEADER_MAGIC
+}
+
+/// Whether hashdeep can hold digests made with `algo`.
+pub(super) fn supports(algo: Algorithm) -> bool {
+	COLUMNS.iter().any(|(_, a)| *a == Some(algo))
+}
+
+/// Parses the magic line and the `%%%% size,md5,...,filename` column list into
+/// the algorithms of the hash columns. Those QuickDash has none for are `None`.
+pub(super) fn parse_header(lines: &[&str]) -> Result<Vec<Option<Algorithm>>, ParseError> {
+	match lines.first() {
+		Some(line) if is_header_start(line) => {}
+		first => {
+			let line = first.copied().unwrap_or("");
+			return Err(ParseError::new(1, line, "expected `%%%% HASHDEEP-1.0`"));
+		}
+	}
+
+	let header = lines.get(1).copied().unwrap_or("");
+	let error = |reason: &str| ParseError::new(2, header, reason);
+	let columns: Vec<_> = header
+		.strip_prefix("%%%% ")
+		.ok_or_else(|| error("expected `%%%% size,...,filename`"))?
+		.split(',')
+		.collect();
+	let algos = match &columns[..] {
+		["size", algos @ .., "filename"] if !algos.is_empty() => algos
+			.iter()
+			.map(|name| {
+				COLUMNS
+					.iter()
+					.find(|(n, _)| n == name)
+					.map(|(_, algo)| *algo)
+					.ok_or_else(|| error("unknown hash column"))
+			})
+			.collect::<Result<Vec<_>, _>>()?,
+		_ => return Err(error("expected `%%%% size,...,filename`")),
+	};
+	if algos.iter().all(Option::is_none) {
+		return Err(error("no supported hash columns"));
+	}
+	Ok(algos)
+}
+
+/// Parses a `size,digest...,name` row with the columns listed in the header.
+/// Digests in columns QuickDash has no algorithm for are dropped, and so is
+/// the `./` prefix `hashdeep -l` gives names. The size is only checked to be a
+/// number: the digests tell whether the file changed.
+pub(super) fn parse_line(
+	algos: &[Option<Algorithm>],
+	line: &str,
+) -> Result<(String, Columns), &'static str> {
+	// Names may contain commas, so everything past the digests is the name
+	let mut fields = line.splitn(algos.len() + 2, ',');
+	fields
+		.next()
+		.and_then(|size| size.parse::<u64>().ok())
+		.ok_or("invalid size")?;
+	let digests = algos
+		.iter()
+		.map(|algo| Some((*algo, fields.next()?)))
+		.collect::<Option<Vec<_>>>()
+		.ok_or("missing hash columns")?;
+	let fname = fields.next().ok_or("missing file name")?;
+
+	let mut row = Vec::new();
+	for (algo, digest) in digests {
+		if !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+			return Err("invalid digest");
+		}
+		if let Some(algo) = algo {
+			row.push((Some(algo), digest.to_uppercase()));
+		}
+	}
+	Ok((fname.strip_prefix("./").unwrap_or(fname).to_string(), row))
+}
+
+/// Writes the column list, the invocation comments, then
+/// `size,digest...,name` rows with lowercase digests, in hashdeep's column
+/// order. Ignored files are left out.
+pub(super) fn write<W: Write>(
+	out: &mut W,
+	root: &Path,
+	algos: &[Algorithm],
+	entries: Entries<'_>,
+) -> io::Result<()> {
+	let columns: Vec<_> = COLUMNS
+		.iter()
+		.filter_map(|(name, algo)| Some((*name, (*algo).filter(|a| algos.contains(a))?)))
+		.collect();
+	let names: Vec<_> = columns.iter().map(|(name, _)| *name).collect();
+
+	writeln!(out, "{}", HEADER_MAGIC)?;
+	writeln!(out, "%%%% size,{},filename", names.join(","))?;
+	writeln!(out, "## Invoked from: {}", root.display())?;
+	writeln!(out, "## $ {}", env::args().collect::<Vec<_>>().join(" "))?;
+	writeln!(out, "##")?;
+	for entry in entries {
+		let (fname, digests) = entry?;
+		let row: Option<Vec<_>> = columns
+			.iter()
+			.map(|(_, algo)| digests.get(algo).filter(|d| !is_placeholder(d)))
+			.map(|digest| digest.map(|d| d.to_lowercase()))
+			.collect();
+		let row = match row {
+			Some(row) => row,
+			None => continue,
+		};
+		let size = metadata(root.join(&fname)).map(|m| m.len()).unwrap_or(0);
+		writeln!(out, "{},{},{}", size, row.join(","), fname)?;
+	}
+	Ok(())
+}
@@ -0,0 +1,314 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Manifest file formats.
+
+mod bsd;
+mod coreutils;
+mod hashdeep;
+mod native;
+mod sfv;
+
+use std::{
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use clap::ArgEnum;
+
+pub(super) use self::coreutils::{escape, unescape};
+use super::ManifestHeader;
+use crate::{Algorithm, Digests, Error, ParseError};
+
+/// Digest columns of a manifest entry, tagged with their algorithm in formats
+/// that record it per digest.
+pub(super) type Columns = Vec<(Option<Algorithm>, String)>;
+
+/// A manifest entry as read from its line.
+pub(super) struct Row {
+	/// 1-based number of the line.
+	pub line: usize,
+	/// The line itself, CR dropped.
+	pub text: String,
+	/// Name of the file.
+	pub name: String,
+	/// Digest columns.
+	pub columns: Columns,
+}
+
+/// Parses a single entry line into its name and digest columns, or tells
+/// what's wrong with it.
+type LineParser = Box<dyn Fn(&str) -> Result<(String, Columns), &'static str>>;
+
+/// Entries to write, sorted by name.
+pub(super) type Entries<'a> = Box<dyn Iterator<Item = io::Result<(String, Digests)>> + 'a>;
+
+/// A manifest file format.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, ArgEnum)]
+pub enum ManifestFormat {
+	/// QuickDash's own format: a header, then `DIGEST[ DIGEST]...  name` lines.
+	Hash,
+	/// Simple File Verification: `name CRC32` lines, `;` comments.
+	Sfv,
+	/// GNU coreutils `sha256sum`, `b2sum`, `md5sum` etc.: `digest  name` lines
+	/// of a single algorithm.
+	Coreutils,
+	/// BSD-style tagged lines, as written by `shasum --tag` or `cksum -a`:
+	/// `ALGO (name) = digest`, any algorithms for any file.
+	Bsd,
+	/// hashdeep/md5deep audit files: a `%%%% HASHDEEP-1.0` header, then
+	/// `size,md5,sha256,filename` rows.
+	Hashdeep,
+}
+
+impl ManifestFormat {
+	/// Pick the format conventionally used by files named like `path`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # use std::path::Path;
+	/// # use quickdash::operations::ManifestFormat;
+	/// assert_eq!(
+	/// 	ManifestFormat::from_path(Path::new("dir/dir.sfv")),
+	/// 	ManifestFormat::Sfv
+	/// );
+	/// assert_eq!(
+	/// 	ManifestFormat::from_path(Path::new("dir/SHA256SUMS")),
+	/// 	ManifestFormat::Coreutils
+	/// );
+	/// assert_eq!(
+	/// 	ManifestFormat::from_path(Path::new("dir/dir.hash")),
+	/// 	ManifestFormat::Hash
+	/// );
+	/// ```
+	pub fn from_path(path: &Path) -> ManifestFormat {
+		let name = path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+		let ext = path
+			.extension()
+			.map(|e| e.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+
+		match &ext[..] {
+			"sfv" => ManifestFormat::Sfv,
+			"hashdeep" => ManifestFormat::Hashdeep,
+			"md5" | "sha1" | "sha224" | "sha256" | "sha384" | "sha512" | "b2" | "b3" => {
+				ManifestFormat::Coreutils
+			}
+			_ if name.ends_with("sums") => ManifestFormat::Coreutils,
+			_ => ManifestFormat::Hash,
+		}
+	}
+
+	/// The extension of files in this format.
+	pub fn extension(&self) -> &'static str {
+		match *self {
+			ManifestFormat::Hash => "hash",
+			ManifestFormat::Sfv => "sfv",
+			ManifestFormat::Coreutils | ManifestFormat::Bsd => "sums",
+			ManifestFormat::Hashdeep => "hashdeep",
+		}
+	}
+
+	/// Whether the format can hold digests made with all of `algos`.
+	pub fn supports(&self, algos: &[Algorithm]) -> bool {
+		match *self {
+			ManifestFormat::Hash | ManifestFormat::Bsd => !algos.is_empty(),
+			ManifestFormat::Sfv => algos == [Algorithm::CRC32],
+			ManifestFormat::Coreutils => algos.len() == 1,
+			ManifestFormat::Hashdeep => {
+				!algos.is_empty() && algos.iter().all(|algo| hashdeep::supports(*algo))
+			}
+		}
+	}
+}
+
+/// Reads a manifest's header, if the format has one, then its entries one line
+/// at a time.
+///
+/// Trailing CRs are dropped, and blank lines and `#` or `;` comments between
+/// entries skipped. Only a malformed header fails the whole manifest: entry
+/// lines that can't be parsed are rejected one by one.
+///
+/// `*SUMS` files hold tagged lines just as often as coreutils ones, so those
+/// are read as tagged if they start with a tagged line. Likewise, hashdeep
+/// files are recognised by their header whatever they're named.
+pub(super) struct RowReader<L> {
+	file: PathBuf,
+	lines: L,
+	/// How many lines were taken out of `lines`.
+	line: usize,
+	lookahead: Option<String>,
+	failed: bool,
+	header: Option<ManifestHeader>,
+	/// `None` until the first entry of a coreutils manifest tells its flavour.
+	parse_line: Option<LineParser>,
+}
+
+impl<L: Iterator<Item = io::Result<String>>> RowReader<L> {
+	/// Read the header of the manifest at `file`, made of `lines`.
+	pub(super) fn new(
+		file: &Path,
+		format: ManifestFormat,
+		lines: L,
+	) -> Result<RowReader<L>, Error> {
+		let mut reader = RowReader {
+			file: file.to_path_buf(),
+			lines,
+			line: 0,
+			lookahead: None,
+			failed: false,
+			header: None,
+			parse_line: None,
+		};
+
+		let is_hashdeep = reader.peek_line()?.is_some_and(hashdeep::is_header_start);
+		reader.parse_line = match format {
+			_ if is_hashdeep || format == ManifestFormat::Hashdeep => {
+				let mut header = Vec::new();
+				while header.len() < 2 {
+					match reader.read_line()? {
+						Some((_, line)) => header.push(line),
+						None => break,
+					}
+				}
+				let header: Vec<_> = header.iter().map(String::as_str).collect();
+				let columns = hashdeep::parse_header(&header).map_err(|e| e.in_file(file))?;
+				Some(Box::new(move |line| hashdeep::parse_line(&columns, line)))
+			}
+			ManifestFormat::Hash => {
+				let mut header = Vec::new();
+				if reader
+					.peek_line()?
+					.is_some_and(ManifestHeader::is_header_start)
+				{
+					while reader.peek_line()?.is_some_and(|l| l.starts_with('#')) {
+						header.extend(reader.read_line()?.map(|(_, line)| line));
+					}
+				}
+				let header: Vec<_> = header.iter().map(String::as_str).collect();
+				reader.header = ManifestHeader::parse(&header).map_err(|e| e.in_file(file))?;
+				Some(Box::new(native::parse_line))
+			}
+			ManifestFormat::Sfv => Some(Box::new(sfv::parse_line)),
+			ManifestFormat::Bsd => Some(Box::new(bsd::parse_line)),
+			ManifestFormat::Coreutils | ManifestFormat::Hashdeep => None,
+		};
+		Ok(reader)
+	}
+
+	/// The manifest's header, if it has one.
+	pub(super) fn header(&self) -> Option<&ManifestHeader> {
+		self.header.as_ref()
+	}
+
+	/// The next line and its number, CR dropped.
+	fn read_line(&mut self) -> Result<Option<(usize, String)>, Error> {
+		if let Some(line) = self.lookahead.take() {
+			return Ok(Some((self.line, line)));
+		}
+		if self.failed {
+			return Ok(None);
+		}
+
+		match self.lines.next() {
+			Some(Ok(mut line)) => {
+				self.line += 1;
+				if line.ends_with('\r') {
+					line.pop();
+				}
+				Ok(Some((self.line, line)))
+			}
+			Some(Err(e)) => {
+				self.failed = true;
+				Err(Error::io(&self.file, e))
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn peek_line(&mut self) -> Result<Option<&str>, Error> {
+		if self.lookahead.is_none() {
+			self.lookahead = self.read_line()?.map(|(_, line)| line);
+		}
+		Ok(self.lookahead.as_deref())
+	}
+}
+
+impl<L: Iterator<Item = io::Result<String>>> Iterator for RowReader<L> {
+	type Item = Result<Row, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let (n, line) = match self.read_line() {
+				Ok(Some(line)) => line,
+				Ok(None) => return None,
+				Err(e) => return Some(Err(e)),
+			};
+			if is_skipped(&line) {
+				continue;
+			}
+
+			let parse_line = self
+				.parse_line
+				.get_or_insert_with(|| match bsd::is_tagged(&line) {
+					true => Box::new(bsd::parse_line),
+					false => Box::new(coreutils::parse_line),
+				});
+			return Some(match parse_line(&line) {
+				Ok((name, columns)) => Ok(Row {
+					line: n,
+					text: line,
+					name,
+					columns,
+				}),
+				Err(reason) => Err(ParseError::new(n, &line, reason).in_file(&self.file).into()),
+			});
+		}
+	}
+}
+
+/// Whether the line holds no entry: it's blank or a comment.
+fn is_skipped(line: &str) -> bool {
+	line.trim().is_empty() || line.starts_with('#') || line.starts_with(';')
+}
+
+/// Write a manifest in the specified format.
+///
+/// `root` is the directory the entries' names are relative to. `entries` is
+/// called once per pass over the entries the format needs.
+pub(super) fn write<'a, W: Write>(
+	format: ManifestFormat,
+	out: &mut W,
+	root: &Path,
+	header: &ManifestHeader,
+	entries: &dyn Fn() -> io::Result<Entries<'a>>,
+) -> io::Result<()> {
+	match format {
+		ManifestFormat::Hash => native::write(out, header, entries()?),
+		ManifestFormat::Sfv => sfv::write(out, root, entries),
+		ManifestFormat::Coreutils => coreutils::write(out, header.algorithms[0], entries()?),
+		ManifestFormat::Bsd => bsd::write(out, &header.algorithms, entries()?),
+		ManifestFormat::Hashdeep => hashdeep::write(out, root, &header.algorithms, entries()?),
+	}
+}
+
+/// Whether the digest marks an ignored file.
+fn is_placeholder(digest: &str) -> bool {
+	digest.bytes().all(|b| b == b'-')
+}
@@ -0,0 +1,75 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::{self, Write};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::{Columns, Entries};
+use crate::{
+	operations::{column_order, ManifestHeader},
+	utilities::mul_str,
+};
+
+/// Parses a `DIGEST[ DIGEST]...  name` line, or a `name DIGEST` one as older
+/// versions wrote them.
+pub(super) fn parse_line(line: &str) -> Result<(String, Columns), &'static str> {
+	static LINE_RGX1: Lazy<Regex> =
+		Lazy::new(|| Regex::new(r"(?i)^([[:xdigit:]-]+(?: [[:xdigit:]-]+)*)\s{2,}(.+?)$").unwrap());
+
+	static LINE_RGX2: Lazy<Regex> =
+		Lazy::new(|| Regex::new(r"(?i)^(.+?)\t{0,}\s{1,}([[:xdigit:]-]+)$").unwrap());
+
+	let (fname, columns) = if let Some(captures) = LINE_RGX1.captures(line) {
+		(captures[2].to_string(), captures[1].to_string())
+	} else if let Some(captures) = LINE_RGX2.captures(line) {
+		(captures[1].to_string(), captures[2].to_string())
+	} else {
+		return Err("expected `DIGEST[ DIGEST]...  name`");
+	};
+
+	let columns = columns
+		.split(' ')
+		.map(|c| (None, c.to_uppercase()))
+		.collect();
+	Ok((fname, columns))
+}
+
+/// With several algorithms, each line holds their digests separated by single
+/// spaces, in the order of `Algorithm`'s variants.
+pub(super) fn write<W: Write>(
+	out: &mut W,
+	header: &ManifestHeader,
+	entries: Entries<'_>,
+) -> io::Result<()> {
+	let algos = column_order(&header.algorithms);
+
+	header.write(out)?;
+	for entry in entries {
+		let (fname, digests) = entry?;
+		let columns: Vec<_> = algos
+			.iter()
+			.map(|algo| {
+				digests
+					.get(algo)
+					.cloned()
+					.unwrap_or_else(|| mul_str("-", algo.hexlen()))
+			})
+			.collect();
+		writeln!(out, "{}  {}", columns.join(" "), fname)?;
+	}
+	Ok(())
+}
@@ -0,0 +1,95 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	fs::metadata,
+	io::{self, Write},
+	path::Path,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{is_placeholder, Columns, Entries};
+use crate::{utilities::utc_datetime, Algorithm};
+
+/// Parses a `name CRC32` line. Names may contain spaces, and backslashes as
+/// path separators.
+pub(super) fn parse_line(line: &str) -> Result<(String, Columns), &'static str> {
+	let (fname, crc) = line
+		.trim_end()
+		.rsplit_once(' ')
+		.filter(|(_, crc)| crc.len() == 8 && crc.bytes().all(|b| b.is_ascii_hexdigit()))
+		.ok_or("expected `name CRC32`")?;
+	Ok((fname.replace('\\', "/"), vec![(None, crc.to_uppercase())]))
+}
+
+/// Writes a QuickSFV-style comment block listing each file's size and
+/// modification time, then the `name CRC32` lines. Ignored files are left out.
+pub(super) fn write<'a, W: Write>(
+	out: &mut W,
+	root: &Path,
+	entries: &dyn Fn() -> io::Result<Entries<'a>>,
+) -> io::Result<()> {
+	let crcs = || -> io::Result<_> {
+		Ok(entries()?.filter_map(|entry| match entry {
+			Ok((fname, mut digests)) => digests
+				.remove(&Algorithm::CRC32)
+				.filter(|crc| !is_placeholder(crc))
+				.map(|crc| Ok((fname, crc))),
+			Err(e) => Some(Err(e)),
+		}))
+	};
+
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	let (date, time) = sfv_timestamp(now);
+	writeln!(
+		out,
+		"; Generated by QuickDash v{} on {} at {}",
+		env!("CARGO_PKG_VERSION"),
+		date,
+		time
+	)?;
+	writeln!(out, ";")?;
+	for entry in crcs()? {
+		let (fname, _) = entry?;
+		if let Ok(meta) = metadata(root.join(&fname)) {
+			let mtime = meta
+				.modified()
+				.ok()
+				.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+				.unwrap_or_default()
+				.as_secs();
+			let (date, time) = sfv_timestamp(mtime);
+			writeln!(out, ";{:>13}  {} {} {}", meta.len(), time, date, fname)?;
+		}
+	}
+
+	for entry in crcs()? {
+		let (fname, crc) = entry?;
+		writeln!(out, "{} {}", fname, crc)?;
+	}
+	Ok(())
+}
+
+/// `YYYY-MM-DD` and `HH:MM.SS`, as QuickSFV writes them.
+fn sfv_timestamp(secs: u64) -> (String, String) {
+	let ((year, month, day), (hour, minute, second)) = utc_datetime(secs);
+	(
+		format!("{:04}-{:02}-{:02}", year, month, day),
+		format!("{:02}:{:02}.{:02}", hour, minute, second),
+	)
+}
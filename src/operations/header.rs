@@ -0,0 +1,311 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{io::Write, str::FromStr};
+
+use super::{FilePredicate, PathFilter};
+use crate::{Algorithm, Error, HashKey, ParseError};
+
+static HEADER_MAGIC: &str = "# QuickDash manifest v";
+
+/// Newest version of the manifest format this version of the tool reads.
+///
+/// Manifests are written in the oldest version that can hold them: 1, or 2 for
+/// keyed ones, so that older versions refuse those instead of reporting every
+/// file as changed.
+pub const MANIFEST_VERSION: u32 = 2;
+
+/// Parameters a manifest was created with, stored at its top.
+///
+/// # Examples
+///
+/// ```
+/// # use quickdash::{operations::ManifestHeader, Algorithm};
+/// let header = ManifestHeader::new(vec![Algorithm::BLAKE3], Some(2), false, vec![]);
+///
+/// let mut out = Vec::new();
+/// header.write(&mut out).unwrap();
+/// let text = String::from_utf8(out).unwrap();
+///
+/// let lines: Vec<_> = text.lines().collect();
+/// assert_eq!(ManifestHeader::parse(&lines).unwrap(), Some(header));
+/// ```
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ManifestHeader {
+	/// Version of the manifest format.
+	pub version: u32,
+	/// Version of QuickDash that created the manifest.
+	pub tool_version: String,
+	/// Algorithms of the digest columns, in order.
+	pub algorithms: Vec<Algorithm>,
+	/// Max recursion depth. Infinite if None.
+	pub depth: Option<usize>,
+	/// Whether symlinks were recursed down.
+	pub follow_symlinks: bool,
+	/// Patterns of the files/directories that were ignored.
+	pub ignored_files: Vec<String>,
+	/// Patterns of the files that were included, if not all were.
+	pub included_files: Vec<String>,
+	/// Whether the files git ignores were left out.
+	pub respect_gitignore: bool,
+	/// Whether version control metadata directories were left out.
+	pub skip_vcs: bool,
+	/// Predicates the files had to meet, other than on modification times.
+	pub predicates: Vec<FilePredicate>,
+	/// ID of the key the digests were made with, if they're keyed.
+	pub key_id: Option<String>,
+	/// Algorithm and root of the Merkle tree of the digests, if it was
+	/// recorded.
+	pub tree_root: Option<(Algorithm, String)>,
+}
+
+impl ManifestHeader {
+	/// Describe a manifest about to be created by this version of the tool.
+	pub fn new(
+		algorithms: Vec<Algorithm>,
+		depth: Option<usize>,
+		follow_symlinks: bool,
+		ignored_files: Vec<String>,
+	) -> ManifestHeader {
+		let mut algorithms = algorithms;
+		algorithms.sort();
+		algorithms.dedup();
+
+		ManifestHeader {
+			version: 1,
+			tool_version: env!("CARGO_PKG_VERSION").to_string(),
+			algorithms,
+			depth,
+			follow_symlinks,
+			ignored_files,
+			included_files: Vec::new(),
+			respect_gitignore: false,
+			skip_vcs: false,
+			predicates: Vec::new(),
+			key_id: None,
+			tree_root: None,
+		}
+	}
+
+	/// Record the predicates files have to meet, except those on modification
+	/// times: they're usually relative to when the manifest is created.
+	pub fn select(mut self, predicates: &[FilePredicate]) -> ManifestHeader {
+		let timeless = predicates.iter().filter(|predicate| {
+			!matches!(
+				predicate,
+				FilePredicate::NewerThan(_) | FilePredicate::OlderThan(_)
+			)
+		});
+		self.predicates.extend(timeless.cloned());
+		self
+	}
+
+	/// Record that the digests are made with the specified key.
+	pub fn with_key(mut self, key: &HashKey) -> ManifestHeader {
+		self.version = 2;
+		self.key_id = Some(key.id());
+		self
+	}
+
+	/// Check that the digests are made with `key`, or plain if there's none.
+	pub fn check_key(&self, key: Option<&HashKey>) -> Result<(), Error> {
+		match (&self.key_id, key) {
+			(None, None) => Ok(()),
+			(Some(id), Some(key)) if *id == key.id() => Ok(()),
+			(Some(id), Some(key)) => Err(Error::Key(format!(
+				"the digests were made with key ID {}, not {}",
+				id,
+				key.id()
+			))),
+			(Some(id), None) => Err(Error::Key(format!(
+				"the digests were made with key ID {}, which is needed to verify them",
+				id
+			))),
+			(None, Some(_)) => Err(Error::Key("the digests aren't keyed".to_string())),
+		}
+	}
+
+	/// The filter of the files the manifest was created with.
+	pub fn path_filter(&self) -> Result<PathFilter, Error> {
+		Ok(PathFilter::new(&self.ignored_files, &self.included_files)?
+			.respect_gitignore(self.respect_gitignore)
+			.skip_vcs(self.skip_vcs)
+			.select(self.predicates.clone()))
+	}
+
+	/// Whether the specified line starts a manifest header.
+	pub fn is_header_start(line: &str) -> bool {
+		line.starts_with(HEADER_MAGIC)
+	}
+
+	/// Write the header lines.
+	pub fn write<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+		let algorithms: Vec<_> = self.algorithms.iter().map(|a| format!("{:?}", a)).collect();
+
+		writeln!(out, "{}{}", HEADER_MAGIC, self.version)?;
+		writeln!(out, "# tool-version: {}", self.tool_version)?;
+		writeln!(out, "# algorithms: {}", algorithms.join(" "))?;
+		match self.depth {
+			Some(depth) => writeln!(out, "# depth: {}", depth)?,
+			None => writeln!(out, "# depth: infinite")?,
+		}
+		writeln!(out, "# follow-symlinks: {}", self.follow_symlinks)?;
+		for ignored in &self.ignored_files {
+			writeln!(out, "# ignore: {}", ignored)?;
+		}
+		for included in &self.included_files {
+			writeln!(out, "# include: {}", included)?;
+		}
+		if self.respect_gitignore {
+			writeln!(out, "# respect-gitignore: true")?;
+		}
+		if self.skip_vcs {
+			writeln!(out, "# skip-vcs: true")?;
+		}
+		for predicate in &self.predicates {
+			match predicate {
+				FilePredicate::MinSize(size) => writeln!(out, "# min-size: {}", size)?,
+				FilePredicate::MaxSize(size) => writeln!(out, "# max-size: {}", size)?,
+				FilePredicate::Owner(uid) => writeln!(out, "# owner: {}", uid)?,
+				FilePredicate::Group(gid) => writeln!(out, "# group: {}", gid)?,
+				FilePredicate::Permissions(bits) => writeln!(out, "# perm: {:o}", bits)?,
+				FilePredicate::Extensions(extensions) => {
+					writeln!(out, "# extensions: {}", extensions.join(","))?
+				}
+				FilePredicate::NewerThan(_) | FilePredicate::OlderThan(_) => {}
+			}
+		}
+		if let Some(key_id) = &self.key_id {
+			writeln!(out, "# key-id: {}", key_id)?;
+		}
+		if let Some((algorithm, root)) = &self.tree_root {
+			writeln!(out, "# tree-root: {:?} {}", algorithm, root)?;
+		}
+		Ok(())
+	}
+
+	/// Parse a header out of the leading `#` lines of a manifest.
+	///
+	/// Returns `None` if the lines don't start with a header. Unknown keys are
+	/// skipped so that newer manifests stay readable, but a newer format
+	/// version is rejected.
+	pub fn parse(lines: &[&str]) -> Result<Option<ManifestHeader>, ParseError> {
+		let version = match lines.first().and_then(|l| l.strip_prefix(HEADER_MAGIC)) {
+			Some(version) => version
+				.trim()
+				.parse()
+				.map_err(|_| ParseError::new(1, lines[0], "invalid manifest version"))?,
+			None => return Ok(None),
+		};
+		if version > MANIFEST_VERSION {
+			return Err(ParseError::new(
+				1,
+				lines[0],
+				format!(
+					"manifest version {} is newer than the supported {}",
+					version, MANIFEST_VERSION
+				),
+			));
+		}
+
+		let mut header = ManifestHeader {
+			version,
+			tool_version: String::new(),
+			algorithms: Vec::new(),
+			depth: None,
+			follow_symlinks: false,
+			ignored_files: Vec::new(),
+			included_files: Vec::new(),
+			respect_gitignore: false,
+			skip_vcs: false,
+			predicates: Vec::new(),
+			key_id: None,
+			tree_root: None,
+		};
+		for (i, line) in lines.iter().enumerate().skip(1) {
+			let error = |reason: &str| ParseError::new(i + 1, line, reason);
+			let (key, value) = line
+				.strip_prefix("# ")
+				.and_then(|l| l.split_once(": "))
+				.ok_or_else(|| error("expected a `# key: value` header line"))?;
+
+			match key {
+				"tool-version" => header.tool_version = value.to_string(),
+				"algorithms" => {
+					header.algorithms = value
+						.split_whitespace()
+						.map(Algorithm::from_str)
+						.collect::<Result<_, _>>()
+						.map_err(|_| error("unknown algorithm"))?
+				}
+				"depth" if value == "infinite" => header.depth = None,
+				"depth" => header.depth = Some(value.parse().map_err(|_| error("invalid depth"))?),
+				"follow-symlinks" => {
+					header.follow_symlinks = value
+						.parse()
+						.map_err(|_| error("expected `true` or `false`"))?
+				}
+				"ignore" => header.ignored_files.push(value.to_string()),
+				"include" => header.included_files.push(value.to_string()),
+				"respect-gitignore" => {
+					header.respect_gitignore = value
+						.parse()
+						.map_err(|_| error("expected `true` or `false`"))?
+				}
+				"skip-vcs" => {
+					header.skip_vcs = value
+						.parse()
+						.map_err(|_| error("expected `true` or `false`"))?
+				}
+				"min-size" | "max-size" => {
+					let size = value.parse().map_err(|_| error("invalid number"))?;
+					header.predicates.push(match key {
+						"min-size" => FilePredicate::MinSize(size),
+						_ => FilePredicate::MaxSize(size),
+					})
+				}
+				"owner" | "group" => {
+					let id = value.parse().map_err(|_| error("invalid number"))?;
+					header.predicates.push(match key {
+						"owner" => FilePredicate::Owner(id),
+						_ => FilePredicate::Group(id),
+					})
+				}
+				"perm" => header.predicates.push(FilePredicate::Permissions(
+					u32::from_str_radix(value, 8).map_err(|_| error("invalid octal mode"))?,
+				)),
+				"extensions" => header.predicates.push(FilePredicate::Extensions(
+					value.split(',').map(str::to_string).collect(),
+				)),
+				"key-id" => header.key_id = Some(value.to_string()),
+				"tree-root" => {
+					header.tree_root = value
+						.split_once(' ')
+						.and_then(|(algorithm, root)| {
+							Some((Algorithm::from_str(algorithm).ok()?, root.to_string()))
+						})
+						.map(Some)
+						.ok_or_else(|| error("expected `ALGORITHM ROOT`"))?
+				}
+				_ => {}
+			}
+		}
+
+		if header.algorithms.is_empty() {
+			return Err(ParseError::unnumbered("", "the header lists no algorithms"));
+		}
+		Ok(Some(header))
+	}
+}
@@ -0,0 +1,261 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs,
+	io::{self, BufRead},
+	iter,
+	path::{Component, Path},
+};
+
+use walkdir::{DirEntry, WalkDir};
+
+use super::{
+	filter::{Filtered, IgnoreFiles},
+	placeholder_digests,
+	walked_file,
+	HashCache,
+	PathFilter,
+	TreeHasher,
+	Walked,
+};
+use crate::{Algorithm, Digests, Error, FileError, HashKey};
+
+/// Read a list of file names, one per line, or separated by NULs if `nul` is
+/// set. Empty names are skipped.
+///
+/// # Examples
+///
+/// ```
+/// # use quickdash::operations::read_file_list;
+/// let names = read_file_list(&b"a\r\nb/c\n\nd\n"[..], false).unwrap();
+/// assert_eq!(names, ["a", "b/c", "d"]);
+///
+/// let names = read_file_list(&b"a\nb\0c\0"[..], true).unwrap();
+/// assert_eq!(names, ["a\nb", "c"]);
+/// ```
+pub fn read_file_list<R: BufRead>(reader: R, nul: bool) -> io::Result<Vec<String>> {
+	let separator = if nul { b'\0' } else { b'\n' };
+	reader
+		.split(separator)
+		.filter_map(|name| {
+			let mut name = match name {
+				Ok(name) => name,
+				Err(e) => return Some(Err(e)),
+			};
+			if !nul && name.ends_with(b"\r") {
+				name.pop();
+			}
+			match name.is_empty() {
+				true => None,
+				false => Some(
+					String::from_utf8(name)
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+				),
+			}
+		})
+		.collect()
+}
+
+/// Like `create_hashes()`, but hash exactly the specified files of the tree at
+/// `path` instead of walking it.
+///
+/// Names are relative to `path`, or absolute paths inside it. The listed files
+/// are selected like walking the tree would: those deeper than `depth` or that
+/// `filter` leaves out are skipped, and ignored ones get placeholder digests.
+/// Symlinks are only followed if `follow_symlinks` is set, and never out of
+/// `path`. Files that can't be read, aren't regular files or aren't inside
+/// `path` are left out of the hashes and returned alongside them, sorted by
+/// name.
+#[allow(clippy::too_many_arguments)]
+pub fn create_hashes_from_list(
+	path: &Path,
+	names: &[String],
+	filter: &PathFilter,
+	algos: &[Algorithm],
+	key: Option<&HashKey>,
+	depth: Option<usize>,
+	follow_symlinks: bool,
+	jobs: usize,
+	cache: Option<&mut HashCache>,
+) -> Result<(BTreeMap<String, Digests>, Vec<FileError>), Error> {
+	let hasher = TreeHasher::new(path, algos, key, jobs)?;
+	let mut selector = Selector {
+		path,
+		root: &hasher.cache_root,
+		filter,
+		depth,
+		follow_symlinks,
+		ignore_files: IgnoreFiles::default(),
+		entered: BTreeSet::new(),
+	};
+
+	let mut hashes = BTreeMap::new();
+	let mut errors = Vec::new();
+
+	let mut files = Vec::new();
+	let names: BTreeSet<_> = names.iter().map(|name| normalize(path, name)).collect();
+	for name in names {
+		let walked = match name {
+			Ok(name) => selector.select(name)?,
+			Err(err) => Some(Walked::Unreadable(err)),
+		};
+		match walked {
+			Some(Walked::File(entry)) => files.push(entry),
+			Some(Walked::Ignored(filename)) => {
+				hashes.insert(filename, placeholder_digests(algos));
+			}
+			Some(Walked::Unreadable(err)) => errors.push(err),
+			None => {}
+		}
+	}
+
+	hasher.pb.set_length(files.len() as u64);
+	hasher.pb.set_message("Hashing files...");
+	hasher.hash(&mut files, cache, &mut errors, |filename, digests, _| {
+		hashes.insert(filename, digests);
+		Ok(())
+	})?;
+
+	errors.sort();
+	Ok((hashes, errors))
+}
+
+/// Selects the listed files of a tree the way walking it would.
+struct Selector<'a> {
+	path: &'a Path,
+	/// The canonical `path`, which symlinks mustn't lead out of
+	root: &'a Path,
+	filter: &'a PathFilter,
+	depth: Option<usize>,
+	follow_symlinks: bool,
+	ignore_files: IgnoreFiles,
+	/// The directories whose ignore files are loaded
+	entered: BTreeSet<String>,
+}
+
+impl Selector<'_> {
+	/// What walking the tree would make of the listed file, if it didn't leave
+	/// it out.
+	fn select(&mut self, name: String) -> Result<Option<Walked>, Error> {
+		if self
+			.depth
+			.is_some_and(|depth| name.split('/').count() > depth + 1)
+		{
+			return Ok(None);
+		}
+
+		// Walking only gets to the file through the directories it keeps
+		let dirs = iter::once("").chain(name.match_indices('/').map(|(end, _)| &name[..end]));
+		for dir in dirs {
+			let full = self.path.join(dir);
+			if !dir.is_empty() {
+				let is_symlink =
+					fs::symlink_metadata(&full).is_ok_and(|meta| meta.file_type().is_symlink());
+				if is_symlink && !self.follow_symlinks {
+					return Ok(Some(Walked::Unreadable(unfollowed(name.clone()))));
+				}
+				if self.filter.filter(&self.ignore_files, dir, true) != Filtered::Kept {
+					return Ok(None);
+				}
+			}
+			if self.entered.insert(dir.to_string()) {
+				self.filter
+					.enter(&mut self.ignore_files, self.path, &full, dir.to_string())?;
+			}
+		}
+
+		let entry = match entry(self.path, name.clone(), self.follow_symlinks) {
+			Ok(entry) => entry,
+			Err(err) => return Ok(Some(Walked::Unreadable(err))),
+		};
+		// Symlinks could lead out of the base directory
+		let inside = entry
+			.path()
+			.canonicalize()
+			.is_ok_and(|file| file.starts_with(self.root));
+		if !inside {
+			return Ok(Some(Walked::Unreadable(outside(name))));
+		}
+		Ok(walked_file(self.filter, &self.ignore_files, name, entry))
+	}
+}
+
+/// The name of the listed file relative to `root`, with `/` separators.
+fn normalize(root: &Path, name: &str) -> Result<String, FileError> {
+	let listed = Path::new(name);
+	let relative = match listed.is_absolute() {
+		true => listed
+			.strip_prefix(root)
+			.map(Path::to_path_buf)
+			.ok()
+			.or_else(|| {
+				let root = root.canonicalize().ok()?;
+				listed.strip_prefix(root).map(Path::to_path_buf).ok()
+			}),
+		false => Some(listed.to_path_buf()),
+	};
+
+	// `..` could lead out of the base directory
+	let components: Option<Vec<_>> = relative.and_then(|relative| {
+		relative
+			.components()
+			.filter(|component| *component != Component::CurDir)
+			.map(|component| match component {
+				Component::Normal(component) => Some(component.to_string_lossy().to_string()),
+				_ => None,
+			})
+			.collect()
+	});
+	match components {
+		Some(components) if !components.is_empty() => Ok(components.join("/")),
+		_ => Err(outside(name.to_string())),
+	}
+}
+
+/// The entry of the listed file, which has to be a regular file.
+fn entry(root: &Path, name: String, follow_symlinks: bool) -> Result<DirEntry, FileError> {
+	match WalkDir::new(root.join(&name))
+		.follow_links(follow_symlinks)
+		.into_iter()
+		.next()
+	{
+		Some(Ok(entry)) if entry.file_type().is_file() => Ok(entry),
+		Some(Ok(entry)) if entry.path_is_symlink() && !follow_symlinks => Err(unfollowed(name)),
+		Some(Ok(_)) => Err(FileError::new(
+			name,
+			&io::Error::new(io::ErrorKind::InvalidInput, "not a regular file"),
+		)),
+		Some(Err(e)) => Err(FileError::new(name, &e.into())),
+		None => Err(FileError::new(name, &io::ErrorKind::NotFound.into())),
+	}
+}
+
+/// The error of a listed file outside the base directory.
+fn outside(name: String) -> FileError {
+	FileError::new(
+		name,
+		&io::Error::new(io::ErrorKind::InvalidInput, "not inside the base directory"),
+	)
+}
+
+/// The error of a listed file behind a symlink that isn't followed.
+fn unfollowed(name: String) -> FileError {
+	FileError::new(
+		name,
+		&io::Error::new(io::ErrorKind::InvalidInput, "symlinks aren't followed"),
+	)
+}
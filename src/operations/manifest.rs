@@ -0,0 +1,586 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Reading and writing manifests one entry at a time.
+
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, BinaryHeap},
+	ffi::OsString,
+	fs::{remove_file, File, OpenOptions},
+	io::{self, BufRead, BufReader, BufWriter, Lines, Seek, SeekFrom, Write},
+	mem,
+	path::{Path, PathBuf},
+};
+
+use super::{
+	column_order,
+	formats::{self, Entries, Row, RowReader},
+	placeholder_digests,
+	FileFingerprint,
+	ManifestFormat,
+	ManifestHeader,
+};
+use crate::{utilities::mul_str, Algorithm, Digests, Error, ParseError};
+
+/// How many journal entries `ManifestWriter::finish()` sorts at once.
+const RUN_LEN: usize = 100_000;
+
+/// First line of journals, followed by the algorithms of the digest columns.
+static JOURNAL_MAGIC: &str = "# quickdash journal:";
+/// Digest column of entries undoing the previous ones for the same file.
+const TOMBSTONE: &str = "!";
+
+/// A single manifest line's worth of digests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+	/// 1-based number of the line the entry is on.
+	pub line: usize,
+	pub name: String,
+	pub digests: Digests,
+}
+
+/// Iterates over the entries of a manifest without loading all of it.
+///
+/// Tagged formats may list a file once per algorithm, so the same name can
+/// come up in several entries, each with some of its digests.
+///
+/// Lines that can't be parsed come up as `Error::HashesFileParsingFailure`,
+/// and iterating can go on past them. Other errors end the iteration.
+pub struct ManifestReader<'a> {
+	rows: RowReader<Lines<Box<dyn BufRead + 'a>>>,
+	algos: Vec<Algorithm>,
+	file: PathBuf,
+}
+
+impl<'a> ManifestReader<'a> {
+	/// Open the manifest at `file` and read its header.
+	///
+	/// Untagged digests are assigned to the algorithms listed in the header or,
+	/// for manifests without one, to `algos` the same way `write_hashes()`
+	/// orders them.
+	pub fn open(
+		file: &Path,
+		format: ManifestFormat,
+		algos: &[Algorithm],
+	) -> Result<ManifestReader<'a>, Error> {
+		let reader = BufReader::new(File::open(file).map_err(|e| Error::io(file, e))?);
+		ManifestReader::new(file, Box::new(reader), format, algos)
+	}
+
+	/// Like `open()`, but read the `contents` of the manifest at `file`,
+	/// already in memory.
+	pub fn from_bytes(
+		file: &Path,
+		contents: &'a [u8],
+		format: ManifestFormat,
+		algos: &[Algorithm],
+	) -> Result<ManifestReader<'a>, Error> {
+		ManifestReader::new(file, Box::new(contents), format, algos)
+	}
+
+	fn new(
+		file: &Path,
+		reader: Box<dyn BufRead + 'a>,
+		format: ManifestFormat,
+		algos: &[Algorithm],
+	) -> Result<ManifestReader<'a>, Error> {
+		let rows = RowReader::new(file, format, reader.lines())?;
+		let algos = column_order(rows.header().map_or(algos, |h| &h.algorithms));
+
+		Ok(ManifestReader {
+			rows,
+			algos,
+			file: file.to_path_buf(),
+		})
+	}
+
+	/// The manifest's header, if it has one.
+	pub fn header(&self) -> Option<&ManifestHeader> {
+		self.rows.header()
+	}
+}
+
+impl Iterator for ManifestReader<'_> {
+	type Item = Result<ManifestEntry, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let Row {
+			line,
+			text,
+			name,
+			columns,
+		} = match self.rows.next()? {
+			Ok(row) => row,
+			Err(e) => return Some(Err(e)),
+		};
+
+		let digests = if columns.iter().all(|(tag, _)| tag.is_some()) {
+			columns
+				.into_iter()
+				.filter_map(|(tag, digest)| Some((tag?, digest)))
+				.collect()
+		} else if columns.len() == self.algos.len() {
+			self.algos
+				.iter()
+				.copied()
+				.zip(columns.into_iter().map(|(_, digest)| digest))
+				.collect()
+		} else {
+			let reason = format!(
+				"expected {} digests, found {}",
+				self.algos.len(),
+				columns.len()
+			);
+			return Some(Err(ParseError::new(line, &text, reason)
+				.in_file(&self.file)
+				.into()));
+		};
+
+		Some(Ok(ManifestEntry {
+			line,
+			name,
+			digests,
+		}))
+	}
+}
+
+/// Writes a manifest out of entries coming in any order, without keeping them
+/// in memory.
+///
+/// Entries are appended to a journal next to the manifest as soon as they're
+/// known, so they aren't lost if hashing is interrupted, and the journal of an
+/// interrupted run can be resumed. `finish()` then sorts them, a run of
+/// `RUN_LEN` at a time, and merges the runs into the manifest.
+pub struct ManifestWriter {
+	file: PathBuf,
+	root: PathBuf,
+	format: ManifestFormat,
+	header: ManifestHeader,
+	algos: Vec<Algorithm>,
+	journal_path: PathBuf,
+	journal: BufWriter<File>,
+	/// The files journaled before resuming, that weren't taken yet.
+	resumed: BTreeMap<String, FileFingerprint>,
+}
+
+impl ManifestWriter {
+	/// Start writing the manifest at `file` in the specified format, preceded
+	/// by the specified header if the format has one.
+	///
+	/// `root` is the directory being hashed. The journal is `file` with a
+	/// `.partial` suffix.
+	pub fn create(
+		file: &Path,
+		root: &Path,
+		format: ManifestFormat,
+		header: &ManifestHeader,
+	) -> Result<ManifestWriter, Error> {
+		let journal_path = suffixed(file, ".partial");
+		let journal = File::create(&journal_path).map_err(|e| Error::io(&journal_path, e))?;
+
+		let mut writer = ManifestWriter::new(file, root, format, header, journal_path, journal);
+		writer.start_journal()?;
+		Ok(writer)
+	}
+
+	/// Like `create()`, but keep on with the journal an interrupted run left
+	/// behind, if any.
+	///
+	/// The names and fingerprints of the files in the journal are held in
+	/// memory until they're taken with `take_resumed()`. Those never taken, as
+	/// their files are gone, are dropped from the manifest. An incomplete last
+	/// entry is dropped from the journal.
+	pub fn resume(
+		file: &Path,
+		root: &Path,
+		format: ManifestFormat,
+		header: &ManifestHeader,
+	) -> Result<ManifestWriter, Error> {
+		let journal_path = suffixed(file, ".partial");
+		let journal = match OpenOptions::new()
+			.read(true)
+			.append(true)
+			.open(&journal_path)
+		{
+			Ok(journal) => journal,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				return ManifestWriter::create(file, root, format, header)
+			}
+			Err(e) => return Err(Error::io(&journal_path, e)),
+		};
+
+		let mut writer = ManifestWriter::new(file, root, format, header, journal_path, journal);
+		let len = writer.read_journal()?;
+		let journal = writer.journal.get_mut();
+		journal
+			.set_len(len)
+			.and_then(|_| journal.seek(SeekFrom::End(0)))
+			.map_err(|e| Error::io(&writer.journal_path, e))?;
+		if len == 0 {
+			writer.start_journal()?;
+		}
+		Ok(writer)
+	}
+
+	fn new(
+		file: &Path,
+		root: &Path,
+		format: ManifestFormat,
+		header: &ManifestHeader,
+		journal_path: PathBuf,
+		journal: File,
+	) -> ManifestWriter {
+		ManifestWriter {
+			file: file.to_path_buf(),
+			root: root.to_path_buf(),
+			format,
+			header: header.clone(),
+			algos: column_order(&header.algorithms),
+			journal_path,
+			journal: BufWriter::new(journal),
+			resumed: BTreeMap::new(),
+		}
+	}
+
+	/// The journal entries are appended to until `finish()` is called.
+	pub fn journal_path(&self) -> &Path {
+		&self.journal_path
+	}
+
+	/// Add a file's digests. Digests for algorithms missing from the header
+	/// are dropped, and a file added twice keeps the digests it was added with
+	/// last.
+	pub fn append(&mut self, name: &str, digests: &Digests) -> Result<(), Error> {
+		self.write_journal_line(&journal_line(&self.algos, name, Some(digests), None))
+	}
+
+	/// Like `append()`, also recording the file's fingerprint, so that resumed
+	/// runs can tell whether the file changed since.
+	pub fn append_file(
+		&mut self,
+		name: &str,
+		digests: &Digests,
+		fingerprint: &FileFingerprint,
+	) -> Result<(), Error> {
+		let line = journal_line(&self.algos, name, Some(digests), Some(fingerprint));
+		self.write_journal_line(&line)
+	}
+
+	/// Drop a file's digests added so far, like when it was never added.
+	pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+		self.write_journal_line(&journal_line(&self.algos, name, None, None))
+	}
+
+	/// Take the fingerprint the resumed journal recorded for a file along with
+	/// its digests, if any. If it still matches the file, there's no need to
+	/// hash it again.
+	pub fn take_resumed(&mut self, name: &str) -> Option<FileFingerprint> {
+		self.resumed.remove(name)
+	}
+
+	/// Write the buffered entries through to the journal.
+	pub fn flush(&mut self) -> Result<(), Error> {
+		self.journal
+			.flush()
+			.map_err(|e| Error::io(&self.journal_path, e))
+	}
+
+	/// Sort the journal into the manifest, then remove it.
+	pub fn finish(mut self) -> Result<(), Error> {
+		for name in mem::take(&mut self.resumed).into_keys() {
+			self.remove(&name)?;
+		}
+		self.flush()?;
+		let runs = self.sort_runs()?;
+
+		let result = self.write_manifest(&runs);
+		for run in &runs {
+			let _ = remove_file(run);
+		}
+		result?;
+		remove_file(&self.journal_path).map_err(|e| Error::io(&self.journal_path, e))
+	}
+
+	/// The journal's first line, naming the algorithms of its digest columns
+	/// and the key they're made with, if any.
+	fn journal_magic(&self) -> String {
+		let algos: Vec<_> = self.algos.iter().map(|a| format!("{:?}", a)).collect();
+		match &self.header.key_id {
+			Some(key_id) => format!("{} {} key-id:{}", JOURNAL_MAGIC, algos.join(" "), key_id),
+			None => format!("{} {}", JOURNAL_MAGIC, algos.join(" ")),
+		}
+	}
+
+	/// Write the magic line, then list the manifest itself as ignored, the way
+	/// `write_hashes()` does.
+	fn start_journal(&mut self) -> Result<(), Error> {
+		let magic = self.journal_magic();
+		self.write_journal_line(&magic)?;
+		let placeholder = placeholder_digests(&self.header.algorithms);
+		let name = self.file.to_string_lossy().into_owned();
+		self.append(&name, &placeholder)
+	}
+
+	/// Load the fingerprints of the files in the journal being resumed and
+	/// return the length of its complete lines.
+	fn read_journal(&mut self) -> Result<u64, Error> {
+		let magic = self.journal_magic();
+		let journal_path = self.journal_path.clone();
+		let mut journal = BufReader::new(self.journal.get_ref());
+
+		let mut len = 0;
+		let mut line = String::new();
+		for n in 1.. {
+			line.clear();
+			let read = journal
+				.read_line(&mut line)
+				.map_err(|e| Error::io(&journal_path, e))?;
+			// A line without a newline was cut short
+			if !line.ends_with('\n') {
+				break;
+			}
+			len += read as u64;
+			let line = &line[..line.len() - 1];
+
+			let error = |reason: &str| ParseError::new(n, line, reason).in_file(&journal_path);
+			if n == 1 {
+				if line != magic {
+					let reason = "the journal was made with other algorithms or another key";
+					return Err(error(reason).into());
+				}
+				continue;
+			}
+			let entry = parse_journal_line(&self.algos, line)
+				.ok_or_else(|| error("corrupt journal entry"))?;
+			match entry {
+				(name, Some(_), Some(fingerprint)) => {
+					self.resumed.insert(name, fingerprint);
+				}
+				(name, ..) => {
+					self.resumed.remove(&name);
+				}
+			}
+		}
+		Ok(len)
+	}
+
+	fn write_journal_line(&mut self, line: &str) -> Result<(), Error> {
+		writeln!(self.journal, "{}", line).map_err(|e| Error::io(&self.journal_path, e))
+	}
+
+	/// Split the journal into sorted runs, each in a file of its own.
+	fn sort_runs(&self) -> Result<Vec<PathBuf>, Error> {
+		let journal =
+			File::open(&self.journal_path).map_err(|e| Error::io(&self.journal_path, e))?;
+		let mut lines = (2..).zip(BufReader::new(journal).lines().skip(1));
+
+		let mut runs = Vec::new();
+		loop {
+			let mut run = Vec::with_capacity(RUN_LEN);
+			for (n, line) in lines.by_ref().take(RUN_LEN) {
+				let line = line.map_err(|e| Error::io(&self.journal_path, e))?;
+				let (name, ..) = self.parse_journal_line(Some(n), &line)?;
+				run.push((name, line));
+			}
+			if run.is_empty() {
+				return Ok(runs);
+			}
+			// Stable, so that the last of several entries for a file stays last
+			run.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+			let path = suffixed(&self.journal_path, &format!(".{}", runs.len()));
+			runs.push(path.clone());
+			let write = || -> io::Result<()> {
+				let mut out = BufWriter::new(File::create(&path)?);
+				for (_, line) in &run {
+					writeln!(out, "{}", line)?;
+				}
+				out.flush()
+			};
+			write().map_err(|e| Error::io(&path, e))?;
+		}
+	}
+
+	fn write_manifest(&self, runs: &[PathBuf]) -> Result<(), Error> {
+		let entries = || -> io::Result<Entries<'_>> {
+			let runs = runs
+				.iter()
+				.map(|run| Ok(BufReader::new(File::open(run)?).lines()))
+				.collect::<io::Result<Vec<_>>>()?;
+			Ok(Box::new(MergedRuns::new(self, runs)?))
+		};
+
+		let file = File::create(&self.file).map_err(|e| Error::io(&self.file, e))?;
+		let mut out = BufWriter::new(file);
+		formats::write(self.format, &mut out, &self.root, &self.header, &entries)
+			.and_then(|_| out.flush())
+			.map_err(|e| Error::io(&self.file, e))
+	}
+
+	/// Parse the journal `line`, numbered `n` unless it was read from a run.
+	fn parse_journal_line(&self, n: Option<usize>, line: &str) -> Result<JournalEntry, Error> {
+		parse_journal_line(&self.algos, line).ok_or_else(|| {
+			let reason = "corrupt journal entry";
+			let error = match n {
+				Some(n) => ParseError::new(n, line, reason),
+				None => ParseError::unnumbered(line, reason),
+			};
+			error.in_file(&self.journal_path).into()
+		})
+	}
+}
+
+/// A file's name, digests and fingerprint. Entries without digests undo the
+/// previous ones for the same file.
+type JournalEntry = (String, Option<Digests>, Option<FileFingerprint>);
+
+/// Merges sorted runs into a single sorted sequence of entries, keeping the
+/// last entry for files listed several times.
+struct MergedRuns<'a> {
+	writer: &'a ManifestWriter,
+	runs: Vec<Lines<BufReader<File>>>,
+	/// The first entry left in each run, by name and run.
+	heads: BinaryHeap<Reverse<(String, usize, Option<Digests>)>>,
+}
+
+impl<'a> MergedRuns<'a> {
+	fn new(
+		writer: &'a ManifestWriter,
+		runs: Vec<Lines<BufReader<File>>>,
+	) -> io::Result<MergedRuns<'a>> {
+		let mut merged = MergedRuns {
+			writer,
+			runs,
+			heads: BinaryHeap::new(),
+		};
+		for run in 0..merged.runs.len() {
+			merged.advance(run)?;
+		}
+		Ok(merged)
+	}
+
+	/// Move the next entry of the specified run into `heads`.
+	fn advance(&mut self, run: usize) -> io::Result<()> {
+		if let Some(line) = self.runs[run].next() {
+			let (name, digests, _) = self
+				.writer
+				.parse_journal_line(None, &line?)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			self.heads.push(Reverse((name, run, digests)));
+		}
+		Ok(())
+	}
+}
+
+impl Iterator for MergedRuns<'_> {
+	type Item = io::Result<(String, Digests)>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let Reverse((mut name, mut run, mut digests)) = self.heads.pop()?;
+			loop {
+				if let Err(e) = self.advance(run) {
+					return Some(Err(e));
+				}
+				// Later runs were journaled later
+				match self.heads.peek() {
+					Some(Reverse((next, ..))) if *next == name => {
+						let Reverse(head) = self.heads.pop().unwrap();
+						(name, run, digests) = head;
+					}
+					_ => break,
+				}
+			}
+			if let Some(digests) = digests {
+				return Some(Ok((name, digests)));
+			}
+		}
+	}
+}
+
+/// `DIGEST[ DIGEST]...<tab>FINGERPRINT<tab>name`, the digests in `algos`'
+/// order, the fingerprint as `size:secs.nanos:inode` and the name escaped the
+/// way coreutils does. Missing digests are `-`, as is a missing fingerprint.
+fn journal_line(
+	algos: &[Algorithm],
+	name: &str,
+	digests: Option<&Digests>,
+	fingerprint: Option<&FileFingerprint>,
+) -> String {
+	let columns = match digests {
+		Some(digests) => algos
+			.iter()
+			.map(|algo| {
+				digests
+					.get(algo)
+					.cloned()
+					.unwrap_or_else(|| mul_str("-", algo.hexlen()))
+			})
+			.collect::<Vec<_>>()
+			.join(" "),
+		None => TOMBSTONE.to_string(),
+	};
+	let fingerprint = match fingerprint {
+		Some(fp) => format!(
+			"{}:{}.{:09}:{}",
+			fp.size, fp.mtime_secs, fp.mtime_nanos, fp.inode
+		),
+		None => "-".to_string(),
+	};
+	format!("{}\t{}\t{}", columns, fingerprint, formats::escape(name))
+}
+
+fn parse_journal_line(algos: &[Algorithm], line: &str) -> Option<JournalEntry> {
+	let mut fields = line.splitn(3, '\t');
+	let (columns, fingerprint, name) = (fields.next()?, fields.next()?, fields.next()?);
+
+	let digests = match columns {
+		TOMBSTONE => None,
+		_ => {
+			let digests: Digests = algos
+				.iter()
+				.copied()
+				.zip(columns.split(' ').map(str::to_string))
+				.collect();
+			if digests.len() != algos.len() {
+				return None;
+			}
+			Some(digests)
+		}
+	};
+	let fingerprint = match fingerprint {
+		"-" => None,
+		_ => {
+			let (size, rest) = fingerprint.split_once(':')?;
+			let (mtime, inode) = rest.split_once(':')?;
+			let (secs, nanos) = mtime.split_once('.')?;
+			Some(FileFingerprint {
+				size: size.parse().ok()?,
+				mtime_secs: secs.parse().ok()?,
+				mtime_nanos: nanos.parse().ok()?,
+				inode: inode.parse().ok()?,
+			})
+		}
+	};
+	Some((formats::unescape(name)?, digests, fingerprint))
+}
+
+/// `path` with `suffix` appended to its file name.
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path.as_os_str());
+	name.push(suffix);
+	PathBuf::from(name)
+}
@@ -20,103 +20,518 @@
 //!
 //! Then use `write_hashes()` to save it to disk, or `read_hashes()` to get the
 //! saved hashes, them with `compare_hashes()` and print them with
-//! `write_hash_comparison_results()`.
+//! `write_hash_comparison_results()`, or `write_report()` for machine-readable
+//! output.
+//!
+//! For trees too large to hold in memory, `create_hashes_streaming()` hands the
+//! hashes to a `ManifestWriter` as they're made, and a `ManifestReader` goes
+//! through a manifest one entry at a time.
 
+mod cache;
 mod compare;
+mod detect;
+mod dupes;
+mod filter;
+mod formats;
+mod header;
+mod list;
+mod manifest;
+mod report;
+mod signature;
+mod tree;
 mod write;
 
 use std::{
 	collections::BTreeMap,
 	fs::File,
-	io::{BufRead, BufReader, Write},
-	path::Path,
+	io::{self, BufRead, BufReader, BufWriter, Write},
+	iter,
+	path::{Path, PathBuf},
+	sync::mpsc,
+	time::{Duration, Instant},
 };
 
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
-use once_cell::sync::Lazy;
 use rayon::{
 	iter::{IntoParallelRefIterator, ParallelIterator},
+	ThreadPool,
 	ThreadPoolBuilder,
 };
-use regex::Regex;
-use tabwriter::TabWriter;
 use walkdir::{DirEntry, WalkDir};
 
-pub use self::{compare::*, write::*};
+use self::filter::{Filtered, IgnoreFiles};
+pub use self::{
+	cache::*,
+	compare::*,
+	detect::*,
+	dupes::*,
+	filter::{FilePredicate, PathFilter, Patterns, IGNORE_FILE, VCS_DIRS},
+	formats::ManifestFormat,
+	header::*,
+	list::*,
+	manifest::*,
+	report::*,
+	signature::*,
+	tree::*,
+	write::*,
+};
 use crate::{
-	hash_file,
+	hash_file_multi_keyed,
 	utilities::{mul_str, relative_name},
 	Algorithm,
+	Digests,
 	Error,
+	FileError,
+	HashKey,
+	ParseError,
 };
 
 static SPINNER_STRINGS: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
 
-/// Create subpath->hash mappings for a given path using a given algorithm up to
-/// a given depth.
+/// How many files `create_hashes_streaming()` hashes at once.
+const BATCH_LEN: usize = 10_000;
+
+/// How often `create_hashes_streaming()` flushes the journal.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Create subpath->hashes mappings for a given path using the given algorithms
+/// up to a given depth.
+///
+/// Every file is read once, no matter how many algorithms are selected.
+///
+/// If a `key` is specified, the digests are keyed, and all algorithms have to
+/// be `Algorithm::is_keyable()`.
+///
+/// If a `cache` is specified, files whose size, mtime and inode didn't change
+/// since they were cached aren't rehashed, and the cache is updated with the
+/// digests of all hashed files. Keyed digests aren't cached.
+///
+/// Files and directories that can't be read, or whose names aren't valid
+/// UTF-8, are left out of the hashes and returned alongside them, sorted by
+/// name. Failing to read `path` itself is an error.
+#[allow(clippy::too_many_arguments)]
 pub fn create_hashes(
 	path: &Path,
-	ignored_files: Vec<String>,
-	algo: Algorithm,
+	filter: &PathFilter,
+	algos: &[Algorithm],
+	key: Option<&HashKey>,
 	depth: Option<usize>,
 	follow_symlinks: bool,
 	jobs: usize,
-) -> BTreeMap<String, String> {
+	mut cache: Option<&mut HashCache>,
+) -> Result<(BTreeMap<String, Digests>, Vec<FileError>), Error> {
+	let hasher = TreeHasher::new(path, algos, key, jobs)?;
+
+	let mut hashes = BTreeMap::new();
+	let mut errors = Vec::new();
+
+	hasher.pb.set_message("Finding files to hash...");
+	let mut files = Vec::new();
+	for walked in walk(path, filter, depth, follow_symlinks, None) {
+		match walked? {
+			Walked::File(entry) => files.push(entry),
+			Walked::Ignored(filename) => {
+				hashes.insert(filename, placeholder_digests(algos));
+			}
+			Walked::Unreadable(err) => errors.push(err),
+		}
+	}
+
+	hasher.pb.reset();
+	hasher.pb.set_length(files.len() as u64);
+	hasher.pb.set_message("Hashing files...");
+	hasher.hash(
+		&mut files,
+		cache.as_deref_mut(),
+		&mut errors,
+		|filename, digests, _| {
+			hashes.insert(filename, digests);
+			Ok(())
+		},
+	)?;
+	hasher.walked(cache);
+
+	errors.sort();
+	Ok((hashes, errors))
+}
+
+/// Like `create_hashes()`, but hash the files walking the tree, a batch at a
+/// time, and append their digests to `writer` as soon as they're known rather
+/// than returning them, so that memory use doesn't grow with the tree. The
+/// writer's journal is flushed every second, so that an interrupted run loses
+/// little work.
+///
+/// The walk is configured by `header`, only the files that meet `predicates`
+/// are hashed, the writer's journal is skipped, and files are appended in no
+/// particular order. The writer is left for the caller to finish. `key` has to
+/// be the one the header records, if any.
+///
+/// If the writer resumed a journal, the files it holds digests for aren't
+/// hashed again unless their size or modification time changed since.
+pub fn create_hashes_streaming(
+	path: &Path,
+	header: &ManifestHeader,
+	predicates: &[FilePredicate],
+	key: Option<&HashKey>,
+	jobs: usize,
+	mut cache: Option<&mut HashCache>,
+	writer: &mut ManifestWriter,
+) -> Result<Vec<FileError>, Error> {
+	header.check_key(key)?;
+	let algos = &header.algorithms;
+	let filter = header.path_filter()?.select(predicates.to_vec());
+	let hasher = TreeHasher::new(path, algos, key, jobs)?;
+	let journal = writer.journal_path().to_path_buf();
+
+	let mut errors = Vec::new();
+	let mut files = Vec::with_capacity(BATCH_LEN);
+	let mut flushed = Instant::now();
+
+	hasher.pb.set_length(0);
+	hasher.pb.set_message("Hashing files...");
+	let mut walked = walk(
+		path,
+		&filter,
+		header.depth,
+		header.follow_symlinks,
+		Some(&journal),
+	);
+	loop {
+		let done = match walked.next().transpose()? {
+			Some(Walked::File(entry)) => {
+				let filename = relative_name(path, entry.path());
+				let resumed = writer.take_resumed(&filename);
+				let journaled = resumed.is_some_and(|was| {
+					entry.metadata().is_ok_and(|meta| {
+						let is = FileFingerprint::from_metadata(&meta);
+						(was.size, was.mtime_secs, was.mtime_nanos)
+							== (is.size, is.mtime_secs, is.mtime_nanos)
+					})
+				});
+				if !journaled {
+					// The stale digests mustn't outlive a failure to hash the file again
+					if resumed.is_some() {
+						writer.remove(&filename)?;
+					}
+					files.push(entry);
+				}
+				false
+			}
+			Some(Walked::Ignored(filename)) => {
+				writer.append(&filename, &placeholder_digests(algos))?;
+				false
+			}
+			Some(Walked::Unreadable(err)) => {
+				errors.push(err);
+				false
+			}
+			None => true,
+		};
+
+		if files.len() == BATCH_LEN || (done && !files.is_empty()) {
+			hasher.pb.inc_length(files.len() as u64);
+			hasher.hash(
+				&mut files,
+				cache.as_deref_mut(),
+				&mut errors,
+				|filename, digests, fingerprint| {
+					match fingerprint {
+						Some(fp) => writer.append_file(&filename, &digests, &fp)?,
+						None => writer.append(&filename, &digests)?,
+					}
+					if flushed.elapsed() >= FLUSH_INTERVAL {
+						writer.flush()?;
+						flushed = Instant::now();
+					}
+					Ok(())
+				},
+			)?;
+			files.clear();
+		}
+		if done {
+			break;
+		}
+	}
+	writer.flush()?;
+	hasher.walked(cache);
+
+	errors.sort();
+	Ok(errors)
+}
+
+/// What walking a tree turned up.
+enum Walked {
+	File(DirEntry),
+	/// The relative name of an ignored file.
+	Ignored(String),
+	Unreadable(FileError),
+}
+
+/// Walk the tree at `path`, skipping the directories `filter` ignores, the
+/// files it doesn't include and the `skip` file. Failing to read `path` itself
+/// is an error.
+fn walk<'a>(
+	path: &'a Path,
+	filter: &'a PathFilter,
+	depth: Option<usize>,
+	follow_symlinks: bool,
+	skip: Option<&'a Path>,
+) -> impl Iterator<Item = Result<Walked, Error>> + 'a {
 	let mut walkdir = WalkDir::new(path).follow_links(follow_symlinks);
 	if let Some(depth) = depth {
 		walkdir = walkdir.max_depth(depth + 1);
 	}
+	let skip = skip.map(|skip| (skip.file_name(), skip.canonicalize().ok()));
 
-	let pb_style = ProgressStyle::default_bar()
-		.template("{prefix:.bold.dim} {spinner} {wide_bar} {pos:>7}/{len:7} ETA: {eta} - {msg}")
-		.tick_strings(&SPINNER_STRINGS);
+	let mut entries = walkdir.into_iter();
+	let mut ignore_files = IgnoreFiles::default();
+	iter::from_fn(move || loop {
+		let entry = match entries.next()? {
+			Ok(entry) => entry,
+			Err(e) if e.depth() == 0 => {
+				return Some(Err(Error::Walk {
+					path: path.to_path_buf(),
+					source: e,
+				}))
+			}
+			Err(e) => {
+				let filename = e
+					.path()
+					.map_or_else(String::new, |p| relative_name(path, p));
+				return Some(Ok(Walked::Unreadable(FileError::new(filename, &e.into()))));
+			}
+		};
+		let filename = relative_name(path, entry.path());
 
-	let pb = ProgressBar::new_spinner();
-	pb.set_style(pb_style);
+		if entry.file_type().is_dir() {
+			let filtered = filter.filter(&ignore_files, &filename, true);
+			if entry.depth() > 0 && filtered != Filtered::Kept {
+				entries.skip_current_dir();
+				continue;
+			}
+			if entry.depth() > 0 && entry.file_name().to_str().is_none() {
+				entries.skip_current_dir();
+				return Some(Ok(Walked::Unreadable(non_utf8(filename))));
+			}
+			if let Err(e) = filter.enter(&mut ignore_files, path, entry.path(), filename) {
+				return Some(Err(e));
+			}
+			continue;
+		}
+		if !entry.file_type().is_file() {
+			continue;
+		}
+		match &skip {
+			Some((name, canonical))
+				if *name == Some(entry.file_name())
+					&& entry.path().canonicalize().ok() == *canonical =>
+			{
+				continue;
+			}
+			_ => {}
+		}
+		match walked_file(filter, &ignore_files, filename, entry) {
+			Some(walked) => return Some(Ok(walked)),
+			None => continue,
+		}
+	})
+}
 
-	ThreadPoolBuilder::new()
-		.num_threads(jobs)
-		.build_global()
-		.unwrap();
+/// What walking makes of a regular file of the tree, unless `filter` leaves it
+/// out.
+fn walked_file(
+	filter: &PathFilter,
+	ignore_files: &IgnoreFiles,
+	filename: String,
+	entry: DirEntry,
+) -> Option<Walked> {
+	if entry.file_name().to_str().is_none() {
+		return Some(Walked::Unreadable(non_utf8(filename)));
+	}
+	if !filter.includes(&filename) {
+		return None;
+	}
+	if filter.has_predicates() {
+		match entry.metadata() {
+			Ok(meta) if filter.selects(&filename, &meta) => {}
+			Ok(_) => return None,
+			Err(e) => return Some(Walked::Unreadable(FileError::new(filename, &e.into()))),
+		}
+	}
+	match filter.filter(ignore_files, &filename, false) {
+		Filtered::Kept => Some(Walked::File(entry)),
+		Filtered::Ignored => Some(Walked::Ignored(filename)),
+		Filtered::Excluded => None,
+	}
+}
 
-	let mut hashes = BTreeMap::new();
+/// The error of a file whose name can't be written to manifests as it is.
+fn non_utf8(filename: String) -> FileError {
+	FileError::new(
+		filename,
+		&io::Error::new(io::ErrorKind::InvalidData, "the name isn't valid UTF-8"),
+	)
+}
+
+/// Hashes the files of a tree on a thread pool of its own.
+struct TreeHasher<'a> {
+	path: &'a Path,
+	/// Cache keys need to stay the same no matter how the path was specified
+	cache_root: PathBuf,
+	algos: &'a [Algorithm],
+	key: Option<&'a HashKey>,
+	pool: ThreadPool,
+	pb: ProgressBar,
+}
 
-	pb.enable_steady_tick(80);
-	pb.set_message("Finding files to hash...");
-	let mut files: Vec<DirEntry> = walkdir
-		.into_iter()
-		.filter_entry(|e: &walkdir::DirEntry| {
-			let filename = relative_name(path, e.path());
-			match (ignored_files.contains(&filename), e.file_type().is_file()) {
-				(true, true) => {
-					hashes.insert(mul_str("-", algo.hexlen()), filename);
-					false
+impl<'a> TreeHasher<'a> {
+	fn new(
+		path: &'a Path,
+		algos: &'a [Algorithm],
+		key: Option<&'a HashKey>,
+		jobs: usize,
+	) -> Result<TreeHasher<'a>, Error> {
+		if let Some(algo) = algos
+			.iter()
+			.find(|algo| key.is_some() && !algo.is_keyable())
+		{
+			return Err(Error::Key(format!(
+				"{} can't make keyed digests",
+				algo.tag()
+			)));
+		}
+		let pool = ThreadPoolBuilder::new()
+			.num_threads(jobs)
+			.build()
+			.map_err(Error::ThreadPool)?;
+
+		let pb_style = ProgressStyle::default_bar()
+			.template("{prefix:.bold.dim} {spinner} {wide_bar} {pos:>7}/{len:7} ETA: {eta} - {msg}")
+			.tick_strings(&SPINNER_STRINGS);
+		let pb = ProgressBar::new_spinner();
+		pb.set_style(pb_style);
+		pb.enable_steady_tick(80);
+
+		Ok(TreeHasher {
+			path,
+			cache_root: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+			algos,
+			key,
+			pool,
+			pb,
+		})
+	}
+
+	/// Hash the specified files and hand each one's name, digests and
+	/// fingerprint from before it was hashed to `sink` as soon as it's known,
+	/// reusing and updating the digests in `cache` unless they're keyed. Files
+	/// that can't be read are added to `errors`.
+	fn hash(
+		&self,
+		files: &mut [DirEntry],
+		cache: Option<&mut HashCache>,
+		errors: &mut Vec<FileError>,
+		mut sink: impl FnMut(String, Digests, Option<FileFingerprint>) -> Result<(), Error>,
+	) -> Result<(), Error> {
+		optimize_file_order(files);
+
+		// The cache can't tell keyed digests from plain ones
+		let cache = cache.filter(|_| self.key.is_none());
+		let cached = cache.as_deref();
+		// The cache is only updated once the workers are done reading it
+		let mut hashed = Vec::new();
+		let (tx, rx) = mpsc::channel();
+		let result = self.pool.in_place_scope(|scope| {
+			let files = &*files;
+			scope.spawn(move |_| {
+				// Sending fails once the sink failed, which stops the workers
+				let _ = files
+					.par_iter()
+					.progress_with(self.pb.clone())
+					.try_for_each_with(tx, |tx, e| tx.send(self.hash_file(cached, e)));
+			});
+
+			for res in rx {
+				let (filename, value, fingerprint) = match res {
+					Ok(res) => res,
+					Err(err) => {
+						errors.push(err);
+						continue;
+					}
+				};
+				if let (Some(_), Some(fp)) = (cached, fingerprint) {
+					hashed.push((filename.clone(), fp, value.clone()));
 				}
-				(true, false) => false,
-				_ => true,
+				sink(filename, value, fingerprint)?;
 			}
-		})
-		.flatten()
-		.filter(|e| e.file_type().is_file())
-		.collect();
-
-	optimize_file_order(&mut files);
-
-	pb.reset();
-	pb.set_length(files.len() as u64);
-	pb.set_message("Hashing files...");
-
-	let mut result: BTreeMap<String, String> = files
-		.par_iter()
-		.progress_with(pb)
-		.map(|e| {
-			let value = hash_file(algo, e.path());
-			let filename = relative_name(path, e.path());
-			(filename, value)
-		})
-		.collect();
-	hashes.append(&mut result);
-	hashes
+			Ok(())
+		});
+
+		if let Some(cache) = cache {
+			for (filename, fp, value) in hashed {
+				let cache_key = self
+					.cache_root
+					.join(&filename)
+					.to_string_lossy()
+					.to_string();
+				for (algo, digest) in value {
+					cache.insert(algo, cache_key.clone(), fp, digest);
+				}
+			}
+		}
+		result
+	}
+
+	/// Record in `cache` that the whole tree was hashed, unless the digests are
+	/// keyed and weren't cached.
+	fn walked(&self, cache: Option<&mut HashCache>) {
+		if let (Some(cache), None) = (cache, self.key) {
+			cache.walked(&self.cache_root);
+		}
+	}
+
+	/// Hash a single file, taking the digests `cache` holds for it as they
+	/// are.
+	fn hash_file(
+		&self,
+		cache: Option<&HashCache>,
+		entry: &DirEntry,
+	) -> Result<(String, Digests, Option<FileFingerprint>), FileError> {
+		let filename = relative_name(self.path, entry.path());
+		let fingerprint = entry
+			.metadata()
+			.ok()
+			.map(|meta| FileFingerprint::from_metadata(&meta));
+
+		let mut value = Digests::new();
+		if let (Some(cache), Some(fp)) = (cache, &fingerprint) {
+			let cache_key = self
+				.cache_root
+				.join(&filename)
+				.to_string_lossy()
+				.to_string();
+			for &algo in self.algos {
+				if let Some(digest) = cache.get(algo, &cache_key, fp) {
+					value.insert(algo, digest.to_string());
+				}
+			}
+		}
+
+		let missing: Vec<_> = self
+			.algos
+			.iter()
+			.filter(|algo| !value.contains_key(algo))
+			.copied()
+			.collect();
+		if !missing.is_empty() {
+			match hash_file_multi_keyed(&missing, self.key, entry.path()) {
+				Ok(mut digests) => value.append(&mut digests),
+				Err(err) => return Err(FileError::new(filename, &err)),
+			}
+		}
+
+		Ok((filename, value, fingerprint))
+	}
 }
 
 #[cfg(target_os = "linux")]
@@ -132,54 +547,158 @@ fn optimize_file_order(dirs: &mut [DirEntry]) {
 #[cfg(not(target_os = "linux"))]
 fn optimize_file_order(_dirs: &mut [DirEntry]) {}
 
-/// Serialise the specified hashes to the specified output file.
-pub fn write_hashes(out_file: &Path, algo: Algorithm, mut hashes: BTreeMap<String, String>) -> i32 {
-	let file = File::create(&out_file).unwrap();
-	let mut out = TabWriter::new(file);
+/// Placeholder digests marking a file as ignored.
+fn placeholder_digests(algos: &[Algorithm]) -> Digests {
+	algos
+		.iter()
+		.map(|algo| (*algo, mul_str("-", algo.hexlen())))
+		.collect()
+}
+
+/// Sort and deduplicate the specified algorithms into the order their digests
+/// are stored in.
+fn column_order(algos: &[Algorithm]) -> Vec<Algorithm> {
+	let mut algos = algos.to_vec();
+	algos.sort();
+	algos.dedup();
+	algos
+}
+
+/// Serialise the specified hashes to the specified output file in the
+/// specified format, preceded by the specified header if the format has one.
+///
+/// `root` is the directory that was hashed. Use a `ManifestWriter` to write
+/// hashes as they're made instead.
+pub fn write_hashes(
+	out_file: &Path,
+	root: &Path,
+	format: ManifestFormat,
+	header: &ManifestHeader,
+	mut hashes: BTreeMap<String, Digests>,
+) -> Result<(), Error> {
+	let file = File::create(out_file).map_err(|e| Error::io(out_file, e))?;
+	let mut out = BufWriter::new(file);
 
 	hashes.insert(
 		out_file.to_string_lossy().to_string(),
-		mul_str("-", algo.hexlen()),
+		placeholder_digests(&header.algorithms),
 	);
-	for (fname, hash) in hashes {
-		writeln!(&mut out, "{}  {}", hash, fname).unwrap();
-	}
-
-	out.flush().expect("Failed to flush output file");
-	0
+	let entries = || -> io::Result<formats::Entries<'_>> {
+		Ok(Box::new(hashes.iter().map(|(fname, digests)| {
+			Ok((fname.clone(), digests.clone()))
+		})))
+	};
+	formats::write(format, &mut out, root, header, &entries)
+		.and_then(|_| out.flush())
+		.map_err(|e| Error::io(out_file, e))
 }
 
 /// Read uppercased hashes with `write_hashes()` from the specified path or fail
-/// with line numbers not matching pattern.
-pub fn read_hashes(file: &Path) -> Result<BTreeMap<String, String>, Error> {
-	let mut hashes = BTreeMap::new();
+/// with the first line not matching the format.
+///
+/// Digests tagged with their algorithm in the manifest keep it. The others are
+/// assigned to the algorithms listed in the manifest's header or, for
+/// manifests without one, to `algos` the same way `write_hashes()` orders them.
+/// Use a `ManifestReader` to go through the entries without loading them all.
+pub fn read_hashes(
+	file: &Path,
+	format: ManifestFormat,
+	algos: &[Algorithm],
+) -> Result<(Option<ManifestHeader>, BTreeMap<String, Digests>), Error> {
+	strict(read_hashes_lenient(file, format, algos)?)
+}
+
+/// Like `read_hashes()`, but parse the `contents` of the manifest at `file`,
+/// already in memory.
+pub fn parse_hashes(
+	file: &Path,
+	contents: &[u8],
+	format: ManifestFormat,
+	algos: &[Algorithm],
+) -> Result<(Option<ManifestHeader>, BTreeMap<String, Digests>), Error> {
+	let reader = ManifestReader::from_bytes(file, contents, format, algos)?;
+	strict(collect_hashes(reader)?)
+}
 
-	let in_file = BufReader::new(File::open(&file).unwrap());
-	for line in in_file.lines().map(Result::unwrap) {
-		try_contains(&line, &mut hashes)?;
+/// Like `read_hashes()`, but skip the entries that can't be parsed and return
+/// why, in order, alongside the hashes. A malformed header still fails.
+pub fn read_hashes_lenient(
+	file: &Path,
+	format: ManifestFormat,
+	algos: &[Algorithm],
+) -> Result<LenientHashes, Error> {
+	collect_hashes(ManifestReader::open(file, format, algos)?)
+}
+
+/// Like `read_hashes_lenient()`, but parse the `contents` of the manifest at
+/// `file`, already in memory.
+pub fn parse_hashes_lenient(
+	file: &Path,
+	contents: &[u8],
+	format: ManifestFormat,
+	algos: &[Algorithm],
+) -> Result<LenientHashes, Error> {
+	collect_hashes(ManifestReader::from_bytes(file, contents, format, algos)?)
+}
+
+fn collect_hashes(mut reader: ManifestReader<'_>) -> Result<LenientHashes, Error> {
+	let mut hashes = BTreeMap::new();
+	let mut rejected = Vec::new();
+	for entry in &mut reader {
+		match entry {
+			Ok(entry) => {
+				// Tagged formats may list a file once per algorithm
+				let digests: &mut Digests = hashes.entry(entry.name).or_default();
+				digests.extend(entry.digests);
+			}
+			Err(Error::HashesFileParsingFailure(e)) => rejected.push(e),
+			Err(e) => return Err(e),
+		}
 	}
 
-	Ok(hashes)
+	Ok((reader.header().cloned(), hashes, rejected))
 }
 
-fn try_contains(line: &str, hashes: &mut BTreeMap<String, String>) -> Result<(), Error> {
-	if line.is_empty() {
-		return Err(Error::HashesFileParsingFailure);
+/// Fail with the first of the rejected lines, if any.
+fn strict(
+	(header, hashes, mut rejected): LenientHashes,
+) -> Result<(Option<ManifestHeader>, BTreeMap<String, Digests>), Error> {
+	match rejected.is_empty() {
+		true => Ok((header, hashes)),
+		false => Err(rejected.remove(0).into()),
 	}
+}
 
-	static LINE_RGX1: Lazy<Regex> =
-		Lazy::new(|| Regex::new(r"(?i)^([[:xdigit:]-]+)\s{2,}(.+?)$").unwrap());
+/// The header, hashes and rejected lines of a manifest read with
+/// `read_hashes_lenient()`.
+pub type LenientHashes = (
+	Option<ManifestHeader>,
+	BTreeMap<String, Digests>,
+	Vec<ParseError>,
+);
 
-	static LINE_RGX2: Lazy<Regex> =
-		Lazy::new(|| Regex::new(r"(?i)^(.+?)\t{0,}\s{1,}([[:xdigit:]-]+)$").unwrap());
+/// Read the header and the entries of a manifest, skipping the lines that
+/// can't be parsed.
+fn read_rows(
+	file: &Path,
+	contents: Option<&[u8]>,
+	format: ManifestFormat,
+) -> Result<(Option<ManifestHeader>, Vec<formats::Row>), Error> {
+	let reader: Box<dyn BufRead> = match contents {
+		Some(contents) => Box::new(contents),
+		None => Box::new(BufReader::new(
+			File::open(file).map_err(|e| Error::io(file, e))?,
+		)),
+	};
+	let mut reader = formats::RowReader::new(file, format, reader.lines())?;
 
-	if let Some(captures) = LINE_RGX1.captures(line) {
-		hashes.insert(captures[2].to_string(), captures[1].to_uppercase());
-		return Ok(());
-	}
-	if let Some(captures) = LINE_RGX2.captures(line) {
-		hashes.insert(captures[1].to_string(), captures[2].to_uppercase());
-		return Ok(());
+	let mut rows = Vec::new();
+	for row in &mut reader {
+		match row {
+			Ok(row) => rows.push(row),
+			Err(Error::HashesFileParsingFailure(_)) => {}
+			Err(e) => return Err(e),
+		}
 	}
-	Err(Error::HashesFileParsingFailure)
+	Ok((reader.header().cloned(), rows))
 }
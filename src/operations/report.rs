@@ -0,0 +1,620 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::BTreeMap,
+	fmt::Write as _,
+	io::{self, Write},
+};
+
+use clap::ArgEnum;
+
+use super::{
+	audit_outcome,
+	comparison_outcome,
+	digests_string,
+	write_audit_results,
+	write_hash_comparison_results,
+	AuditSummary,
+	CompareError,
+	CompareFileResult,
+	CompareResult,
+	Signer,
+};
+use crate::{Algorithm, Digests, Error};
+
+/// How verification results are reported.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, ArgEnum)]
+pub enum ReportFormat {
+	/// Human-readable prose, wrapped at 80 columns.
+	Text,
+	/// A single JSON document holding every result and a summary.
+	Json,
+	/// One JSON object per line: the results, then the summary.
+	Ndjson,
+	/// JUnit XML, each file being a test case.
+	Junit,
+	/// Test Anything Protocol, each file being a test point.
+	Tap,
+}
+
+/// What became of a file during verification.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportStatus {
+	Matches,
+	Differs,
+	Added,
+	Removed,
+	Ignored,
+	Moved,
+	Copied,
+	Unreadable,
+}
+
+impl ReportStatus {
+	/// The status' name in reports.
+	pub fn name(&self) -> &'static str {
+		match *self {
+			ReportStatus::Matches => "matches",
+			ReportStatus::Differs => "differs",
+			ReportStatus::Added => "added",
+			ReportStatus::Removed => "removed",
+			ReportStatus::Ignored => "ignored",
+			ReportStatus::Moved => "moved",
+			ReportStatus::Copied => "copied",
+			ReportStatus::Unreadable => "unreadable",
+		}
+	}
+
+	/// Whether files with the status keep verification from passing, the way
+	/// they count toward the exit value: added, removed, moved and copied
+	/// files only do when auditing.
+	pub fn fails(&self, audit: bool) -> bool {
+		match *self {
+			ReportStatus::Matches | ReportStatus::Ignored => false,
+			ReportStatus::Differs | ReportStatus::Unreadable => true,
+			ReportStatus::Added
+			| ReportStatus::Removed
+			| ReportStatus::Moved
+			| ReportStatus::Copied => audit,
+		}
+	}
+}
+
+/// A single `CompareResult` or `CompareFileResult`, flattened.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ReportEntry {
+	pub path: String,
+	pub status: ReportStatus,
+	/// The loaded digest(s), for the files that were loaded.
+	pub expected: Option<String>,
+	/// The current digest(s), for the files that were hashed.
+	pub actual: Option<String>,
+	/// The loaded name of a moved or copied file.
+	pub from: Option<String>,
+	/// Why an unreadable file couldn't be read.
+	pub error: Option<String>,
+}
+
+impl ReportEntry {
+	/// A one-line description of the entry's status, the way the text report
+	/// puts it.
+	pub fn message(&self) -> String {
+		match (self.status, &self.from) {
+			(ReportStatus::Matches, _) => "File matches".to_string(),
+			(ReportStatus::Differs, _) => "File doesn't match".to_string(),
+			(ReportStatus::Added, _) => "File added".to_string(),
+			(ReportStatus::Removed, _) => "File removed".to_string(),
+			(ReportStatus::Ignored, _) => "File ignored".to_string(),
+			(ReportStatus::Moved, Some(from)) => format!("File moved from \"{}\"", from),
+			(ReportStatus::Moved, None) => "File moved".to_string(),
+			(ReportStatus::Copied, Some(from)) => format!("File copied from \"{}\"", from),
+			(ReportStatus::Copied, None) => "File copied".to_string(),
+			(ReportStatus::Unreadable, _) => match &self.error {
+				Some(error) => format!("File couldn't be read: {}", error),
+				None => "File couldn't be read".to_string(),
+			},
+		}
+	}
+
+	fn new(path: &str, status: ReportStatus) -> ReportEntry {
+		ReportEntry {
+			path: path.to_string(),
+			status,
+			expected: None,
+			actual: None,
+			from: None,
+			error: None,
+		}
+	}
+}
+
+/// The digests verification results were made of, reported along with them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDigests {
+	/// The current digests, by file name.
+	pub current: BTreeMap<String, Digests>,
+	/// The loaded digests, by file name.
+	pub loaded: BTreeMap<String, Digests>,
+}
+
+impl ReportDigests {
+	/// Fill in the expected and actual digests of the entries that don't have
+	/// them yet, made with the specified algorithms. Ignored files' are
+	/// placeholders, so they're left out.
+	fn fill(&self, entries: &mut [ReportEntry], algorithms: &[Algorithm]) {
+		let digests = |hashes: &BTreeMap<String, Digests>, name: &str| {
+			let digests: Vec<_> = hashes
+				.get(name)?
+				.iter()
+				.filter(|(algo, _)| algorithms.contains(algo))
+				.map(|(algo, digest)| (*algo, digest.as_str()))
+				.collect();
+			(!digests.is_empty()).then(|| digests_string(digests))
+		};
+		for entry in entries
+			.iter_mut()
+			.filter(|entry| entry.status != ReportStatus::Ignored)
+		{
+			if entry.expected.is_none() {
+				let loaded = entry.from.as_deref().unwrap_or(&entry.path);
+				entry.expected = digests(&self.loaded, loaded);
+			}
+			if entry.actual.is_none() {
+				entry.actual = digests(&self.current, &entry.path);
+			}
+		}
+	}
+}
+
+/// Flatten comparison results into report entries, in the order the text
+/// report lists them.
+pub fn report_entries(
+	results: &[CompareResult],
+	file_results: &[CompareFileResult],
+) -> Vec<ReportEntry> {
+	let mut results = results.to_vec();
+	let mut file_results = file_results.to_vec();
+	results.sort();
+	file_results.sort();
+
+	let entries = results.iter().map(|res| match res {
+		CompareResult::FileAdded(file) => ReportEntry::new(file, ReportStatus::Added),
+		CompareResult::FileRemoved(file) => ReportEntry::new(file, ReportStatus::Removed),
+		CompareResult::FileIgnored(file) => ReportEntry::new(file, ReportStatus::Ignored),
+		CompareResult::FileMoved { from, to } => ReportEntry {
+			from: Some(from.clone()),
+			..ReportEntry::new(to, ReportStatus::Moved)
+		},
+		CompareResult::FileCopied { from, to } => ReportEntry {
+			from: Some(from.clone()),
+			..ReportEntry::new(to, ReportStatus::Copied)
+		},
+		CompareResult::FileUnreadable(err) => ReportEntry {
+			error: Some(err.message.clone()),
+			..ReportEntry::new(&err.file, ReportStatus::Unreadable)
+		},
+	});
+	let file_entries = file_results.iter().map(|res| match res {
+		CompareFileResult::FileMatches(file) => ReportEntry::new(file, ReportStatus::Matches),
+		CompareFileResult::FileDiffers {
+			file,
+			was_hash,
+			new_hash,
+		} => ReportEntry {
+			expected: Some(was_hash.clone()),
+			actual: Some(new_hash.clone()),
+			..ReportEntry::new(file, ReportStatus::Differs)
+		},
+	});
+	entries.chain(file_entries).collect()
+}
+
+/// Write verification results in the specified format and return the same
+/// outcome the text report would.
+///
+/// `algorithms` are the ones the files were compared with, `digests` the ones
+/// the results were made of, `audit` whether the results come from
+/// `audit_hashes()` rather than `compare_hashes()`, and `signer` who signed the
+/// manifest, if its signature was checked.
+pub fn write_report<W: Write>(
+	output: &mut W,
+	format: ReportFormat,
+	algorithms: &[Algorithm],
+	results: Result<(Vec<CompareResult>, Vec<CompareFileResult>), CompareError>,
+	digests: &ReportDigests,
+	audit: bool,
+	signer: Option<&Signer>,
+) -> Result<(), Error> {
+	if let (ReportFormat::Text, Some(signer)) = (format, signer) {
+		writeln!(output, "Manifest signed by {}", signer)
+			.and_then(|_| writeln!(output))
+			.map_err(Error::Output)?;
+	}
+	let (results, file_results) = match (format, results) {
+		(ReportFormat::Text, Ok(results)) if audit => return write_audit_results(output, results),
+		(ReportFormat::Text, results) => {
+			return write_hash_comparison_results(output, &mut io::stderr(), results)
+		}
+		(_, Ok(results)) => results,
+		(
+			_,
+			Err(CompareError::HashLengthDiffers {
+				previous_len,
+				current_len,
+			}),
+		) => {
+			write_length_error(output, format, previous_len, current_len)
+				.and_then(|_| output.flush())
+				.map_err(Error::Output)?;
+			return Err(Error::HashLengthDiffers);
+		}
+	};
+
+	let mut entries = report_entries(&results, &file_results);
+	digests.fill(&mut entries, algorithms);
+	let result = if audit {
+		audit_outcome(&AuditSummary::new(&results, &file_results))
+	} else {
+		comparison_outcome(&results, &file_results)
+	};
+	let exit_code = result.as_ref().map_or_else(Error::exit_value, |_| 0);
+
+	let algorithm = algorithms
+		.iter()
+		.map(|algo| format!("{:?}", algo))
+		.collect::<Vec<_>>()
+		.join(",");
+	match format {
+		ReportFormat::Text => unreachable!(),
+		ReportFormat::Json => write_json(output, &entries, &algorithm, signer, exit_code, false),
+		ReportFormat::Ndjson => write_json(output, &entries, &algorithm, signer, exit_code, true),
+		ReportFormat::Junit => write_junit(output, &entries, &algorithm, signer, audit),
+		ReportFormat::Tap => write_tap(output, &entries, signer, audit),
+	}
+	.and_then(|_| output.flush())
+	.map_err(Error::Output)?;
+
+	result
+}
+
+fn write_length_error<W: Write>(
+	output: &mut W,
+	format: ReportFormat,
+	previous_len: usize,
+	current_len: usize,
+) -> io::Result<()> {
+	let message = format!(
+		"Hash lengths do not match; selected: {}, loaded: {}",
+		current_len, previous_len
+	);
+	match format {
+		ReportFormat::Text => unreachable!(),
+		ReportFormat::Json | ReportFormat::Ndjson => {
+			let error = format!(
+				"{{\"type\": \"error\", \"error\": \"hash_length_differs\", \"selected_len\": {}, \
+				 \"loaded_len\": {}}}",
+				current_len, previous_len
+			);
+			match format {
+				ReportFormat::Ndjson => writeln!(output, "{}", error),
+				_ => writeln!(output, "{{\n  \"error\": {}\n}}", error),
+			}
+		}
+		ReportFormat::Junit => {
+			writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+			writeln!(output, "<testsuites>")?;
+			writeln!(
+				output,
+				r#"  <testsuite name="quickdash" tests="1" failures="0" errors="1" skipped="0">"#
+			)?;
+			writeln!(
+				output,
+				r#"    <testcase classname="quickdash" name="manifest">"#
+			)?;
+			writeln!(
+				output,
+				r#"      <error message="{}" type="HashLengthDiffers"/>"#,
+				xml_escape(&message)
+			)?;
+			writeln!(output, "    </testcase>")?;
+			writeln!(output, "  </testsuite>")?;
+			writeln!(output, "</testsuites>")
+		}
+		ReportFormat::Tap => {
+			writeln!(output, "TAP version 13")?;
+			writeln!(output, "Bail out! {}", message)
+		}
+	}
+}
+
+fn write_json<W: Write>(
+	output: &mut W,
+	entries: &[ReportEntry],
+	algorithm: &str,
+	signer: Option<&Signer>,
+	exit_code: i32,
+	ndjson: bool,
+) -> io::Result<()> {
+	let records: Vec<_> = entries
+		.iter()
+		.map(|entry| json_entry(entry, algorithm))
+		.collect();
+	let summary = json_summary(entries, signer, exit_code);
+
+	if ndjson {
+		for record in &records {
+			writeln!(output, "{}", record)?;
+		}
+		writeln!(output, "{}", summary)
+	} else {
+		writeln!(output, "{{")?;
+		writeln!(output, "  \"results\": [")?;
+		for (i, record) in records.iter().enumerate() {
+			let comma = if i + 1 < records.len() { "," } else { "" };
+			writeln!(output, "    {}{}", record, comma)?;
+		}
+		writeln!(output, "  ],")?;
+		writeln!(output, "  \"summary\": {}", summary)?;
+		writeln!(output, "}}")
+	}
+}
+
+/// Every file is a test case: matching ones pass, differing ones fail,
+/// ignored ones are skipped, and unreadable ones are errors. Added, removed,
+/// moved and copied ones are errors when auditing, and otherwise pass with
+/// what became of them in `system-out`, as they don't count toward the exit
+/// value.
+fn write_junit<W: Write>(
+	output: &mut W,
+	entries: &[ReportEntry],
+	algorithm: &str,
+	signer: Option<&Signer>,
+	audit: bool,
+) -> io::Result<()> {
+	let failures = count(entries, ReportStatus::Differs);
+	let skipped = count(entries, ReportStatus::Ignored);
+	let errors = entries
+		.iter()
+		.filter(|entry| entry.status.fails(audit))
+		.count()
+		- failures;
+
+	writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+	writeln!(output, "<testsuites>")?;
+	writeln!(
+		output,
+		r#"  <testsuite name="quickdash" tests="{}" failures="{}" errors="{}" skipped="{}">"#,
+		entries.len(),
+		failures,
+		errors,
+		skipped
+	)?;
+	writeln!(output, "    <properties>")?;
+	writeln!(
+		output,
+		r#"      <property name="algorithm" value="{}"/>"#,
+		xml_escape(algorithm)
+	)?;
+	if let Some(signer) = signer {
+		writeln!(
+			output,
+			r#"      <property name="signer" value="{}"/>"#,
+			xml_escape(&signer.key_id)
+		)?;
+		writeln!(
+			output,
+			r#"      <property name="trusted-comment" value="{}"/>"#,
+			xml_escape(&signer.trusted_comment)
+		)?;
+	}
+	writeln!(output, "    </properties>")?;
+
+	for entry in entries {
+		let testcase = format!(
+			r#"    <testcase classname="quickdash" name="{}""#,
+			xml_escape(&entry.path)
+		);
+		let (tag, kind) = match entry.status {
+			ReportStatus::Matches => {
+				writeln!(output, "{}/>", testcase)?;
+				continue;
+			}
+			status if status != ReportStatus::Ignored && !status.fails(audit) => {
+				writeln!(output, "{}>", testcase)?;
+				writeln!(
+					output,
+					"      <system-out>{}</system-out>",
+					xml_escape(&entry.message())
+				)?;
+				writeln!(output, "    </testcase>")?;
+				continue;
+			}
+			ReportStatus::Differs => ("failure", "FileDiffers"),
+			ReportStatus::Ignored => ("skipped", "FileIgnored"),
+			ReportStatus::Added => ("error", "FileAdded"),
+			ReportStatus::Removed => ("error", "FileRemoved"),
+			ReportStatus::Moved => ("error", "FileMoved"),
+			ReportStatus::Copied => ("error", "FileCopied"),
+			ReportStatus::Unreadable => ("error", "FileUnreadable"),
+		};
+
+		writeln!(output, "{}>", testcase)?;
+		match (&entry.expected, &entry.actual) {
+			(Some(was), Some(is)) => writeln!(
+				output,
+				r#"      <{0} message="{1}" type="{2}">Was: {3}&#10;Is : {4}</{0}>"#,
+				tag,
+				xml_escape(&entry.message()),
+				kind,
+				xml_escape(was),
+				xml_escape(is)
+			)?,
+			_ => writeln!(
+				output,
+				r#"      <{} message="{}" type="{}"/>"#,
+				tag,
+				xml_escape(&entry.message()),
+				kind
+			)?,
+		}
+		writeln!(output, "    </testcase>")?;
+	}
+
+	writeln!(output, "  </testsuite>")?;
+	writeln!(output, "</testsuites>")
+}
+
+/// Every file is a test point, with the same outcomes as in JUnit reports.
+/// Ignored files are skipped with the `SKIP` directive, and what became of
+/// other ones that aren't matching is in a YAML diagnostic block.
+fn write_tap<W: Write>(
+	output: &mut W,
+	entries: &[ReportEntry],
+	signer: Option<&Signer>,
+	audit: bool,
+) -> io::Result<()> {
+	writeln!(output, "TAP version 13")?;
+	writeln!(output, "1..{}", entries.len())?;
+	if let Some(signer) = signer {
+		writeln!(output, "# Manifest signed by {}", signer)?;
+	}
+	for (i, entry) in entries.iter().enumerate() {
+		// `#` starts a directive in the description
+		let name = entry.path.replace('\\', "\\\\").replace('#', "\\#");
+		match entry.status {
+			ReportStatus::Matches => writeln!(output, "ok {} - {}", i + 1, name)?,
+			ReportStatus::Ignored => {
+				writeln!(output, "ok {} - {} # SKIP {}", i + 1, name, entry.message())?
+			}
+			status => {
+				match status.fails(audit) {
+					true => writeln!(output, "not ok {} - {}", i + 1, name)?,
+					false => writeln!(output, "ok {} - {}", i + 1, name)?,
+				}
+				writeln!(output, "  ---")?;
+				writeln!(output, "  message: {}", json_string(&entry.message()))?;
+				writeln!(output, "  status: {}", entry.status.name())?;
+				if let Some(from) = &entry.from {
+					writeln!(output, "  from: {}", json_string(from))?;
+				}
+				if let (Some(was), Some(is)) = (&entry.expected, &entry.actual) {
+					writeln!(output, "  was: {}", json_string(was))?;
+					writeln!(output, "  is: {}", json_string(is))?;
+				}
+				writeln!(output, "  ...")?;
+			}
+		}
+	}
+	Ok(())
+}
+
+fn count(entries: &[ReportEntry], status: ReportStatus) -> usize {
+	entries.iter().filter(|e| e.status == status).count()
+}
+
+fn json_entry(entry: &ReportEntry, algorithm: &str) -> String {
+	format!(
+		"{{\"type\": \"result\", \"path\": {}, \"status\": \"{}\", \"expected\": {}, \"actual\": \
+		 {}, \"algorithm\": {}, \"from\": {}, \"error\": {}}}",
+		json_string(&entry.path),
+		entry.status.name(),
+		json_option(&entry.expected),
+		json_option(&entry.actual),
+		json_string(algorithm),
+		json_option(&entry.from),
+		json_option(&entry.error),
+	)
+}
+
+fn json_summary(entries: &[ReportEntry], signer: Option<&Signer>, exit_code: i32) -> String {
+	let mut summary = format!("{{\"type\": \"summary\", \"files\": {}", entries.len());
+	for status in [
+		ReportStatus::Matches,
+		ReportStatus::Differs,
+		ReportStatus::Added,
+		ReportStatus::Removed,
+		ReportStatus::Ignored,
+		ReportStatus::Moved,
+		ReportStatus::Copied,
+		ReportStatus::Unreadable,
+	] {
+		write!(
+			summary,
+			", \"{}\": {}",
+			status.name(),
+			count(entries, status)
+		)
+		.unwrap();
+	}
+	match signer {
+		Some(signer) => write!(
+			summary,
+			", \"signer\": {{\"key_id\": {}, \"trusted_comment\": {}}}",
+			json_string(&signer.key_id),
+			json_string(&signer.trusted_comment)
+		)
+		.unwrap(),
+		None => summary.push_str(", \"signer\": null"),
+	}
+	write!(summary, ", \"exit_code\": {}}}", exit_code).unwrap();
+	summary
+}
+
+fn json_option(value: &Option<String>) -> String {
+	value
+		.as_deref()
+		.map_or_else(|| "null".to_string(), json_string)
+}
+
+/// Escape a string for use in XML text and attribute values.
+fn xml_escape(value: &str) -> String {
+	let mut out = String::with_capacity(value.len());
+	for c in value.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&apos;"),
+			'\n' => out.push_str("&#10;"),
+			'\r' => out.push_str("&#13;"),
+			'\t' => out.push_str("&#9;"),
+			// Not representable in XML 1.0
+			c if (c as u32) < 0x20 => out.push('\u{FFFD}'),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+/// Quote and escape a string as a JSON string literal.
+fn json_string(value: &str) -> String {
+	let mut out = String::with_capacity(value.len() + 2);
+	out.push('"');
+	for c in value.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
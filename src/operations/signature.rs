@@ -0,0 +1,339 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Detached manifest signatures, compatible with minisign.
+//!
+//! Keys are read from minisign's key files, and signatures are written to and
+//! read from `.minisig` files, so `minisign -Vm manifest.hash -p key.pub` can
+//! check a signature QuickDash made and the other way round.
+
+use std::{
+	fmt,
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use blake2::{digest::consts::U32, Blake2b, Blake2b512, Digest};
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
+
+use crate::Error;
+
+/// Ed25519 over the message itself, as minisign did before 0.10.
+const LEGACY_ALG: &[u8; 2] = b"Ed";
+/// Ed25519 over the message's BLAKE2b-512 digest.
+const PREHASHED_ALG: &[u8; 2] = b"ED";
+const CHECKSUM_ALG: &[u8; 2] = b"B2";
+const SCRYPT_ALG: &[u8; 2] = b"Sc";
+const NO_KDF_ALG: &[u8; 2] = b"\0\0";
+
+const UNTRUSTED_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+
+/// Length of the key number, checksum and secret key minisign encrypts.
+const KEYNUM_SK_LEN: usize = 8 + 64 + 32;
+const SECRET_KEY_LEN: usize = 2 + 2 + 2 + 32 + 8 + 8 + KEYNUM_SK_LEN;
+
+/// An Ed25519 key to sign manifests with.
+pub struct SecretKey {
+	id: [u8; 8],
+	key: SigningKey,
+}
+
+impl SecretKey {
+	/// Read a minisign secret key, asking for its password on the terminal if
+	/// it's encrypted.
+	pub fn open(path: &Path) -> Result<SecretKey, Error> {
+		SecretKey::open_with(path, || {
+			rpassword::prompt_password(format!("Password for {}: ", path.display()))
+		})
+	}
+
+	/// Like `open()`, but get the password of an encrypted key from `password`.
+	pub fn open_with(
+		path: &Path,
+		password: impl FnOnce() -> io::Result<String>,
+	) -> Result<SecretKey, Error> {
+		let bytes = read_key_file(path, "secret key")?;
+		let error = |reason: &str| Error::signature(path, reason);
+		if bytes.len() != SECRET_KEY_LEN
+			|| &bytes[..2] != LEGACY_ALG
+			|| &bytes[4..6] != CHECKSUM_ALG
+		{
+			return Err(error("not a minisign secret key"));
+		}
+
+		let (kdf_alg, salt) = (&bytes[2..4], &bytes[6..38]);
+		let opslimit = u64::from_le_bytes(bytes[38..46].try_into().unwrap());
+		let memlimit = u64::from_le_bytes(bytes[46..54].try_into().unwrap());
+		let mut keynum_sk = bytes[54..].to_vec();
+		let encrypted = match kdf_alg.try_into().unwrap() {
+			NO_KDF_ALG => false,
+			SCRYPT_ALG => {
+				let password = password().map_err(|e| Error::io(path, e))?;
+				let stream = scrypt_stream(password.as_bytes(), salt, opslimit, memlimit)
+					.ok_or_else(|| error("unsupported key derivation parameters"))?;
+				keynum_sk
+					.iter_mut()
+					.zip(stream)
+					.for_each(|(byte, key)| *byte ^= key);
+				true
+			}
+			_ => return Err(error("unsupported key derivation algorithm")),
+		};
+
+		let (id, rest) = keynum_sk.split_at(8);
+		let (secret, checksum) = rest.split_at(64);
+		let expected = Blake2b::<U32>::new()
+			.chain_update(LEGACY_ALG)
+			.chain_update(id)
+			.chain_update(secret)
+			.finalize();
+		if expected.as_slice() != checksum {
+			return Err(error(match encrypted {
+				true => "wrong password",
+				false => "corrupt secret key",
+			}));
+		}
+		let key = SigningKey::from_keypair_bytes(secret.try_into().unwrap())
+			.map_err(|_| error("corrupt secret key"))?;
+		Ok(SecretKey {
+			id: id.try_into().unwrap(),
+			key,
+		})
+	}
+
+	/// The key the signatures made with this one are checked with.
+	pub fn public_key(&self) -> PublicKey {
+		PublicKey {
+			id: self.id,
+			key: self.key.verifying_key(),
+		}
+	}
+}
+
+/// An Ed25519 key to check manifest signatures with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+	id: [u8; 8],
+	key: VerifyingKey,
+}
+
+impl PublicKey {
+	/// Read a minisign public key from the file `key` names, or take `key` as
+	/// the key itself, as printed by `minisign -G` (`RW...`).
+	pub fn open(key: &str) -> Result<PublicKey, Error> {
+		let path = Path::new(key);
+		match path.is_file() {
+			true => PublicKey::parse(&read_key_file(path, "public key")?)
+				.ok_or_else(|| Error::signature(path, "not a minisign public key")),
+			false => base64::decode(key)
+				.ok()
+				.and_then(|bytes| PublicKey::parse(&bytes))
+				.ok_or_else(|| Error::signature(path, "neither a public key nor a file")),
+		}
+	}
+
+	/// The key's ID, the way minisign shows it.
+	pub fn key_id(&self) -> String {
+		key_id(&self.id)
+	}
+
+	fn parse(bytes: &[u8]) -> Option<PublicKey> {
+		match bytes.len() == 42 && &bytes[..2] == LEGACY_ALG {
+			true => Some(PublicKey {
+				id: bytes[2..10].try_into().unwrap(),
+				key: VerifyingKey::from_bytes(bytes[10..].try_into().unwrap()).ok()?,
+			}),
+			false => None,
+		}
+	}
+}
+
+/// Whose signature a manifest carries.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Signer {
+	/// ID of the key the manifest was signed with, the way minisign shows it.
+	pub key_id: String,
+	/// The signed comment stored with the signature, by default the time of
+	/// signing and the manifest's name.
+	pub trusted_comment: String,
+}
+
+impl fmt::Display for Signer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "key ID {} ({})", self.key_id, self.trusted_comment)
+	}
+}
+
+/// Where the signature of `file` is kept by default: `file.minisig`.
+pub fn signature_path(file: &Path) -> PathBuf {
+	let mut path = file.as_os_str().to_owned();
+	path.push(".minisig");
+	PathBuf::from(path)
+}
+
+/// Sign `file` with `key`, writing the signature to `signature`, and return
+/// the signer as `verify_manifest()` will.
+pub fn sign_manifest(file: &Path, signature: &Path, key: &SecretKey) -> Result<Signer, Error> {
+	let digest = prehash(file)?;
+	let file_sig = key.key.sign(&digest).to_bytes();
+	let timestamp = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs());
+	let name = file.file_name().unwrap_or_default().to_string_lossy();
+	let trusted_comment = format!("timestamp:{}\tfile:{}\thashed", timestamp, name);
+	let global_sig = key
+		.key
+		.sign(&[&file_sig[..], trusted_comment.as_bytes()].concat())
+		.to_bytes();
+
+	let mut bytes = PREHASHED_ALG.to_vec();
+	bytes.extend(key.id);
+	bytes.extend(file_sig);
+	let contents = format!(
+		"{}signature from quickdash secret key\n{}\n{}{}\n{}\n",
+		UNTRUSTED_PREFIX,
+		base64::encode(bytes),
+		TRUSTED_PREFIX,
+		trusted_comment,
+		base64::encode(global_sig)
+	);
+	fs::write(signature, contents).map_err(|e| Error::io(signature, e))?;
+	Ok(Signer {
+		key_id: key_id(&key.id),
+		trusted_comment,
+	})
+}
+
+/// Check the minisign `signature` of `file`, whose `contents` were read
+/// beforehand, against `key`, and return who signed it.
+///
+/// Only `contents` are checked, so that they can be parsed without the file
+/// changing in between.
+pub fn verify_manifest(
+	file: &Path,
+	contents: &[u8],
+	signature: &Path,
+	key: &PublicKey,
+) -> Result<Signer, Error> {
+	let error = |reason: String| Error::signature(signature, reason);
+	let text = fs::read_to_string(signature).map_err(|e| Error::io(signature, e))?;
+	let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+	let (sig, trusted_comment, global_sig) = match (
+		lines
+			.next()
+			.filter(|line| line.starts_with(UNTRUSTED_PREFIX)),
+		lines.next().and_then(|line| base64::decode(line).ok()),
+		lines
+			.next()
+			.and_then(|line| line.strip_prefix(TRUSTED_PREFIX)),
+		lines.next().and_then(|line| base64::decode(line).ok()),
+	) {
+		(Some(_), Some(sig), Some(comment), Some(global_sig))
+			if sig.len() == 74 && global_sig.len() == 64 =>
+		{
+			(sig, comment, global_sig)
+		}
+		_ => return Err(error("not a minisign signature".to_string())),
+	};
+
+	let (alg, id, file_sig) = (&sig[..2], &sig[2..10], &sig[10..]);
+	if id != key.id {
+		return Err(error(format!(
+			"signed with key ID {}, not {}",
+			key_id(id.try_into().unwrap()),
+			key.key_id()
+		)));
+	}
+	let prehashed;
+	let message = match alg.try_into().unwrap() {
+		PREHASHED_ALG => {
+			prehashed = Blake2b512::digest(contents);
+			&prehashed[..]
+		}
+		LEGACY_ALG => contents,
+		_ => return Err(error("unsupported signature algorithm".to_string())),
+	};
+	let valid = |message: &[u8], sig: &[u8]| {
+		key.key
+			.verify_strict(message, &Signature::from_slice(sig).unwrap())
+			.is_ok()
+	};
+	if !valid(message, file_sig) {
+		return Err(error(format!(
+			"the signature doesn't match {}",
+			file.display()
+		)));
+	}
+	if !valid(
+		&[file_sig, trusted_comment.as_bytes()].concat(),
+		&global_sig,
+	) {
+		return Err(error("the trusted comment was tampered with".to_string()));
+	}
+	Ok(Signer {
+		key_id: key.key_id(),
+		trusted_comment: trusted_comment.to_string(),
+	})
+}
+
+/// The base64 line of a minisign key file, decoded.
+fn read_key_file(path: &Path, what: &str) -> Result<Vec<u8>, Error> {
+	let contents = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+	contents
+		.lines()
+		.map(str::trim)
+		.find(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_PREFIX))
+		.and_then(|line| base64::decode(line).ok())
+		.ok_or_else(|| Error::signature(path, format!("not a minisign {}", what)))
+}
+
+fn prehash(file: &Path) -> Result<[u8; 64], Error> {
+	let mut hasher = Blake2b512::new();
+	File::open(file)
+		.and_then(|mut f| io::copy(&mut f, &mut hasher))
+		.map_err(|e| Error::io(file, e))?;
+	Ok(hasher.finalize().into())
+}
+
+/// minisign shows the little-endian key number in hex.
+fn key_id(id: &[u8; 8]) -> String {
+	format!("{:016X}", u64::from_le_bytes(*id))
+}
+
+/// The scrypt output minisign XORs encrypted secret keys with, its
+/// operations and memory limits turned into parameters the way libsodium
+/// does.
+fn scrypt_stream(password: &[u8], salt: &[u8], opslimit: u64, memlimit: u64) -> Option<Vec<u8>> {
+	let opslimit = opslimit.max(32768);
+	let r = 8u64;
+	let log_n = |max_n: u64| (1..63).find(|n| 1u64 << n > max_n / 2).unwrap_or(63);
+	let (log_n, p) = if opslimit < memlimit / 32 {
+		(log_n(opslimit / (r * 4)), 1)
+	} else {
+		let log_n = log_n(memlimit / (r * 128));
+		let max_rp = ((opslimit / 4) >> log_n).min(0x3fff_ffff);
+		(log_n, max_rp / r)
+	};
+
+	// The length is only used for password hashes, which can't be that long
+	let len = scrypt::Params::RECOMMENDED_LEN;
+	let params = scrypt::Params::new(log_n, r as u32, p as u32, len).ok()?;
+	let mut stream = vec![0; KEYNUM_SK_LEN];
+	scrypt::scrypt(password, salt, &params, &mut stream).ok()?;
+	Some(stream)
+}
@@ -0,0 +1,215 @@
+/* Copyright [2021] [Cerda]
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
+};
+
+use crate::{hash_reader, Algorithm, Digests};
+
+/// A Merkle tree over the digests of a directory tree, fingerprinting the
+/// names and contents of every directory with a digest of its own.
+///
+/// A directory's digest is made with the tree's algorithm over the sorted list
+/// of its entries' names and digests, so the root changes with any file in the
+/// tree. Ignored files and empty directories are left out.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use quickdash::{operations::{MerkleTree, TreeDifference}, Algorithm};
+/// let digests = |digest: &str| BTreeMap::from([(Algorithm::CRC32, digest.to_string())]);
+/// let was = BTreeMap::from([
+/// 	("a/b".to_string(), digests("00000001")),
+/// 	("c".to_string(), digests("00000002")),
+/// ]);
+/// let mut is = was.clone();
+/// is.insert("a/b".to_string(), digests("00000003"));
+///
+/// let was = MerkleTree::new(Algorithm::CRC32, &was);
+/// let is = MerkleTree::new(Algorithm::CRC32, &is);
+/// assert_ne!(was.root(), is.root());
+/// assert_eq!(
+/// 	is.compare(&was),
+/// 	[
+/// 		TreeDifference::SubtreeDiffers("a/".to_string()),
+/// 		TreeDifference::FileDiffers("a/b".to_string())
+/// 	]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+	algorithm: Algorithm,
+	root: Directory,
+}
+
+/// Where two Merkle trees differ, as found by `MerkleTree::compare()`.
+/// Directory paths end with a `/`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TreeDifference {
+	/// The digests of a directory differ, and it was descended into.
+	SubtreeDiffers(String),
+	FileDiffers(String),
+	/// A file or a whole directory only in the current tree.
+	Added(String),
+	/// A file or a whole directory only in the loaded tree.
+	Removed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+	File(String),
+	Directory(Directory),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Directory {
+	digest: String,
+	children: BTreeMap<String, Node>,
+}
+
+impl MerkleTree {
+	/// Build the tree of the specified subpath->hashes mappings, using their
+	/// digests made with `algorithm`. Files without one are left out.
+	pub fn new(algorithm: Algorithm, hashes: &BTreeMap<String, Digests>) -> MerkleTree {
+		let mut root = Directory::default();
+		for (name, digests) in hashes {
+			match digests.get(&algorithm) {
+				Some(digest) if !digest.bytes().all(|b| b == b'-') => {
+					root.insert(name, digest.clone())
+				}
+				_ => {}
+			}
+		}
+		root.seal(algorithm);
+
+		MerkleTree { algorithm, root }
+	}
+
+	/// The algorithm the tree is made with.
+	pub fn algorithm(&self) -> Algorithm {
+		self.algorithm
+	}
+
+	/// The digest of the whole tree.
+	pub fn root(&self) -> &str {
+		&self.root.digest
+	}
+
+	/// The digests of every directory, by path, the root being `./`.
+	pub fn subtrees(&self) -> Vec<(String, &str)> {
+		let mut subtrees = vec![("./".to_string(), self.root())];
+		self.root.subtrees("", &mut subtrees);
+		subtrees
+	}
+
+	/// Find where the current tree differs from `loaded`, made with the same
+	/// algorithm, descending only into the directories whose digests differ.
+	///
+	/// Differences are listed in the order they're found, each directory before
+	/// what's in it.
+	pub fn compare(&self, loaded: &MerkleTree) -> Vec<TreeDifference> {
+		let mut differences = Vec::new();
+		if self.root.digest != loaded.root.digest {
+			self.root.compare("", &loaded.root, &mut differences);
+		}
+		differences
+	}
+}
+
+impl Directory {
+	fn insert(&mut self, path: &str, digest: String) {
+		match path.split_once('/') {
+			Some((name, rest)) => {
+				let child = self
+					.children
+					.entry(name.to_string())
+					.or_insert_with(|| Node::Directory(Directory::default()));
+				// A file can't be named like a directory in the same tree
+				if let Node::Directory(directory) = child {
+					directory.insert(rest, digest);
+				}
+			}
+			None => {
+				self.children.insert(path.to_string(), Node::File(digest));
+			}
+		}
+	}
+
+	/// Compute the digests of this directory and the ones below it.
+	fn seal(&mut self, algorithm: Algorithm) {
+		let mut listing = String::new();
+		for (name, node) in &mut self.children {
+			let (kind, digest) = match node {
+				Node::File(digest) => ('F', &*digest),
+				Node::Directory(directory) => {
+					directory.seal(algorithm);
+					('D', &directory.digest)
+				}
+			};
+			// Length-prefixed, since names may hold anything but `/`
+			writeln!(listing, "{} {} {}:{}", kind, digest, name.len(), name).unwrap();
+		}
+		self.digest = hash_reader(algorithm, &mut listing.as_bytes()).unwrap();
+	}
+
+	fn subtrees<'a>(&'a self, prefix: &str, subtrees: &mut Vec<(String, &'a str)>) {
+		for (name, node) in &self.children {
+			if let Node::Directory(directory) = node {
+				let path = format!("{}{}/", prefix, name);
+				subtrees.push((path.clone(), &directory.digest));
+				directory.subtrees(&path, subtrees);
+			}
+		}
+	}
+
+	fn compare(&self, prefix: &str, loaded: &Directory, differences: &mut Vec<TreeDifference>) {
+		let names: BTreeSet<_> = self.children.keys().chain(loaded.children.keys()).collect();
+		for name in names {
+			let path = format!("{}{}", prefix, name);
+			match (self.children.get(name), loaded.children.get(name)) {
+				(Some(Node::File(current)), Some(Node::File(was))) => {
+					if current != was {
+						differences.push(TreeDifference::FileDiffers(path));
+					}
+				}
+				(Some(Node::Directory(current)), Some(Node::Directory(was))) => {
+					if current.digest != was.digest {
+						let path = path + "/";
+						differences.push(TreeDifference::SubtreeDiffers(path.clone()));
+						current.compare(&path, was, differences);
+					}
+				}
+				(current, was) => {
+					if let Some(was) = was {
+						differences.push(TreeDifference::Removed(node_path(&path, was)));
+					}
+					if let Some(current) = current {
+						differences.push(TreeDifference::Added(node_path(&path, current)));
+					}
+				}
+			}
+		}
+	}
+}
+
+fn node_path(path: &str, node: &Node) -> String {
+	match node {
+		Node::File(_) => path.to_string(),
+		Node::Directory(_) => format!("{}/", path),
+	}
+}
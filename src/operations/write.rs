@@ -13,10 +13,17 @@
  * limitations under the License.
  */
 
-use std::io::Write;
+use std::io::{self, Write};
 
-use super::{CompareError, CompareFileResult, CompareResult};
-use crate::{utilities::mul_str, Error};
+use super::{
+	AuditSummary,
+	CompareError,
+	CompareFileResult,
+	CompareResult,
+	MerkleTree,
+	TreeDifference,
+};
+use crate::{utilities::mul_str, Error, FileError, ParseError};
 
 /// Write hash comparison results to the output streams in a human-consumable
 /// format
@@ -24,106 +31,307 @@ pub fn write_hash_comparison_results<Wo: Write, We: Write>(
 	output: &mut Wo,
 	error: &mut We,
 	results: Result<(Vec<CompareResult>, Vec<CompareFileResult>), CompareError>,
-) -> Error {
-	let result = match results {
+) -> Result<(), Error> {
+	match results {
 		Ok((mut compare_results, mut file_compare_results)) => {
 			compare_results.sort();
 			file_compare_results.sort();
+			write_comparison(output, &compare_results, &file_compare_results)
+				.and_then(|_| output.flush())
+				.map_err(Error::Output)?;
+			comparison_outcome(&compare_results, &file_compare_results)
+		}
+		Err(CompareError::HashLengthDiffers {
+			previous_len,
+			current_len,
+		}) => {
+			write_length_error(error, previous_len, current_len)
+				.and_then(|_| error.flush())
+				.map_err(Error::Output)?;
+			Err(Error::HashLengthDiffers)
+		}
+	}
+}
+
+/// Write the results of `audit_hashes()` followed by a hashdeep-style audit
+/// summary. Matching files aren't listed.
+pub fn write_audit_results<W: Write>(
+	output: &mut W,
+	(mut compare_results, mut file_compare_results): (Vec<CompareResult>, Vec<CompareFileResult>),
+) -> Result<(), Error> {
+	compare_results.sort();
+	file_compare_results.sort();
+	let summary = AuditSummary::new(&compare_results, &file_compare_results);
+
+	write_audit(output, &compare_results, &file_compare_results, &summary)
+		.and_then(|_| output.flush())
+		.map_err(Error::Output)?;
+	audit_outcome(&summary)
+}
 
-			for res in &compare_results {
-				match *res {
-					CompareResult::FileAdded(ref file) => {
-						write_compare_result(output, "File added: ", file)
-					}
-					CompareResult::FileRemoved(ref file) => {
-						write_compare_result(output, "File removed: ", file)
-					}
-					CompareResult::FileIgnored(ref file) => {
-						write_compare_result(output, "File ignored, skipping: ", file)
-					}
+/// Compare the tree roots of the current and loaded digests and, if they
+/// differ, write where the trees do, found with `MerkleTree::compare()`.
+pub fn write_tree_comparison<W: Write>(
+	output: &mut W,
+	current: &MerkleTree,
+	loaded: &MerkleTree,
+) -> Result<(), Error> {
+	let differences = current.compare(loaded);
+	let write = |output: &mut W| {
+		if differences.is_empty() {
+			return writeln!(output, "Tree root {} matches", current.root());
+		}
+		writeln!(output, "Tree root doesn't match")?;
+		writeln!(output, "  Was: {}", loaded.root())?;
+		writeln!(output, "  Is : {}", current.root())?;
+		writeln!(output)?;
+		for difference in &differences {
+			match difference {
+				TreeDifference::SubtreeDiffers(path) => {
+					writeln!(output, "Subtree \"{}\" doesn't match", path)?
+				}
+				TreeDifference::FileDiffers(path) => {
+					writeln!(output, "File \"{}\" doesn't match", path)?
 				}
+				TreeDifference::Added(path) => writeln!(output, "File added: \"{}\"", path)?,
+				TreeDifference::Removed(path) => writeln!(output, "File removed: \"{}\"", path)?,
 			}
+		}
+		Ok(())
+	};
+	write(output)
+		.and_then(|_| output.flush())
+		.map_err(Error::Output)?;
 
-			if file_compare_results.is_empty() && compare_results.is_empty() {
-				writeln!(output, "No files left to verify").expect("io err");
-				Error::NoError
-			} else if file_compare_results.is_empty() {
-				writeln!(output, "No files to verify").expect("io err");
-				Error::NoError
-			} else {
-				if !compare_results.is_empty() {
-					writeln!(output).unwrap();
-				}
+	let differed_n = differences
+		.iter()
+		.filter(|difference| !matches!(difference, TreeDifference::SubtreeDiffers(_)))
+		.count();
+	match differed_n {
+		0 => Ok(()),
+		n => Err(Error::NFilesDiffer(n as i32)),
+	}
+}
 
-				let mut differed_n = 0;
-				for fres in &file_compare_results {
-					match *fres {
-						CompareFileResult::FileMatches(ref file) => {
-							write_file_result_match(output, file)
-						}
-						CompareFileResult::FileDiffers {
-							ref file,
-							ref was_hash,
-							ref new_hash,
-						} => {
-							write_file_result_diff(output, file, was_hash, new_hash);
-							differed_n += 1;
-						}
-					}
-				}
+/// Write a summary of the files that couldn't be read, if any.
+pub fn write_file_errors<W: Write>(output: &mut W, errors: &[FileError]) -> Result<(), Error> {
+	if errors.is_empty() {
+		return Ok(());
+	}
 
-				match differed_n {
-					0 => Error::NoError,
-					n => Error::NFilesDiffer(n),
-				}
+	let write = |output: &mut W| {
+		match errors.len() {
+			1 => writeln!(output, "1 file couldn't be read:")?,
+			n => writeln!(output, "{} files couldn't be read:", n)?,
+		}
+		for err in errors {
+			writeln!(output, "  \"{}\": {}", err.file, err.message)?;
+		}
+		output.flush()
+	};
+	write(output).map_err(Error::Output)
+}
+
+/// List the manifest lines `read_hashes_lenient()` skipped, if any.
+pub fn write_rejected_lines<W: Write>(
+	output: &mut W,
+	rejected: &[ParseError],
+) -> Result<(), Error> {
+	if rejected.is_empty() {
+		return Ok(());
+	}
+
+	let write = |output: &mut W| {
+		match rejected.len() {
+			1 => writeln!(output, "1 manifest line was skipped:")?,
+			n => writeln!(output, "{} manifest lines were skipped:", n)?,
+		}
+		for err in rejected {
+			writeln!(output, "  {}", err)?;
+		}
+		output.flush()
+	};
+	write(output).map_err(Error::Output)
+}
+
+/// What verifying with `compare_hashes()` amounts to: unreadable files trump
+/// differing ones.
+pub(super) fn comparison_outcome(
+	compare_results: &[CompareResult],
+	file_compare_results: &[CompareFileResult],
+) -> Result<(), Error> {
+	let unreadable = compare_results
+		.iter()
+		.any(|res| matches!(res, CompareResult::FileUnreadable(_)));
+	let differed_n = file_compare_results
+		.iter()
+		.filter(|res| matches!(res, CompareFileResult::FileDiffers { .. }))
+		.count();
+
+	match (unreadable, differed_n) {
+		(true, _) => Err(Error::FilesUnreadable),
+		(false, 0) => Ok(()),
+		(false, n) => Err(Error::NFilesDiffer(n as i32)),
+	}
+}
+
+/// What auditing with `audit_hashes()` amounts to.
+pub(super) fn audit_outcome(summary: &AuditSummary) -> Result<(), Error> {
+	match (summary.unreadable, summary.failures()) {
+		(0, 0) => Ok(()),
+		(0, n) => Err(Error::NFilesDiffer(n as i32)),
+		_ => Err(Error::FilesUnreadable),
+	}
+}
+
+fn write_comparison<W: Write>(
+	output: &mut W,
+	compare_results: &[CompareResult],
+	file_compare_results: &[CompareFileResult],
+) -> io::Result<()> {
+	for res in compare_results {
+		match *res {
+			CompareResult::FileAdded(ref file) => {
+				write_compare_result(output, "File added: ", file)?
 			}
+			CompareResult::FileRemoved(ref file) => {
+				write_compare_result(output, "File removed: ", file)?
+			}
+			CompareResult::FileIgnored(ref file) => {
+				write_compare_result(output, "File ignored, skipping: ", file)?
+			}
+			CompareResult::FileMoved { ref from, ref to } => {
+				write_file_result_moved(output, from, to)?
+			}
+			CompareResult::FileCopied { ref from, ref to } => {
+				write_file_result_copied(output, from, to)?
+			}
+			CompareResult::FileUnreadable(ref err) => write_file_result_unreadable(output, err)?,
 		}
-		Err(CompareError::HashLengthDiffers {
-			previous_len,
-			current_len,
-		}) => {
-			let previous_len_len = format!("{}", previous_len).len();
-			let current_len_len = format!("{}", current_len).len();
-
-			if previous_len_len + current_len_len + 47 <= 80 {
-				writeln!(
-					error,
-					"Hash lengths do not match; selected: {}, loaded: {}",
-					current_len, previous_len
-				)
-				.unwrap();
-			} else {
-				writeln!(error, "Hash lengths do not match;").unwrap();
-				if previous_len_len + current_len_len + 20 <= 80 {
-					writeln!(error, "selected: {}, loaded: {}", current_len, previous_len).unwrap();
-				} else {
-					writeln!(error, "Selected: {}", current_len).unwrap();
-					writeln!(error, "Loaded  : {}", previous_len).unwrap();
-				}
+	}
+
+	if file_compare_results.is_empty() && compare_results.is_empty() {
+		writeln!(output, "No files left to verify")
+	} else if file_compare_results.is_empty() {
+		writeln!(output, "No files to verify")
+	} else {
+		if !compare_results.is_empty() {
+			writeln!(output)?;
+		}
+
+		for fres in file_compare_results {
+			match *fres {
+				CompareFileResult::FileMatches(ref file) => write_file_result_match(output, file)?,
+				CompareFileResult::FileDiffers {
+					ref file,
+					ref was_hash,
+					ref new_hash,
+				} => write_file_result_diff(output, file, was_hash, new_hash)?,
 			}
+		}
+		Ok(())
+	}
+}
+
+fn write_length_error<W: Write>(
+	error: &mut W,
+	previous_len: usize,
+	current_len: usize,
+) -> io::Result<()> {
+	let previous_len_len = format!("{}", previous_len).len();
+	let current_len_len = format!("{}", current_len).len();
 
-			Error::HashLengthDiffers
+	if previous_len_len + current_len_len + 47 <= 80 {
+		writeln!(
+			error,
+			"Hash lengths do not match; selected: {}, loaded: {}",
+			current_len, previous_len
+		)
+	} else {
+		writeln!(error, "Hash lengths do not match;")?;
+		if previous_len_len + current_len_len + 20 <= 80 {
+			writeln!(error, "selected: {}, loaded: {}", current_len, previous_len)
+		} else {
+			writeln!(error, "Selected: {}", current_len)?;
+			writeln!(error, "Loaded  : {}", previous_len)
 		}
-	};
+	}
+}
 
-	output.flush().unwrap();
-	error.flush().unwrap();
+fn write_audit<W: Write>(
+	output: &mut W,
+	compare_results: &[CompareResult],
+	file_compare_results: &[CompareFileResult],
+	summary: &AuditSummary,
+) -> io::Result<()> {
+	for res in compare_results {
+		match *res {
+			CompareResult::FileAdded(ref file) => write_compare_result(output, "New file: ", file)?,
+			CompareResult::FileRemoved(ref file) => {
+				write_compare_result(output, "Known file not found: ", file)?
+			}
+			CompareResult::FileIgnored(ref file) => {
+				write_compare_result(output, "File ignored, skipping: ", file)?
+			}
+			CompareResult::FileMoved { ref from, ref to } => {
+				write_file_result_moved(output, from, to)?
+			}
+			CompareResult::FileCopied { ref from, ref to } => {
+				write_file_result_copied(output, from, to)?
+			}
+			CompareResult::FileUnreadable(ref err) => write_file_result_unreadable(output, err)?,
+		}
+	}
+	for fres in file_compare_results {
+		if let CompareFileResult::FileDiffers {
+			ref file,
+			ref was_hash,
+			ref new_hash,
+		} = *fres
+		{
+			write_file_result_diff(output, file, was_hash, new_hash)?;
+		}
+	}
+	if !compare_results.is_empty() || summary.partially_matched != 0 {
+		writeln!(output)?;
+	}
 
-	result
+	if summary.passed() {
+		writeln!(output, "Audit passed")?;
+	} else {
+		writeln!(output, "Audit failed")?;
+	}
+	writeln!(output, "          Files matched: {}", summary.matched)?;
+	writeln!(
+		output,
+		"Files partially matched: {}",
+		summary.partially_matched
+	)?;
+	writeln!(output, "            Files moved: {}", summary.moved)?;
+	writeln!(output, "           Files copied: {}", summary.copied)?;
+	writeln!(output, "        New files found: {}", summary.new)?;
+	writeln!(output, "  Known files not found: {}", summary.missing)?;
+	writeln!(output, "       Unreadable files: {}", summary.unreadable)
 }
 
-fn write_compare_result<W: Write>(out: &mut W, pre: &str, fname: &str) {
+fn write_compare_result<W: Write>(out: &mut W, pre: &str, fname: &str) -> io::Result<()> {
 	write_result(out, pre, fname, 2, true)
 }
 
-fn write_result<W: Write>(out: &mut W, pre: &str, fname: &str, fname_indent: usize, quote: bool) {
+fn write_result<W: Write>(
+	out: &mut W,
+	pre: &str,
+	fname: &str,
+	fname_indent: usize,
+	quote: bool,
+) -> io::Result<()> {
 	if pre.len() + quote as usize + fname.len() + quote as usize <= 80 {
 		let quote_s = if quote { "\"" } else { "" };
-		writeln!(out, "{}{2}{}{2}", pre, fname, quote_s).unwrap();
+		writeln!(out, "{}{2}{}{2}", pre, fname, quote_s)?;
 	} else {
-		writeln!(out, "{}", pre).unwrap();
+		writeln!(out, "{}", pre)?;
 		if fname.len() <= 80 - fname_indent {
-			writeln!(out, "  {}", fname).unwrap();
+			writeln!(out, "  {}", fname)?;
 		} else {
 			let indent = mul_str(" ", fname_indent);
 			for fname_chunk in fname
@@ -132,27 +340,48 @@ fn write_result<W: Write>(out: &mut W, pre: &str, fname: &str, fname_indent: usi
 				.chunks(80 - fname_indent)
 				.map(|cc| cc.iter().cloned().collect::<String>())
 			{
-				writeln!(out, "{}{}", indent, fname_chunk).unwrap();
+				writeln!(out, "{}{}", indent, fname_chunk)?;
 			}
 		}
 	}
+	Ok(())
 }
 
-fn write_file_result_match<W: Write>(out: &mut W, fname: &str) {
+fn write_file_result_match<W: Write>(out: &mut W, fname: &str) -> io::Result<()> {
 	if 15 + fname.len() <= 80 {
-		writeln!(out, "File \"{}\" matches", fname).unwrap();
+		writeln!(out, "File \"{}\" matches", fname)
 	} else {
-		write_compare_result(out, "File matches: ", fname);
+		write_compare_result(out, "File matches: ", fname)
 	}
 }
 
-fn write_file_result_diff<W: Write>(out: &mut W, fname: &str, lhash: &str, chash: &str) {
+fn write_file_result_moved<W: Write>(out: &mut W, from: &str, to: &str) -> io::Result<()> {
+	write_compare_result(out, "File moved: ", to)?;
+	write_result(out, "  From: ", from, 4, true)
+}
+
+fn write_file_result_copied<W: Write>(out: &mut W, from: &str, to: &str) -> io::Result<()> {
+	write_compare_result(out, "File copied: ", to)?;
+	write_result(out, "  From: ", from, 4, true)
+}
+
+fn write_file_result_unreadable<W: Write>(out: &mut W, err: &FileError) -> io::Result<()> {
+	write_compare_result(out, "File couldn't be read: ", &err.file)?;
+	write_result(out, "  Error: ", &err.message, 4, false)
+}
+
+fn write_file_result_diff<W: Write>(
+	out: &mut W,
+	fname: &str,
+	lhash: &str,
+	chash: &str,
+) -> io::Result<()> {
 	if 21 + fname.len() <= 80 {
-		writeln!(out, "File \"{}\" doesn't match", fname).unwrap();
+		writeln!(out, "File \"{}\" doesn't match", fname)?;
 	} else {
-		write_result(out, "File doesn't match: ", fname, 4, true);
+		write_result(out, "File doesn't match: ", fname, 4, true)?;
 	}
 
-	write_result(out, "  Was: ", lhash, 4, false);
-	write_result(out, "  Is : ", chash, 4, false);
+	write_result(out, "  Was: ", lhash, 4, false)?;
+	write_result(out, "  Is : ", chash, 4, false)
 }
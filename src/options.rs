@@ -13,11 +13,15 @@
  * limitations under the License.
  */
 
-use std::path::PathBuf;
+use std::{fs, path::PathBuf, time::SystemTime};
 
 use clap::{Parser, Subcommand};
 
-use crate::Algorithm;
+use crate::{
+	operations::{DuplicateAction, FilePredicate, ManifestFormat, MatchPolicy, ReportFormat},
+	utilities::{self, parse_size},
+	Algorithm,
+};
 
 #[derive(Parser)]
 #[clap(
@@ -27,37 +31,162 @@ use crate::Algorithm;
 	long_about = "A modern alternative to QuickSFV using Rust. Made with <3 by Cerda."
 )]
 pub struct Commands {
-	/// Hashing algorithm to use.
-	#[clap(arg_enum, short, long, default_value = "blake3")]
-	pub algorithm: Algorithm,
+	/// Hashing algorithm(s) to use. Can be used multiple times. Default:
+	/// `blake3`, or the manifest's algorithms when verifying
+	#[clap(
+		arg_enum,
+		short,
+		long,
+		multiple_occurrences = true,
+		use_value_delimiter = true
+	)]
+	pub algorithm: Vec<Algorithm>,
 	/// Max recursion depth. Infinite if None. Default: `0`
 	#[clap(short, long)]
 	pub depth: Option<usize>,
 	/// Whether to recurse down symlinks. Default: `true`
 	#[clap(long)]
 	pub follow_symlinks: bool,
-	/// Files/directories to ignore. Default: none
+	/// gitignore-style patterns of files/directories to ignore, on top of
+	/// the `.quickdashignore` files in the tree. Default: none
 	#[clap(short, long)]
 	pub ignored_files: Vec<String>,
+	/// gitignore-style patterns of the files to hash, leaving the others out.
+	/// Default: all
+	#[clap(long = "include")]
+	pub included_files: Vec<String>,
+	/// Leave out the files git ignores, by the tree's `.gitignore` files, its
+	/// repository's `.git/info/exclude` and the global excludes file.
+	#[clap(long)]
+	pub respect_gitignore: bool,
+	/// Leave out `.git`, `.hg` and `.svn` directories.
+	#[clap(long)]
+	pub skip_vcs: bool,
+	/// Only hash files at least this big, e.g. `10K`. Default: none
+	#[clap(long, parse(try_from_str = parse_size))]
+	pub min_size: Option<u64>,
+	/// Only hash files at most this big, e.g. `4G`. Default: none
+	#[clap(long, parse(try_from_str = parse_size))]
+	pub max_size: Option<u64>,
+	/// Only hash files modified after this time: a UTC
+	/// `YYYY-MM-DD[THH:MM[:SS]]` date, `@UNIX_TIMESTAMP` or an age like `7d`.
+	/// Default: none
+	#[clap(long, parse(try_from_str = parse_time))]
+	pub newer: Option<SystemTime>,
+	/// Only hash files modified after this file was. Default: none
+	#[clap(long, value_name = "FILE", parse(try_from_str = parse_file_time), conflicts_with = "newer")]
+	pub newer_than_file: Option<SystemTime>,
+	/// Only hash files modified before this time, like `--newer`. Default: none
+	#[clap(long, parse(try_from_str = parse_time))]
+	pub older: Option<SystemTime>,
+	/// Only hash files modified before this file was. Default: none
+	#[clap(long, value_name = "FILE", parse(try_from_str = parse_file_time), conflicts_with = "older")]
+	pub older_than_file: Option<SystemTime>,
+	/// Only hash files owned by this user, by name or ID. Default: none
+	#[clap(long, parse(try_from_str = parse_user))]
+	pub owner: Option<u32>,
+	/// Only hash files owned by this group, by name or ID. Default: none
+	#[clap(long, parse(try_from_str = parse_group))]
+	pub group: Option<u32>,
+	/// Only hash files with all of these octal permission bits set, e.g.
+	/// `111`. Default: none
+	#[clap(long, parse(try_from_str = parse_mode))]
+	pub perm: Option<u32>,
+	/// Only hash files with one of these extensions. Can be used multiple
+	/// times. Default: all
+	#[clap(
+		long = "extension",
+		multiple_occurrences = true,
+		use_value_delimiter = true
+	)]
+	pub extensions: Vec<String>,
 	/// # of threads used for hashing.
 	#[clap(short, long, default_value_t = 0)]
 	pub jobs: usize,
+	/// Manifest format. Default: picked by the file's extension, `hash` if
+	/// unknown
+	#[clap(arg_enum, long)]
+	pub format: Option<ManifestFormat>,
+	/// Cache file used to skip rehashing unchanged files. Default: none
+	#[clap(long)]
+	pub cache: Option<PathBuf>,
+	/// Rehash every file, ignoring digests stored in the cache.
+	#[clap(long, requires = "cache")]
+	pub rehash_all: bool,
+	/// File holding the secret key to make keyed digests with: BLAKE3 in keyed
+	/// mode, HMACs for SHA2 and SHA3. Default: none
+	#[clap(long, conflicts_with = "key-env")]
+	pub key_file: Option<PathBuf>,
+	/// Environment variable holding the secret key, like `--key-file`.
+	/// Default: none
+	#[clap(long)]
+	pub key_env: Option<String>,
 	/// Whether to verify or create hashes. Default: Verify
 	#[clap(subcommand)]
 	pub command: Mode,
 }
 
+impl Commands {
+	/// The predicates files have to meet to be hashed.
+	pub fn predicates(&self) -> Vec<FilePredicate> {
+		let mut predicates = Vec::new();
+		predicates.extend(self.min_size.map(FilePredicate::MinSize));
+		predicates.extend(self.max_size.map(FilePredicate::MaxSize));
+		predicates.extend(
+			self.newer
+				.or(self.newer_than_file)
+				.map(FilePredicate::NewerThan),
+		);
+		predicates.extend(
+			self.older
+				.or(self.older_than_file)
+				.map(FilePredicate::OlderThan),
+		);
+		predicates.extend(self.owner.map(FilePredicate::Owner));
+		predicates.extend(self.group.map(FilePredicate::Group));
+		predicates.extend(self.perm.map(FilePredicate::Permissions));
+		if !self.extensions.is_empty() {
+			predicates.push(FilePredicate::Extensions(self.extensions.clone()));
+		}
+		predicates
+	}
+}
+
 #[derive(Subcommand)]
 pub enum Mode {
 	Create {
 		/// Directory to hash. Default: current directory
 		#[clap(default_value = ".")]
 		path: PathBuf,
-		/// Output filename. Default: `directory_name.hash"`
+		/// Output filename. Default: `directory_name.hash"`, or the format's
+		/// extension
 		#[clap(long)]
 		file: Option<PathBuf>,
 		#[clap(short, long)]
 		force: bool,
+		/// Append digests to a journal as files are hashed, then sort them into
+		/// the manifest, so that memory use doesn't grow with the tree.
+		#[clap(long)]
+		stream: bool,
+		/// Pick up where an interrupted `--stream` or `--resume` run left off,
+		/// rehashing only files that changed since.
+		#[clap(long)]
+		resume: bool,
+		/// minisign secret key to sign the manifest with, writing the
+		/// signature to `file.minisig`.
+		#[clap(long)]
+		sign: Option<PathBuf>,
+		/// Record the Merkle tree root of the digests in the manifest's header,
+		/// and print it.
+		#[clap(long, conflicts_with_all = &["stream", "resume"])]
+		tree: bool,
+		/// Hash exactly the files listed in this file, `-` for stdin, relative
+		/// to the directory, instead of walking it.
+		#[clap(long, conflicts_with_all = &["stream", "resume"])]
+		files_from: Option<PathBuf>,
+		/// The `--files-from` list is NUL-separated, like `find -print0`'s.
+		#[clap(short = '0', long, requires = "files-from")]
+		null: bool,
 	},
 	Verify {
 		/// Directory to verify. Default: current directory
@@ -66,5 +195,117 @@ pub enum Mode {
 		/// Input filename. Default: `directory_name.hash"`
 		#[clap(long)]
 		file: Option<PathBuf>,
+		/// Whether all or any of a file's digests have to match.
+		#[clap(arg_enum, long, default_value = "all")]
+		check: MatchPolicy,
+		/// Match files by content like `hashdeep -a -k`, reporting moved, new
+		/// and missing files and an audit summary.
+		#[clap(long)]
+		audit: bool,
+		/// How to report the results. Default: `text`
+		#[clap(arg_enum, long, default_value = "text")]
+		report_format: ReportFormat,
+		/// Skip manifest lines that can't be parsed, listing them, instead of
+		/// failing.
+		#[clap(long)]
+		lenient: bool,
+		/// minisign public key (file or `RW...` string) the manifest's
+		/// signature has to validate against before anything is verified.
+		#[clap(long)]
+		public_key: Option<String>,
+		/// Signature of the manifest. Default: `file.minisig`
+		#[clap(long, requires = "public-key")]
+		signature: Option<PathBuf>,
+		/// Compare Merkle tree roots, then list where the trees differ,
+		/// descending only into directories that don't match.
+		#[clap(long, conflicts_with_all = &["audit", "report-format"])]
+		tree: bool,
+	},
+	/// Compare two manifests without reading the files they list.
+	Diff {
+		/// Manifest to compare against, e.g. yesterday's or the primary's
+		old: PathBuf,
+		/// Manifest to compare, e.g. today's or the replica's
+		new: PathBuf,
+		/// Whether all or any of a file's digests have to match.
+		#[clap(arg_enum, long, default_value = "all")]
+		check: MatchPolicy,
+		/// Match files by content like `hashdeep -a -k`, reporting moved, new
+		/// and missing files and an audit summary.
+		#[clap(long)]
+		audit: bool,
+		/// How to report the results. Default: `text`
+		#[clap(arg_enum, long, default_value = "text")]
+		report_format: ReportFormat,
+	},
+	/// Print the Merkle tree root of a directory or a manifest.
+	Tree {
+		/// Directory to hash. Default: current directory
+		#[clap(default_value = ".")]
+		path: PathBuf,
+		/// Manifest to read the digests from instead of hashing the directory.
+		#[clap(long)]
+		file: Option<PathBuf>,
+		/// Print the digests of every directory, not only the root's.
+		#[clap(long)]
+		subtrees: bool,
 	},
+	/// List the files with the same content, and optionally deduplicate them.
+	Dupes {
+		/// Directory to search. Default: current directory
+		#[clap(default_value = ".")]
+		path: PathBuf,
+		/// What to do with the duplicates of the first file of each group.
+		/// Default: nothing
+		#[clap(arg_enum, long)]
+		action: Option<DuplicateAction>,
+		/// Carry out `--action` instead of only listing what it would do.
+		#[clap(long, requires = "action")]
+		apply: bool,
+	},
+}
+
+fn parse_time(time: &str) -> Result<SystemTime, String> {
+	utilities::parse_time(time, SystemTime::now())
+}
+
+fn parse_file_time(file: &str) -> Result<SystemTime, String> {
+	fs::metadata(file)
+		.and_then(|meta| meta.modified())
+		.map_err(|e| format!("{}: {}", file, e))
+}
+
+fn parse_user(user: &str) -> Result<u32, String> {
+	parse_id(user, "/etc/passwd")
+}
+
+fn parse_group(group: &str) -> Result<u32, String> {
+	parse_id(group, "/etc/group")
+}
+
+/// Parse a numeric ID, or look a name up in the specified `/etc` database.
+fn parse_id(name: &str, database: &str) -> Result<u32, String> {
+	if !cfg!(unix) {
+		return Err("owners are only supported on Unix".to_string());
+	}
+	if let Ok(id) = name.parse() {
+		return Ok(id);
+	}
+	fs::read_to_string(database)
+		.map_err(|e| format!("{}: {}", database, e))?
+		.lines()
+		.map(|line| line.split(':').collect::<Vec<_>>())
+		.find(|fields| fields.len() > 2 && fields[0] == name)
+		.and_then(|fields| fields[2].parse().ok())
+		.ok_or_else(|| format!("no {:?} in {}", name, database))
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+	if !cfg!(unix) {
+		return Err("permissions are only supported on Unix".to_string());
+	}
+	u32::from_str_radix(mode, 8)
+		.ok()
+		.filter(|mode| *mode <= 0o7777)
+		.ok_or_else(|| format!("invalid octal mode {:?}", mode))
 }
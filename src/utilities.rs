@@ -15,7 +15,10 @@
 
 //! Module containing various utility functions
 
-use std::path::Path;
+use std::{
+	path::{Path, MAIN_SEPARATOR},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// Merges two `Vec`s.
 ///
@@ -46,7 +49,11 @@ pub fn mul_str(what: &str, n: usize) -> String {
 	what.repeat(n)
 }
 
-/// Create a user-usable path to `what` from `prefix`.
+/// Create a user-usable path to `what` from `prefix`, or of `what` itself if
+/// it isn't inside `prefix`.
+///
+/// Names that aren't valid UTF-8 have their invalid sequences replaced with
+/// U+FFFD. Walks refuse such files, so these only ever name them in errors.
 ///
 /// # Examples
 ///
@@ -59,8 +66,164 @@ pub fn mul_str(what: &str, n: usize) -> String {
 /// ```
 pub fn relative_name(prefix: &Path, what: &Path) -> String {
 	what.strip_prefix(prefix)
-		.unwrap()
-		.to_str()
-		.unwrap()
-		.replace('\\', "/")
+		.unwrap_or(what)
+		.to_string_lossy()
+		.replace(MAIN_SEPARATOR, "/")
+}
+
+/// Split a UNIX timestamp into its UTC `(year, month, day)` and `(hour,
+/// minute, second)`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(
+/// 	quickdash::utilities::utc_datetime(1624619558),
+/// 	((2021, 6, 25), (11, 12, 38))
+/// );
+/// ```
+pub fn utc_datetime(secs: u64) -> ((u64, u64, u64), (u64, u64, u64)) {
+	let (days, time) = (secs / 86400, secs % 86400);
+
+	// Howard Hinnant's civil_from_days, for days since 1970-01-01
+	let z = days + 719468;
+	let era = z / 146097;
+	let doe = z % 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + (month <= 2) as u64;
+
+	(
+		(year, month, day),
+		(time / 3600, time % 3600 / 60, time % 60),
+	)
+}
+
+/// Join a UTC `(year, month, day)` and `(hour, minute, second)` into a UNIX
+/// timestamp, the inverse of `utc_datetime()`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(
+/// 	quickdash::utilities::utc_timestamp((2021, 6, 25), (11, 12, 38)),
+/// 	1624619558
+/// );
+/// ```
+pub fn utc_timestamp(date: (u64, u64, u64), time: (u64, u64, u64)) -> u64 {
+	let ((year, month, day), (hour, minute, second)) = (date, time);
+
+	// Howard Hinnant's days_from_civil, for days since 1970-01-01
+	let year = year - (month <= 2) as u64;
+	let era = year / 400;
+	let yoe = year % 400;
+	let mp = (month + 9) % 12;
+	let doy = (153 * mp + 2) / 5 + day - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	let days = era * 146097 + doe - 719468;
+
+	days * 86400 + hour * 3600 + minute * 60 + second
+}
+
+/// Parse a size in bytes, optionally followed by a binary `K`, `M`, `G` or `T`
+/// multiplier.
+///
+/// # Examples
+///
+/// ```
+/// # use quickdash::utilities::parse_size;
+/// assert_eq!(parse_size("512"), Ok(512));
+/// assert_eq!(parse_size("4G"), Ok(4 << 30));
+/// assert_eq!(parse_size("10kib"), Ok(10 << 10));
+/// assert!(parse_size("4X").is_err());
+/// ```
+pub fn parse_size(size: &str) -> Result<u64, String> {
+	let digits = size
+		.find(|c: char| !c.is_ascii_digit())
+		.unwrap_or(size.len());
+	let (number, unit) = size.split_at(digits);
+	let shift = match unit.to_ascii_uppercase().as_str() {
+		"" | "B" => 0,
+		"K" | "KB" | "KIB" => 10,
+		"M" | "MB" | "MIB" => 20,
+		"G" | "GB" | "GIB" => 30,
+		"T" | "TB" | "TIB" => 40,
+		_ => return Err(format!("unknown size unit {:?}", unit)),
+	};
+	number
+		.parse::<u64>()
+		.ok()
+		.and_then(|number| number.checked_mul(1 << shift))
+		.ok_or_else(|| format!("invalid size {:?}", size))
+}
+
+/// Parse a point in time: a UTC `YYYY-MM-DD[THH:MM[:SS]]` date, `@` followed by
+/// a UNIX timestamp, or an age before `now` like `90s`, `15m`, `12h`, `7d` or
+/// `2w`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::{Duration, UNIX_EPOCH};
+/// # use quickdash::utilities::parse_time;
+/// let now = UNIX_EPOCH + Duration::from_secs(1624619558);
+/// assert_eq!(parse_time("2021-06-25T11:12:38", now), Ok(now));
+/// assert_eq!(parse_time("@1624619558", now), Ok(now));
+/// assert_eq!(
+/// 	parse_time("2021-06-25", now),
+/// 	Ok(UNIX_EPOCH + Duration::from_secs(1624579200))
+/// );
+/// assert_eq!(
+/// 	parse_time("7d", now),
+/// 	Ok(now - Duration::from_secs(7 * 86400))
+/// );
+/// ```
+pub fn parse_time(time: &str, now: SystemTime) -> Result<SystemTime, String> {
+	let invalid = || format!("invalid time {:?}", time);
+	if let Some(secs) = time.strip_prefix('@') {
+		let secs = secs.parse().map_err(|_| invalid())?;
+		return Ok(UNIX_EPOCH + Duration::from_secs(secs));
+	}
+	if let Some(unit) = time.chars().last().filter(char::is_ascii_alphabetic) {
+		let unit = match unit {
+			's' => 1,
+			'm' => 60,
+			'h' => 3600,
+			'd' => 86400,
+			'w' => 7 * 86400,
+			_ => return Err(invalid()),
+		};
+		return time[..time.len() - 1]
+			.parse::<u64>()
+			.ok()
+			.and_then(|age| age.checked_mul(unit))
+			.and_then(|age| now.checked_sub(Duration::from_secs(age)))
+			.ok_or_else(invalid);
+	}
+
+	let (date, clock) = time.split_once(['T', ' ']).unwrap_or((time, "0:0"));
+	let numbers = |text: &str, separator| {
+		text.split(separator)
+			.map(|n| n.parse::<u64>().map_err(|_| invalid()))
+			.collect::<Result<Vec<_>, _>>()
+	};
+	let (date, clock) = (numbers(date, '-')?, numbers(clock, ':')?);
+	match (&date[..], &clock[..]) {
+		(&[year, month, day], &[hour, minute, ref second @ ..])
+			if year >= 1970
+				&& (1..=12).contains(&month)
+				&& (1..=31).contains(&day)
+				&& hour < 24 && minute < 60
+				&& second.len() <= 1
+				&& second.iter().all(|&s| s < 60) =>
+		{
+			let second = second.first().copied().unwrap_or(0);
+			let secs = utc_timestamp((year, month, day), (hour, minute, second));
+			Ok(UNIX_EPOCH + Duration::from_secs(secs))
+		}
+		_ => Err(invalid()),
+	}
 }
@@ -27,3 +27,10 @@ fn from_str() {
 		assert_eq!(Algorithm::from_str(a.0).unwrap(), a.1);
 	}
 }
+
+#[test]
+fn tag_round_trip() {
+	for algo in <Algorithm as clap::ArgEnum>::value_variants() {
+		assert_eq!(Algorithm::from_str(algo.tag()).as_ref(), Ok(algo));
+	}
+}
@@ -0,0 +1,127 @@
+use std::{
+	fs::{self, metadata},
+	path::Path,
+};
+
+use quickdash::{
+	operations::{create_hashes, FileFingerprint, HashCache, PathFilter},
+	Algorithm,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn unchanged_files_use_cached_digest() {
+	let dir = scratch_dir("cache");
+	let tree = dir.join("tree");
+	fs::create_dir(&tree).unwrap();
+	fs::write(tree.join("file"), b"quickdash").unwrap();
+
+	let mut cache = HashCache::open(&dir.join("cache")).unwrap();
+	let (hashes, _) = create_hashes(
+		&tree,
+		&PathFilter::default(),
+		&[Algorithm::MD5],
+		None,
+		None,
+		false,
+		1,
+		Some(&mut cache),
+	)
+	.unwrap();
+	cache.save().unwrap();
+	let real = hashes["file"][&Algorithm::MD5].clone();
+
+	let mut cache = HashCache::open(&dir.join("cache")).unwrap();
+	let key = tree.canonicalize().unwrap().join("file");
+	let fingerprint = FileFingerprint::from_metadata(&metadata(&key).unwrap());
+	assert_eq!(
+		cache.get(Algorithm::MD5, &key.to_string_lossy(), &fingerprint),
+		Some(real.as_str())
+	);
+	assert_eq!(
+		cache.get(Algorithm::SHA1, &key.to_string_lossy(), &fingerprint),
+		None
+	);
+
+	cache.insert(
+		Algorithm::MD5,
+		key.to_string_lossy().to_string(),
+		fingerprint,
+		"CACHED".to_string(),
+	);
+	let (hashes, _) = create_hashes(
+		&tree,
+		&PathFilter::default(),
+		&[Algorithm::MD5],
+		None,
+		None,
+		false,
+		1,
+		Some(&mut cache),
+	)
+	.unwrap();
+	assert_eq!(hashes["file"][&Algorithm::MD5], "CACHED");
+
+	cache.set_rehash_all(true);
+	let (hashes, _) = create_hashes(
+		&tree,
+		&PathFilter::default(),
+		&[Algorithm::MD5],
+		None,
+		None,
+		false,
+		1,
+		Some(&mut cache),
+	)
+	.unwrap();
+	assert_eq!(hashes["file"][&Algorithm::MD5], real);
+}
+
+#[test]
+fn deleted_files_are_pruned() {
+	let dir = scratch_dir("cache-prune");
+	let tree = dir.join("tree");
+	fs::create_dir(&tree).unwrap();
+	fs::write(tree.join("kept"), b"kept").unwrap();
+	fs::write(tree.join("deleted"), b"deleted").unwrap();
+
+	let cached = |tree: &Path| {
+		let mut cache = HashCache::open(&dir.join("cache")).unwrap();
+		create_hashes(
+			tree,
+			&PathFilter::default(),
+			&[Algorithm::MD5],
+			None,
+			None,
+			false,
+			1,
+			Some(&mut cache),
+		)
+		.unwrap();
+		cache.save().unwrap();
+		fs::read_to_string(dir.join("cache")).unwrap()
+	};
+	let cache = cached(&tree);
+	assert!(cache.contains("/deleted\n"));
+	assert!(cache.contains("/kept\n"));
+
+	fs::remove_file(tree.join("deleted")).unwrap();
+	let cache = cached(&tree);
+	assert!(!cache.contains("/deleted\n"));
+	assert!(cache.contains("/kept\n"));
+
+	// Other trees' entries are left alone
+	let other = dir.join("other");
+	fs::create_dir(&other).unwrap();
+	fs::write(other.join("elsewhere"), b"elsewhere").unwrap();
+	cached(&other);
+	let cache = cached(&tree);
+	assert!(cache.contains("/elsewhere\n"));
+	assert!(cache.contains("/kept\n"));
+
+	// Runs that hash nothing don't touch the cache
+	HashCache::open(&dir.join("cache")).unwrap().save().unwrap();
+	assert_eq!(fs::read_to_string(dir.join("cache")).unwrap(), cache);
+}
@@ -0,0 +1,78 @@
+use std::{
+	fs,
+	path::Path,
+	process::{Command, Output},
+};
+
+mod common;
+use common::scratch_dir;
+
+fn quickdash(dir: &Path, args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_quickdash"))
+		.args(args)
+		.current_dir(dir)
+		.output()
+		.unwrap()
+}
+
+#[test]
+fn create_in_current_directory() {
+	let tree = scratch_dir("cli-create");
+	fs::write(tree.join("file"), "quickdash").unwrap();
+	let manifest = tree.join(format!("quickdash-cli-create-{}.hash", std::process::id()));
+
+	let output = quickdash(&tree, &["create"]);
+	assert_eq!(output.status.code(), Some(0));
+	assert!(manifest.exists());
+
+	let output = quickdash(&tree, &["create", "."]);
+	assert_eq!(output.status.code(), Some(1));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains("file already exists, use --force to overwrite it"));
+
+	let output = quickdash(&tree, &["verify"]);
+	assert_eq!(output.status.code(), Some(0));
+	let output = quickdash(&tree, &["-a", "md5", "verify"]);
+	assert_eq!(output.status.code(), Some(1));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains("no digests to compare were made with the selected algorithms"));
+}
+
+#[test]
+fn tree_of_current_directory() {
+	let tree = scratch_dir("cli-tree");
+	fs::write(tree.join("file"), "quickdash").unwrap();
+
+	let output = quickdash(&tree, &["tree"]);
+	assert_eq!(output.status.code(), Some(0));
+	let root = output.stdout;
+	assert!(!root.is_empty());
+
+	// The default manifest is left out
+	assert_eq!(quickdash(&tree, &["create"]).status.code(), Some(0));
+	let output = quickdash(&tree, &["tree"]);
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(output.stdout, root);
+}
+
+#[test]
+fn reports_leave_out_the_default_manifest() {
+	let scratch = scratch_dir("cli-report");
+	let tree = scratch.join("d");
+	fs::create_dir(&tree).unwrap();
+	fs::write(tree.join("file"), "quickdash").unwrap();
+	assert_eq!(quickdash(&scratch, &["create", "d"]).status.code(), Some(0));
+
+	let output = quickdash(&scratch, &["verify", "--report-format", "junit", "d"]);
+	assert_eq!(output.status.code(), Some(0));
+	let junit = String::from_utf8_lossy(&output.stdout);
+	assert!(junit.contains(r#"tests="1" failures="0" errors="0" skipped="0""#));
+	assert!(!junit.contains("d.hash"));
+
+	let output = quickdash(&scratch, &["verify", "--report-format", "tap", "d"]);
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(
+		String::from_utf8_lossy(&output.stdout),
+		"TAP version 13\n1..1\nok 1 - file\n"
+	);
+}
@@ -0,0 +1,40 @@
+//! Helpers shared by the integration tests.
+
+use std::{
+	env,
+	fs,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+
+/// An empty directory for a single test, removed when dropped, even if the
+/// test fails.
+pub struct ScratchDir(PathBuf);
+
+impl Deref for ScratchDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for ScratchDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for ScratchDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+/// Create an empty directory for the test `name`, unique to this process.
+pub fn scratch_dir(name: &str) -> ScratchDir {
+	let dir = env::temp_dir().join(format!("quickdash-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	ScratchDir(dir)
+}
@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, path::Path};
+
+use quickdash::{
+	hash_reader,
+	hash_reader_multi,
+	operations::{
+		audit_hashes,
+		compare_hashes,
+		remove_manifest_entry,
+		AuditSummary,
+		CompareFileResult,
+		CompareResult,
+		MatchPolicy,
+	},
+	Algorithm,
+	Digests,
+};
+
+fn digests(pairs: &[(Algorithm, &str)]) -> Digests {
+	pairs.iter().map(|(a, d)| (*a, d.to_string())).collect()
+}
+
+#[test]
+fn multi_matches_single() {
+	let algos = [Algorithm::BLAKE3, Algorithm::SHA2256, Algorithm::CRC32];
+	let multi = hash_reader_multi(&algos, &mut &b"quickdash"[..]).unwrap();
+	for algo in algos {
+		assert_eq!(
+			multi[&algo],
+			hash_reader(algo, &mut &b"quickdash"[..]).unwrap()
+		);
+	}
+}
+
+#[test]
+fn match_policy() {
+	let loaded = BTreeMap::from([(
+		"file".to_string(),
+		digests(&[
+			(Algorithm::CRC32, "AAAAAAAA"),
+			(Algorithm::MD5, &"B".repeat(32)),
+		]),
+	)]);
+	let current = BTreeMap::from([(
+		"file".to_string(),
+		digests(&[
+			(Algorithm::CRC32, "AAAAAAAA"),
+			(Algorithm::MD5, &"C".repeat(32)),
+		]),
+	)]);
+
+	let (_, any) = compare_hashes(
+		Path::new("out.hash"),
+		current.clone(),
+		loaded.clone(),
+		MatchPolicy::Any,
+	)
+	.unwrap();
+	assert_eq!(
+		any,
+		vec![CompareFileResult::FileMatches("file".to_string())]
+	);
+
+	let (_, all) =
+		compare_hashes(Path::new("out.hash"), current, loaded, MatchPolicy::All).unwrap();
+	assert!(matches!(all[..], [CompareFileResult::FileDiffers { .. }]));
+}
+
+#[test]
+fn audit() {
+	let md5 = |c: &str| digests(&[(Algorithm::MD5, &c.repeat(32))]);
+	let loaded = BTreeMap::from([
+		("same".to_string(), md5("A")),
+		("old".to_string(), md5("B")),
+		("changed".to_string(), md5("C")),
+	]);
+	let current = BTreeMap::from([
+		("same".to_string(), md5("A")),
+		("new".to_string(), md5("B")),
+		("changed".to_string(), md5("D")),
+	]);
+
+	let (results, file_results) = audit_hashes(Path::new("out.hash"), current, loaded);
+	assert_eq!(
+		file_results,
+		vec![CompareFileResult::FileMatches("same".to_string())]
+	);
+	assert!(results.contains(&CompareResult::FileMoved {
+		from: "old".to_string(),
+		to: "new".to_string(),
+	}));
+	assert!(results.contains(&CompareResult::FileAdded("changed".to_string())));
+	assert!(results.contains(&CompareResult::FileRemoved("changed".to_string())));
+
+	let summary = AuditSummary::new(&results, &file_results);
+	assert_eq!(
+		(summary.matched, summary.moved, summary.new, summary.missing),
+		(1, 1, 1, 1)
+	);
+	assert!(!summary.passed());
+}
+
+#[test]
+fn manifest_entries_are_removed() {
+	let placeholder = digests(&[(Algorithm::CRC32, "--------")]);
+	let mut loaded = BTreeMap::from([
+		("./replica/tree.hash".to_string(), placeholder.clone()),
+		("ignored".to_string(), placeholder.clone()),
+		("sub/tree.hash".to_string(), placeholder),
+		(
+			"tree.hash".to_string(),
+			digests(&[(Algorithm::CRC32, "AAAAAAAA")]),
+		),
+	]);
+	remove_manifest_entry(Path::new("/srv/replica/tree.hash"), &mut loaded);
+	assert_eq!(
+		loaded.keys().collect::<Vec<_>>(),
+		["ignored", "sub/tree.hash", "tree.hash"]
+	);
+}
+
+#[test]
+fn audit_copies() {
+	let md5 = |c: &str| digests(&[(Algorithm::MD5, &c.repeat(32))]);
+	let loaded = BTreeMap::from([
+		("kept".to_string(), md5("A")),
+		("old".to_string(), md5("B")),
+	]);
+	let current = BTreeMap::from([
+		("a copy".to_string(), md5("A")),
+		("kept".to_string(), md5("A")),
+		("new".to_string(), md5("B")),
+		("new copy".to_string(), md5("B")),
+	]);
+
+	let (mut results, file_results) = audit_hashes(Path::new("out.hash"), current, loaded);
+	results.sort();
+	// Only originals that aren't found in place are moved
+	assert_eq!(
+		results,
+		vec![
+			CompareResult::FileMoved {
+				from: "old".to_string(),
+				to: "new".to_string(),
+			},
+			CompareResult::FileCopied {
+				from: "kept".to_string(),
+				to: "a copy".to_string(),
+			},
+			CompareResult::FileCopied {
+				from: "old".to_string(),
+				to: "new copy".to_string(),
+			},
+		]
+	);
+	assert_eq!(
+		file_results,
+		vec![CompareFileResult::FileMatches("kept".to_string())]
+	);
+
+	let summary = AuditSummary::new(&results, &file_results);
+	assert_eq!((summary.matched, summary.moved, summary.copied), (1, 1, 2));
+	assert!(!summary.passed());
+}
+
+#[test]
+fn moves_and_copies() {
+	let crc = |c: &str| digests(&[(Algorithm::CRC32, &c.repeat(8))]);
+	let loaded = BTreeMap::from([
+		("kept".to_string(), crc("A")),
+		("old".to_string(), crc("B")),
+		("gone".to_string(), crc("C")),
+	]);
+	let current = BTreeMap::from([
+		("kept".to_string(), crc("A")),
+		("kept copy".to_string(), crc("A")),
+		("new".to_string(), crc("B")),
+		("new copy".to_string(), crc("B")),
+		("other".to_string(), crc("D")),
+	]);
+
+	let (mut results, file_results) =
+		compare_hashes(Path::new("out.hash"), current, loaded, MatchPolicy::All).unwrap();
+	results.sort();
+	assert_eq!(
+		results,
+		vec![
+			CompareResult::FileAdded("other".to_string()),
+			CompareResult::FileRemoved("gone".to_string()),
+			CompareResult::FileMoved {
+				from: "old".to_string(),
+				to: "new".to_string(),
+			},
+			CompareResult::FileCopied {
+				from: "kept".to_string(),
+				to: "kept copy".to_string(),
+			},
+			CompareResult::FileCopied {
+				from: "old".to_string(),
+				to: "new copy".to_string(),
+			},
+		]
+	);
+	assert_eq!(
+		file_results,
+		vec![CompareFileResult::FileMatches("kept".to_string())]
+	);
+
+	let summary = AuditSummary::new(&results, &file_results);
+	assert_eq!((summary.moved, summary.copied, summary.new), (1, 2, 1));
+}
+
+#[test]
+fn empty_files_are_not_paired() {
+	let empty = hash_reader(Algorithm::MD5, &mut &b""[..]).unwrap();
+	let loaded = BTreeMap::from([("deleted".to_string(), digests(&[(Algorithm::MD5, &empty)]))]);
+	let current = BTreeMap::from([(
+		"created".to_string(),
+		digests(&[(Algorithm::MD5, &empty.to_lowercase())]),
+	)]);
+
+	let (mut results, _) =
+		compare_hashes(Path::new("out.hash"), current, loaded, MatchPolicy::All).unwrap();
+	results.sort();
+	assert_eq!(
+		results,
+		vec![
+			CompareResult::FileAdded("created".to_string()),
+			CompareResult::FileRemoved("deleted".to_string()),
+		]
+	);
+}
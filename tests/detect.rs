@@ -0,0 +1,63 @@
+use std::{fs, path::Path};
+
+use quickdash::{
+	hash_reader,
+	operations::{algorithm_from_name, detect_algorithm, DetectionMethod, ManifestFormat},
+	Algorithm,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn detects_headerless_manifests() {
+	let dir = scratch_dir("detect");
+	fs::write(dir.join("file"), b"quickdash").unwrap();
+
+	let sha3 = hash_reader(Algorithm::SHA3256, &mut &b"quickdash"[..])
+		.unwrap()
+		.to_lowercase();
+	let md5 = hash_reader(Algorithm::MD5, &mut &b"quickdash"[..])
+		.unwrap()
+		.to_lowercase();
+	fs::write(dir.join("list.txt"), format!("{}  file\n", sha3)).unwrap();
+	fs::write(dir.join("SHA3-256SUMS"), format!("{}  file\n", sha3)).unwrap();
+	fs::write(dir.join("old.md5"), format!("{}  file\n", md5)).unwrap();
+
+	for (manifest, algorithm, method) in [
+		("list.txt", Algorithm::SHA3256, DetectionMethod::Sample),
+		(
+			"SHA3-256SUMS",
+			Algorithm::SHA3256,
+			DetectionMethod::FileName,
+		),
+		("old.md5", Algorithm::MD5, DetectionMethod::DigestLength),
+	] {
+		let detection = detect_algorithm(&dir.join(manifest), ManifestFormat::Hash, Some(&dir))
+			.unwrap()
+			.unwrap();
+		assert_eq!((detection.algorithm, detection.method), (algorithm, method));
+	}
+
+	// Without a root, nothing tells the candidates apart
+	let detection = detect_algorithm(&dir.join("list.txt"), ManifestFormat::Hash, None)
+		.unwrap()
+		.unwrap();
+	assert_eq!(detection.method, DetectionMethod::Guess);
+}
+
+#[test]
+fn name_hints_are_tokens() {
+	for (name, algorithm) in [
+		("SHA256SUMS", Some(Algorithm::SHA2256)),
+		("sha3_256sums.txt", Some(Algorithm::SHA3256)),
+		("B2SUMS", Some(Algorithm::BLAKE2B)),
+		("files.b3", Some(Algorithm::BLAKE3)),
+		("release-md5sum", Some(Algorithm::MD5)),
+		("web2.hash", None),
+		("tab3.txt", None),
+		("md5s.txt", None),
+	] {
+		assert_eq!(algorithm_from_name(Path::new(name)), algorithm, "{}", name);
+	}
+}
@@ -0,0 +1,125 @@
+use std::fs;
+
+use quickdash::{
+	operations::{
+		apply_duplicate_action,
+		find_duplicates,
+		write_duplicates,
+		DuplicateAction,
+		PathFilter,
+	},
+	Algorithm,
+	Error,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn duplicate_groups() {
+	let tree = scratch_dir("dupes");
+	fs::create_dir_all(tree.join("sub")).unwrap();
+	fs::write(tree.join("a"), "quickdash").unwrap();
+	fs::write(tree.join("sub/b"), "quickdash").unwrap();
+	fs::write(tree.join("c"), "quickdasH").unwrap();
+	fs::write(tree.join("empty"), "").unwrap();
+	fs::write(tree.join("empty2"), "").unwrap();
+	// Same size and beginning, different ends
+	let mut long = vec![0; 10000];
+	fs::write(tree.join("long1"), &long).unwrap();
+	fs::write(tree.join("long2"), &long).unwrap();
+	long[9999] = 1;
+	fs::write(tree.join("long3"), &long).unwrap();
+
+	let filter = PathFilter::default();
+	let (groups, errors) =
+		find_duplicates(&tree, &filter, Algorithm::CRC32, None, false, 1).unwrap();
+	assert!(errors.is_empty());
+	let files: Vec<_> = groups.iter().map(|group| group.files.clone()).collect();
+	assert_eq!(files, [vec!["long1", "long2"], vec!["a", "sub/b"]]);
+	assert_eq!(groups[0].size, 10000);
+	assert_eq!(groups[1].redundant_size(), 9);
+
+	let ignored = PathFilter::new(&["sub"], &[]).unwrap();
+	let (ignoring, _) = find_duplicates(&tree, &ignored, Algorithm::CRC32, None, false, 1).unwrap();
+	assert_eq!(ignoring.len(), 1);
+
+	let mut out = Vec::new();
+	write_duplicates(&mut out, &groups).unwrap();
+	let out = String::from_utf8(out).unwrap();
+	assert!(out.contains("  \"long1\"\n  \"long2\"\n"));
+	assert!(out.ends_with("2 groups of duplicates, 2 redundant files, 9.77KiB to reclaim\n"));
+
+	// Checksums can't be trusted to tell files apart
+	assert!(matches!(
+		apply_duplicate_action(
+			&mut Vec::new(),
+			&tree,
+			&groups,
+			DuplicateAction::Delete,
+			true
+		),
+		Err(Error::NotCryptographic(Algorithm::CRC32))
+	));
+	assert!(tree.join("long2").exists());
+	let (groups, _) = find_duplicates(&tree, &filter, Algorithm::BLAKE3, None, false, 1).unwrap();
+
+	// Dry run by default
+	let mut out = Vec::new();
+	apply_duplicate_action(&mut out, &tree, &groups, DuplicateAction::Delete, false).unwrap();
+	assert_eq!(
+		String::from_utf8(out).unwrap(),
+		"Would delete \"long2\", a duplicate of \"long1\"\nWould delete \"sub/b\", a duplicate of \
+		 \"a\"\n"
+	);
+	assert!(tree.join("long2").exists());
+
+	let mut out = Vec::new();
+	apply_duplicate_action(&mut out, &tree, &groups[..1], DuplicateAction::Delete, true).unwrap();
+	assert!(!tree.join("long2").exists());
+
+	let mut out = Vec::new();
+	apply_duplicate_action(
+		&mut out,
+		&tree,
+		&groups[1..],
+		DuplicateAction::Hardlink,
+		true,
+	)
+	.unwrap();
+	assert_eq!(fs::read(tree.join("sub/b")).unwrap(), b"quickdash");
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		let a = fs::metadata(tree.join("a")).unwrap();
+		let b = fs::metadata(tree.join("sub/b")).unwrap();
+		assert_eq!(a.ino(), b.ino());
+
+		// Hard links to the same file aren't duplicates
+		let (groups, _) =
+			find_duplicates(&tree, &filter, Algorithm::CRC32, None, false, 1).unwrap();
+		assert!(groups.is_empty());
+	}
+}
+
+#[test]
+fn changed_duplicates_are_kept() {
+	let tree = scratch_dir("dupes-changed");
+	fs::write(tree.join("a"), "quickdash").unwrap();
+	fs::write(tree.join("b"), "quickdash").unwrap();
+
+	let filter = PathFilter::default();
+	let (groups, _) = find_duplicates(&tree, &filter, Algorithm::BLAKE3, None, false, 1).unwrap();
+	assert_eq!(groups[0].files, ["a", "b"]);
+
+	fs::write(tree.join("b"), "quickdasH").unwrap();
+	let result = apply_duplicate_action(
+		&mut Vec::new(),
+		&tree,
+		&groups,
+		DuplicateAction::Delete,
+		true,
+	);
+	assert!(matches!(result, Err(Error::DuplicateChanged(path)) if path == tree.join("b")));
+	assert!(tree.join("b").exists());
+}
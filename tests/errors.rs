@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use std::{fs, io::ErrorKind, os::unix::fs::symlink, path::Path};
+
+use quickdash::{
+	operations::{create_hashes, mark_unreadable, CompareResult, PathFilter},
+	Algorithm,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn unreadable_files_are_reported() {
+	let tree = scratch_dir("errors");
+	fs::write(tree.join("file"), "quickdash").unwrap();
+	symlink(Path::new("nowhere"), tree.join("dangling")).unwrap();
+
+	let filter = PathFilter::default();
+	let (hashes, errors) = create_hashes(
+		&tree,
+		&filter,
+		&[Algorithm::CRC32],
+		None,
+		None,
+		true,
+		1,
+		None,
+	)
+	.unwrap();
+	assert!(hashes.contains_key("file"));
+	assert_eq!(errors.len(), 1);
+	assert_eq!(errors[0].file, "dangling");
+	assert_eq!(errors[0].kind, ErrorKind::NotFound);
+
+	let mut results = vec![CompareResult::FileRemoved("dangling".to_string())];
+	mark_unreadable(&mut results, &errors);
+	assert_eq!(
+		results,
+		vec![CompareResult::FileUnreadable(errors[0].clone())]
+	);
+}
+
+#[test]
+fn non_utf8_names_are_refused() {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	let tree = scratch_dir("non-utf8");
+	fs::write(tree.join(OsStr::from_bytes(b"bad\xff")), "quickdash").unwrap();
+	fs::create_dir(tree.join(OsStr::from_bytes(b"dir\xfe"))).unwrap();
+	fs::write(tree.join(OsStr::from_bytes(b"dir\xfe/file")), "quickdash").unwrap();
+	fs::write(tree.join("good"), "quickdash").unwrap();
+
+	let (hashes, errors) = create_hashes(
+		&tree,
+		&PathFilter::default(),
+		&[Algorithm::CRC32],
+		None,
+		None,
+		false,
+		1,
+		None,
+	)
+	.unwrap();
+	assert_eq!(hashes.keys().collect::<Vec<_>>(), ["good"]);
+	let errors: Vec<_> = errors.iter().map(|e| (e.file.as_str(), e.kind)).collect();
+	assert_eq!(
+		errors,
+		[
+			("bad\u{FFFD}", ErrorKind::InvalidData),
+			("dir\u{FFFD}", ErrorKind::InvalidData),
+		]
+	);
+}
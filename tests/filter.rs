@@ -0,0 +1,286 @@
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	time::{Duration, SystemTime},
+};
+
+use quickdash::{
+	operations::{create_hashes, FilePredicate, ManifestHeader, PathFilter, Patterns},
+	Algorithm,
+	Digests,
+	Error,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn gitignore_patterns() {
+	let patterns =
+		Patterns::new(&["target", "**/node_modules", "docs/*.md", "!docs/README.md"]).unwrap();
+	assert_eq!(patterns.matched("target", true), Some(true));
+	assert_eq!(patterns.matched("a/b/target", false), Some(true));
+	assert_eq!(patterns.matched("a/node_modules", true), Some(true));
+	assert_eq!(patterns.matched("docs/a.md", false), Some(true));
+	// Anchored, and `*` doesn't match `/`
+	assert_eq!(patterns.matched("a/docs/a.md", false), None);
+	assert_eq!(patterns.matched("docs/a/b.md", false), None);
+	assert_eq!(patterns.matched("docs/README.md", false), Some(false));
+
+	let dirs = Patterns::new(&["cache/"]).unwrap();
+	assert_eq!(dirs.matched("a/cache", true), Some(true));
+	assert_eq!(dirs.matched("a/cache", false), None);
+
+	assert!(matches!(Patterns::new(&["a[b"]), Err(Error::Pattern(_))));
+	assert!(matches!(Patterns::new(&["re:("]), Err(Error::Pattern(_))));
+}
+
+#[test]
+fn filtered_walks() {
+	let tree = scratch_dir("filter");
+	for dir in ["src/gen", "target/debug", "docs"] {
+		fs::create_dir_all(tree.join(dir)).unwrap();
+	}
+	for file in [
+		"a.rs",
+		"a.tmp",
+		"src/b.rs",
+		"src/b.tmp",
+		"src/keep.tmp",
+		"src/gen/c.rs",
+		"target/debug/d",
+		"docs/e.md",
+	] {
+		fs::write(tree.join(file), file).unwrap();
+	}
+	fs::write(tree.join(".quickdashignore"), "# build output\n/target/\n").unwrap();
+	fs::write(tree.join("src/.quickdashignore"), "gen/\n!keep.tmp\n").unwrap();
+
+	let names = |filter: &PathFilter| {
+		let (hashes, errors) = create_hashes(
+			&tree,
+			filter,
+			&[Algorithm::CRC32],
+			None,
+			None,
+			false,
+			1,
+			None,
+		)
+		.unwrap();
+		assert!(errors.is_empty());
+		hashes
+			.into_iter()
+			.map(
+				|(name, digests)| match digests[&Algorithm::CRC32].starts_with('-') {
+					true => format!("-{}", name),
+					false => name,
+				},
+			)
+			.collect::<Vec<_>>()
+	};
+
+	// Ignored files are listed, ignored directories left out
+	let filter = PathFilter::new(&["*.tmp"], &[]).unwrap();
+	assert_eq!(
+		names(&filter),
+		[
+			".quickdashignore",
+			"a.rs",
+			"-a.tmp",
+			"docs/e.md",
+			"src/.quickdashignore",
+			"src/b.rs",
+			"-src/b.tmp",
+			"-src/keep.tmp",
+		]
+	);
+
+	// Specified patterns take precedence over the tree's
+	let filter = PathFilter::new(&["!target", "/*.tmp"], &[]).unwrap();
+	let unignored = names(&filter);
+	assert!(unignored.contains(&"target/debug/d".to_string()));
+	assert!(unignored.contains(&"-a.tmp".to_string()));
+	assert!(unignored.contains(&"src/keep.tmp".to_string()));
+
+	let filter = PathFilter::new(&[], &["*.rs", "docs", "!src/b.rs"]).unwrap();
+	assert_eq!(names(&filter), ["a.rs", "docs/e.md"]);
+
+	fs::write(tree.join("docs/.quickdashignore"), "re:(\n").unwrap();
+	assert!(matches!(
+		create_hashes(
+			&tree,
+			&filter,
+			&[Algorithm::CRC32],
+			None,
+			None,
+			false,
+			1,
+			None
+		),
+		Err(Error::Pattern(_))
+	));
+}
+
+#[test]
+fn gitignore_and_vcs() {
+	let tree = scratch_dir("gitignore");
+	for dir in [".git/info", ".git/objects", ".hg", "src/target", "docs"] {
+		fs::create_dir_all(tree.join(dir)).unwrap();
+	}
+	for file in [
+		".git/objects/0a",
+		".hg/store",
+		"a.log",
+		"b.bak",
+		"src/c.rs",
+		"src/target/d",
+		"docs/e.o",
+		"re:x",
+	] {
+		fs::write(tree.join(file), file).unwrap();
+	}
+	fs::write(tree.join(".git/info/exclude"), "*.bak\n").unwrap();
+	// git has no regular expressions
+	fs::write(tree.join(".gitignore"), "*.log\n/docs/*.o\nre:x\nre:.*\n").unwrap();
+	fs::write(tree.join("src/.gitignore"), "target/\n").unwrap();
+
+	let names = |path: &std::path::Path, filter: &PathFilter| {
+		let (hashes, _) = create_hashes(
+			path,
+			filter,
+			&[Algorithm::CRC32],
+			None,
+			None,
+			false,
+			1,
+			None,
+		)
+		.unwrap();
+		hashes.into_keys().collect::<Vec<_>>()
+	};
+
+	let filter = PathFilter::default().respect_gitignore(true).skip_vcs(true);
+	assert_eq!(
+		names(&tree, &filter),
+		[".gitignore", "src/.gitignore", "src/c.rs"]
+	);
+
+	let filter = PathFilter::default().skip_vcs(true);
+	assert_eq!(names(&tree, &filter).len(), 8);
+	let filter = PathFilter::default().respect_gitignore(true);
+	assert!(names(&tree, &filter).contains(&".git/objects/0a".to_string()));
+
+	// The repository's patterns apply to its subdirectories' trees too
+	let filter = PathFilter::default().respect_gitignore(true);
+	fs::write(tree.join("docs/f.bak"), "f").unwrap();
+	fs::write(tree.join("docs/g"), "g").unwrap();
+	assert_eq!(names(&tree.join("docs"), &filter), ["g"]);
+}
+
+#[test]
+fn predicates() {
+	let tree = scratch_dir("predicates");
+	fs::create_dir_all(tree.join("sub")).unwrap();
+	fs::write(tree.join("small.txt"), "a").unwrap();
+	fs::write(tree.join("big.img"), vec![0; 4096]).unwrap();
+	fs::write(tree.join("sub/old.TXT"), "old").unwrap();
+	fs::write(tree.join(".txt"), "dotfile").unwrap();
+	let week_ago = SystemTime::now() - Duration::from_secs(7 * 86400);
+	let old = File::options()
+		.write(true)
+		.open(tree.join("sub/old.TXT"))
+		.unwrap();
+	old.set_modified(week_ago - Duration::from_secs(60))
+		.unwrap();
+
+	let names = |predicates: Vec<FilePredicate>| {
+		let filter = PathFilter::default().select(predicates);
+		let (hashes, _) = create_hashes(
+			&tree,
+			&filter,
+			&[Algorithm::CRC32],
+			None,
+			None,
+			false,
+			1,
+			None,
+		)
+		.unwrap();
+		hashes.into_keys().collect::<Vec<_>>()
+	};
+
+	assert_eq!(
+		names(vec![FilePredicate::MaxSize(1024)]),
+		[".txt", "small.txt", "sub/old.TXT"]
+	);
+	assert_eq!(names(vec![FilePredicate::MinSize(1024)]), ["big.img"]);
+	assert_eq!(
+		names(vec![FilePredicate::Extensions(vec![".txt".to_string()])]),
+		["small.txt", "sub/old.TXT"]
+	);
+	assert_eq!(
+		names(vec![FilePredicate::OlderThan(week_ago)]),
+		["sub/old.TXT"]
+	);
+	// All predicates have to be met
+	assert_eq!(
+		names(vec![
+			FilePredicate::NewerThan(week_ago),
+			FilePredicate::MaxSize(1)
+		]),
+		["small.txt"]
+	);
+
+	// Only the loaded digests of selected files are compared, and gone files
+	// are kept
+	let filter = PathFilter::default().select(vec![FilePredicate::MaxSize(1)]);
+	let mut loaded: BTreeMap<_, _> = ["big.img", "gone", "small.txt"]
+		.into_iter()
+		.map(|name| (name.to_string(), Digests::new()))
+		.collect();
+	filter.deselect(&tree, &mut loaded);
+	assert_eq!(
+		loaded.into_keys().collect::<Vec<_>>(),
+		["gone", "small.txt"]
+	);
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(tree.join("big.img"), fs::Permissions::from_mode(0o755)).unwrap();
+		assert_eq!(names(vec![FilePredicate::Permissions(0o111)]), ["big.img"]);
+	}
+}
+
+#[test]
+fn header_records_predicates() {
+	let predicates = [
+		FilePredicate::MinSize(10),
+		FilePredicate::NewerThan(SystemTime::now()),
+		FilePredicate::Owner(1000),
+		FilePredicate::Permissions(0o644),
+		FilePredicate::Extensions(vec!["txt".to_string(), "md".to_string()]),
+	];
+	let header =
+		ManifestHeader::new(vec![Algorithm::CRC32], None, false, vec![]).select(&predicates);
+
+	let mut out = Vec::new();
+	header.write(&mut out).unwrap();
+	let text = String::from_utf8(out).unwrap();
+	assert!(text.contains("# min-size: 10\n# owner: 1000\n# perm: 644\n# extensions: txt,md\n"));
+	assert!(!text.contains("newer"));
+
+	let lines: Vec<_> = text.lines().collect();
+	let parsed = ManifestHeader::parse(&lines).unwrap().unwrap();
+	assert_eq!(
+		parsed.predicates,
+		[&predicates[..1], &predicates[2..]].concat()
+	);
+	assert_eq!(parsed, header);
+
+	// IDs don't wrap around to root's
+	let text = text.replace("# owner: 1000", "# owner: 4294967296");
+	let lines: Vec<_> = text.lines().collect();
+	assert!(ManifestHeader::parse(&lines).is_err());
+}
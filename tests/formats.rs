@@ -0,0 +1,365 @@
+use std::{collections::BTreeMap, fs};
+
+use quickdash::{
+	hash_reader,
+	operations::{
+		detect_algorithm,
+		read_hashes,
+		read_hashes_lenient,
+		write_hashes,
+		DetectionMethod,
+		ManifestFormat,
+		ManifestHeader,
+	},
+	Algorithm,
+	Digests,
+	Error,
+};
+
+mod common;
+use common::scratch_dir;
+
+fn digests(algo: Algorithm, digest: &str) -> Digests {
+	Digests::from([(algo, digest.to_string())])
+}
+
+#[test]
+fn coreutils_round_trip() {
+	let dir = scratch_dir("formats");
+	let manifest = dir.join("MD5SUMS");
+
+	let md5 = "D41D8CD98F00B204E9800998ECF8427E";
+	let hashes = BTreeMap::from([
+		("plain".to_string(), digests(Algorithm::MD5, md5)),
+		("back\\slash".to_string(), digests(Algorithm::MD5, md5)),
+		("new\nline".to_string(), digests(Algorithm::MD5, md5)),
+	]);
+	let header = ManifestHeader::new(vec![Algorithm::MD5], None, false, vec![]);
+	write_hashes(
+		&manifest,
+		&dir,
+		ManifestFormat::Coreutils,
+		&header,
+		hashes.clone(),
+	)
+	.unwrap();
+
+	let expected = "\\d41d8cd98f00b204e9800998ecf8427e  \
+	                back\\\\slash\n\\d41d8cd98f00b204e9800998ecf8427e  \
+	                new\\nline\nd41d8cd98f00b204e9800998ecf8427e  plain\n";
+	assert_eq!(fs::read_to_string(&manifest).unwrap(), expected);
+
+	fs::write(&manifest, expected.replace("  plain", " *plain")).unwrap();
+	let (header, loaded) =
+		read_hashes(&manifest, ManifestFormat::Coreutils, &[Algorithm::MD5]).unwrap();
+	assert_eq!(header, None);
+	assert_eq!(loaded, hashes);
+}
+
+#[test]
+fn sfv_round_trip() {
+	let dir = scratch_dir("sfv");
+	fs::create_dir(dir.join("sub")).unwrap();
+	fs::write(dir.join("with space"), "quickdash").unwrap();
+	fs::write(dir.join("sub/b"), "sfv").unwrap();
+	let manifest = dir.join("checksums.txt");
+
+	let crc = |data: &[u8]| hash_reader(Algorithm::CRC32, &mut &data[..]).unwrap();
+	let hashes = BTreeMap::from([
+		("sub/b".to_string(), digests(Algorithm::CRC32, &crc(b"sfv"))),
+		(
+			"with space".to_string(),
+			digests(Algorithm::CRC32, &crc(b"quickdash")),
+		),
+		("ignored".to_string(), digests(Algorithm::CRC32, "--------")),
+	]);
+	let header = ManifestHeader::new(vec![Algorithm::CRC32], None, false, vec![]);
+	write_hashes(
+		&manifest,
+		&dir,
+		ManifestFormat::Sfv,
+		&header,
+		hashes.clone(),
+	)
+	.unwrap();
+
+	let written = fs::read_to_string(&manifest).unwrap();
+	let lines: Vec<_> = written.lines().collect();
+	assert!(lines[0].starts_with("; Generated by QuickDash v"));
+	assert_eq!(lines[1], ";");
+	assert!(lines[2].starts_with(";            3  ") && lines[2].ends_with(" sub/b"));
+	assert!(lines[3].starts_with(";            9  ") && lines[3].ends_with(" with space"));
+	assert_eq!(
+		lines[4..],
+		[
+			format!("sub/b {}", crc(b"sfv")),
+			format!("with space {}", crc(b"quickdash"))
+		]
+	);
+
+	let mut expected = hashes;
+	expected.remove("ignored");
+	let (header, loaded) =
+		read_hashes(&manifest, ManifestFormat::Sfv, &[Algorithm::CRC32]).unwrap();
+	assert_eq!(header, None);
+	assert_eq!(loaded, expected);
+
+	// Whatever the manifest is named, SFV digests are CRC32 ones
+	let detection = detect_algorithm(&manifest, ManifestFormat::Sfv, None)
+		.unwrap()
+		.unwrap();
+	assert_eq!(
+		(detection.algorithm, detection.method),
+		(Algorithm::CRC32, DetectionMethod::Format)
+	);
+
+	fs::write(&manifest, "broken 123\n").unwrap();
+	assert!(matches!(
+		read_hashes(&manifest, ManifestFormat::Sfv, &[Algorithm::CRC32]),
+		Err(Error::HashesFileParsingFailure(_))
+	));
+}
+
+#[test]
+fn sfv_parsing() {
+	let dir = scratch_dir("sfv-parse");
+	let manifest = dir.join("files.sfv");
+	fs::write(
+		&manifest,
+		"; comment\r\nsub\\a.bin 0a1b2c3d\r\nname with spaces DEADBEEF\n\n",
+	)
+	.unwrap();
+
+	let (_, loaded) = read_hashes(&manifest, ManifestFormat::Sfv, &[Algorithm::CRC32]).unwrap();
+	assert_eq!(
+		loaded,
+		BTreeMap::from([
+			(
+				"name with spaces".to_string(),
+				digests(Algorithm::CRC32, "DEADBEEF")
+			),
+			(
+				"sub/a.bin".to_string(),
+				digests(Algorithm::CRC32, "0A1B2C3D")
+			),
+		])
+	);
+
+	fs::write(&manifest, "broken 123\n").unwrap();
+	assert!(matches!(
+		read_hashes(&manifest, ManifestFormat::Sfv, &[Algorithm::CRC32]),
+		Err(Error::HashesFileParsingFailure(_))
+	));
+}
+
+#[test]
+fn bsd_round_trip() {
+	let dir = scratch_dir("bsd");
+	let manifest = dir.join("CHECKSUMS");
+
+	// As written by `sha256sum --tag` and `md5sum --tag`
+	let expected = concat!(
+		"SHA256 (a b) = 9ba1c8261f517f397ed3740f4334845849d6305a262d749887e31f3912608ada\n",
+		"MD5 (a b) = fab57c6c74fc77d4bff17fb1678bcfe8\n",
+		"\\SHA256 (back\\\\slash) = \
+		 2d711642b726b04401627ca9fbac32f5c8530fb1903cc4db02258717921a4881\n",
+		"\\MD5 (back\\\\slash) = 9dd4e461268c8034f5c8564e155c67a6\n",
+	);
+	let hashes = BTreeMap::from([
+		(
+			"a b".to_string(),
+			Digests::from([
+				(
+					Algorithm::MD5,
+					"FAB57C6C74FC77D4BFF17FB1678BCFE8".to_string(),
+				),
+				(
+					Algorithm::SHA2256,
+					"9BA1C8261F517F397ED3740F4334845849D6305A262D749887E31F3912608ADA".to_string(),
+				),
+			]),
+		),
+		(
+			"back\\slash".to_string(),
+			Digests::from([
+				(
+					Algorithm::MD5,
+					"9DD4E461268C8034F5C8564E155C67A6".to_string(),
+				),
+				(
+					Algorithm::SHA2256,
+					"2D711642B726B04401627CA9FBAC32F5C8530FB1903CC4DB02258717921A4881".to_string(),
+				),
+			]),
+		),
+	]);
+	let header = ManifestHeader::new(
+		vec![Algorithm::MD5, Algorithm::SHA2256],
+		None,
+		false,
+		vec![],
+	);
+	write_hashes(
+		&manifest,
+		&dir,
+		ManifestFormat::Bsd,
+		&header,
+		hashes.clone(),
+	)
+	.unwrap();
+	assert_eq!(fs::read_to_string(&manifest).unwrap(), expected);
+
+	let algos = [Algorithm::MD5, Algorithm::SHA2256];
+	let (_, loaded) = read_hashes(&manifest, ManifestFormat::Bsd, &algos).unwrap();
+	assert_eq!(loaded, hashes);
+	// `*SUMS` files holding tagged lines are read as such
+	let sums = dir.join("SHA256SUMS");
+	fs::copy(&manifest, &sums).unwrap();
+	let (_, loaded) = read_hashes(&sums, ManifestFormat::Coreutils, &algos).unwrap();
+	assert_eq!(loaded, hashes);
+
+	fs::write(&manifest, "MD4 (a) = 31d6cfe0d16ae931b73c59d7e0c089c0\n").unwrap();
+	match read_hashes(&manifest, ManifestFormat::Bsd, &algos) {
+		Err(Error::HashesFileParsingFailure(e)) => assert_eq!(e.reason, "unknown algorithm"),
+		other => panic!("expected a parse error, got {:?}", other),
+	}
+}
+
+#[test]
+fn hashdeep_round_trip() {
+	let dir = scratch_dir("hashdeep");
+	fs::create_dir(dir.join("sub")).unwrap();
+	fs::write(dir.join("a b"), "quickdash").unwrap();
+	fs::write(dir.join("sub/c,d"), "x").unwrap();
+	let manifest = dir.join("files.hashdeep");
+
+	// As written by `hashdeep -l -r .`, in traversal order
+	fs::write(
+		&manifest,
+		concat!(
+			"%%%% HASHDEEP-1.0\n",
+			"%%%% size,md5,sha256,filename\n",
+			"## Invoked from: /home/user/tree\n",
+			"## $ hashdeep -l -r .\n",
+			"##\n",
+			"1,9dd4e461268c8034f5c8564e155c67a6,",
+			"2d711642b726b04401627ca9fbac32f5c8530fb1903cc4db02258717921a4881,./sub/c,d\n",
+			"9,fab57c6c74fc77d4bff17fb1678bcfe8,",
+			"9ba1c8261f517f397ed3740f4334845849d6305a262d749887e31f3912608ada,./a b\n",
+		),
+	)
+	.unwrap();
+	let algos = [Algorithm::MD5, Algorithm::SHA2256];
+	let (_, loaded) = read_hashes(&manifest, ManifestFormat::Hashdeep, &algos).unwrap();
+	let hashes = BTreeMap::from([
+		(
+			"a b".to_string(),
+			Digests::from([
+				(
+					Algorithm::MD5,
+					"FAB57C6C74FC77D4BFF17FB1678BCFE8".to_string(),
+				),
+				(
+					Algorithm::SHA2256,
+					"9BA1C8261F517F397ED3740F4334845849D6305A262D749887E31F3912608ADA".to_string(),
+				),
+			]),
+		),
+		(
+			"sub/c,d".to_string(),
+			Digests::from([
+				(
+					Algorithm::MD5,
+					"9DD4E461268C8034F5C8564E155C67A6".to_string(),
+				),
+				(
+					Algorithm::SHA2256,
+					"2D711642B726B04401627CA9FBAC32F5C8530FB1903CC4DB02258717921A4881".to_string(),
+				),
+			]),
+		),
+	]);
+	assert_eq!(loaded, hashes);
+
+	let header = ManifestHeader::new(algos.to_vec(), None, false, vec![]);
+	write_hashes(
+		&manifest,
+		&dir,
+		ManifestFormat::Hashdeep,
+		&header,
+		hashes.clone(),
+	)
+	.unwrap();
+	let written = fs::read_to_string(&manifest).unwrap();
+	let lines: Vec<_> = written.lines().collect();
+	assert_eq!(
+		lines[..2],
+		["%%%% HASHDEEP-1.0", "%%%% size,md5,sha256,filename"]
+	);
+	assert_eq!(lines[2], format!("## Invoked from: {}", dir.display()));
+	assert_eq!(
+		lines[5..],
+		[
+			"9,fab57c6c74fc77d4bff17fb1678bcfe8,\
+			 9ba1c8261f517f397ed3740f4334845849d6305a262d749887e31f3912608ada,a b",
+			"1,9dd4e461268c8034f5c8564e155c67a6,\
+			 2d711642b726b04401627ca9fbac32f5c8530fb1903cc4db02258717921a4881,sub/c,d",
+		]
+	);
+	let (_, loaded) = read_hashes(&manifest, ManifestFormat::Hash, &algos).unwrap();
+	assert_eq!(loaded, hashes);
+}
+
+#[test]
+fn parse_errors_name_the_line() {
+	let dir = scratch_dir("parse");
+	let manifest = dir.join("MD5SUMS");
+	fs::write(
+		&manifest,
+		"d41d8cd98f00b204e9800998ecf8427e  plain\nnot a digest\n",
+	)
+	.unwrap();
+
+	match read_hashes(&manifest, ManifestFormat::Coreutils, &[Algorithm::MD5]) {
+		Err(Error::HashesFileParsingFailure(e)) => {
+			assert_eq!((&e.file, e.line), (&manifest, Some(2)));
+			assert_eq!(e.text, "not a digest");
+		}
+		other => panic!("expected a parse error, got {:?}", other),
+	}
+
+	let algos = [Algorithm::MD5, Algorithm::SHA1];
+	match read_hashes(&manifest, ManifestFormat::Coreutils, &algos) {
+		Err(Error::HashesFileParsingFailure(e)) => {
+			assert_eq!(e.line, Some(1));
+			assert_eq!(e.text, "d41d8cd98f00b204e9800998ecf8427e  plain");
+		}
+		other => panic!("expected a parse error, got {:?}", other),
+	}
+}
+
+#[test]
+fn lenient_parsing() {
+	let dir = scratch_dir("lenient");
+	let manifest = dir.join("MD5SUMS");
+	fs::write(
+		&manifest,
+		"# comment\r\nd41d8cd98f00b204e9800998ecf8427e  a\r\n\r\n; \
+		 comment\nbroken\nd41d8cd98f00b204e9800998ecf8427e  b\n\n",
+	)
+	.unwrap();
+
+	let (_, loaded, rejected) =
+		read_hashes_lenient(&manifest, ManifestFormat::Coreutils, &[Algorithm::MD5]).unwrap();
+	assert_eq!(loaded.keys().collect::<Vec<_>>(), ["a", "b"]);
+	assert_eq!(rejected.len(), 1);
+	assert_eq!(
+		(rejected[0].line, &rejected[0].text[..]),
+		(Some(5), "broken")
+	);
+
+	assert!(matches!(
+		read_hashes(&manifest, ManifestFormat::Coreutils, &[Algorithm::MD5]),
+		Err(Error::HashesFileParsingFailure(e)) if e.line == Some(5)
+	));
+}
@@ -0,0 +1,108 @@
+use std::fs;
+
+use quickdash::{
+	hash_reader_keyed,
+	hash_string,
+	operations::{
+		create_hashes,
+		read_hashes,
+		write_hashes,
+		ManifestFormat,
+		ManifestHeader,
+		PathFilter,
+	},
+	Algorithm,
+	Error,
+	HashKey,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn keyed_digests() {
+	let data = b"quickdash";
+	let key = HashKey::new([7; 32]);
+	assert_eq!(
+		hash_reader_keyed(Algorithm::BLAKE3, &key, &mut &data[..]).unwrap(),
+		hash_string(blake3::keyed_hash(&[7; 32], data).as_bytes())
+	);
+
+	// Other key lengths are derived into a BLAKE3 key
+	let short = HashKey::new(*b"secret");
+	let keyed = hash_reader_keyed(Algorithm::BLAKE3, &short, &mut &data[..]).unwrap();
+	assert_eq!(keyed.len(), Algorithm::BLAKE3.hexlen());
+	assert_ne!(
+		keyed,
+		hash_reader_keyed(Algorithm::BLAKE3, &key, &mut &data[..]).unwrap()
+	);
+
+	assert!(hash_reader_keyed(Algorithm::SHA3256, &short, &mut &data[..]).is_ok());
+	assert!(hash_reader_keyed(Algorithm::CRC32, &short, &mut &data[..]).is_err());
+}
+
+#[test]
+fn keyed_manifests() {
+	let tree = scratch_dir("keyed");
+	fs::write(tree.join("file"), "quickdash").unwrap();
+	let manifest = tree.join("tree.hash");
+
+	let key = HashKey::new(*b"secret");
+	let filter = PathFilter::default();
+	let algos = [Algorithm::SHA2256];
+	let header = ManifestHeader::new(algos.to_vec(), None, false, vec![]).with_key(&key);
+	let (plain, _) = create_hashes(&tree, &filter, &algos, None, None, false, 1, None).unwrap();
+	let (hashes, _) =
+		create_hashes(&tree, &filter, &algos, Some(&key), None, false, 1, None).unwrap();
+	assert_ne!(hashes["file"], plain["file"]);
+	write_hashes(
+		&manifest,
+		&tree,
+		ManifestFormat::Hash,
+		&header,
+		hashes.clone(),
+	)
+	.unwrap();
+
+	let (loaded, loaded_hashes) = read_hashes(&manifest, ManifestFormat::Hash, &[]).unwrap();
+	let loaded = loaded.unwrap();
+	assert_eq!(loaded, header);
+	assert_eq!(loaded.version, 2);
+	assert_eq!(loaded_hashes["file"], hashes["file"]);
+
+	assert!(loaded.check_key(Some(&key)).is_ok());
+	assert!(matches!(loaded.check_key(None), Err(Error::Key(_))));
+	assert!(matches!(
+		loaded.check_key(Some(&HashKey::new(*b"other"))),
+		Err(Error::Key(_))
+	));
+	let unkeyed = ManifestHeader::new(algos.to_vec(), None, false, vec![]);
+	assert!(matches!(unkeyed.check_key(Some(&key)), Err(Error::Key(_))));
+
+	assert!(matches!(
+		create_hashes(
+			&tree,
+			&filter,
+			&[Algorithm::MD5],
+			Some(&key),
+			None,
+			false,
+			1,
+			None
+		),
+		Err(Error::Key(_))
+	));
+}
+
+#[cfg(unix)]
+#[test]
+fn keys_from_env_are_raw_bytes() {
+	use std::{env, ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	let var = "QUICKDASH_TEST_KEY";
+	env::set_var(var, OsStr::from_bytes(b"k\xffy"));
+	assert!(HashKey::from_env(var).unwrap() == HashKey::new(*b"k\xffy"));
+
+	env::remove_var(var);
+	assert!(matches!(HashKey::from_env(var), Err(Error::Key(_))));
+}
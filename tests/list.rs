@@ -0,0 +1,141 @@
+use std::{fs, io::ErrorKind};
+
+use quickdash::{
+	operations::{create_hashes, create_hashes_from_list, FilePredicate, PathFilter},
+	Algorithm,
+};
+
+mod common;
+use common::scratch_dir;
+
+#[test]
+fn hashes_from_list() {
+	let tree = scratch_dir("list");
+	fs::create_dir_all(tree.join("sub")).unwrap();
+	for file in ["a", "b", "sub/c"] {
+		fs::write(tree.join(file), file).unwrap();
+	}
+
+	let algos = [Algorithm::CRC32];
+	let (walked, _) = create_hashes(
+		&tree,
+		&PathFilter::default(),
+		&algos,
+		None,
+		None,
+		false,
+		1,
+		None,
+	)
+	.unwrap();
+
+	let absolute = tree.join("sub/c").to_str().unwrap().to_string();
+	let names = [
+		"./a",
+		"a",
+		&absolute,
+		"sub",
+		"missing",
+		"../a",
+		"/elsewhere",
+	]
+	.map(String::from);
+	let (hashes, errors) = create_hashes_from_list(
+		&tree,
+		&names,
+		&PathFilter::default(),
+		&algos,
+		None,
+		None,
+		false,
+		1,
+		None,
+	)
+	.unwrap();
+
+	// Only the listed files, digested like when walked
+	assert_eq!(hashes.keys().collect::<Vec<_>>(), ["a", "sub/c"]);
+	assert_eq!(hashes["a"], walked["a"]);
+	assert_eq!(hashes["sub/c"], walked["sub/c"]);
+
+	let errors: Vec<_> = errors.iter().map(|e| (e.file.as_str(), e.kind)).collect();
+	assert_eq!(
+		errors,
+		[
+			("../a", ErrorKind::InvalidInput),
+			("/elsewhere", ErrorKind::InvalidInput),
+			("missing", ErrorKind::NotFound),
+			("sub", ErrorKind::InvalidInput),
+		]
+	);
+}
+
+#[test]
+fn lists_are_filtered_like_walks() {
+	let scratch = scratch_dir("filtered-list");
+	let tree = scratch.join("tree");
+	fs::create_dir_all(tree.join("sub/deep")).unwrap();
+	fs::create_dir_all(scratch.join("outside")).unwrap();
+	for file in ["a", "b.tmp", "longer", "sub/c", "sub/deep/d"] {
+		fs::write(tree.join(file), file).unwrap();
+	}
+	fs::write(scratch.join("outside/secret"), "secret").unwrap();
+
+	let filter = PathFilter::new(&["*.tmp"], &[])
+		.unwrap()
+		.select(vec![FilePredicate::MinSize(2)]);
+	let algos = [Algorithm::CRC32];
+	let hash_list = |names: &[&str], follow_symlinks| {
+		let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+		create_hashes_from_list(
+			&tree,
+			&names,
+			&filter,
+			&algos,
+			None,
+			Some(1),
+			follow_symlinks,
+			1,
+			None,
+		)
+		.unwrap()
+	};
+
+	// The listed files are the ones walking the tree finds
+	let all = ["a", "b.tmp", "longer", "sub/c", "sub/deep/d"];
+	let (walked, _) = create_hashes(&tree, &filter, &algos, None, Some(1), false, 1, None).unwrap();
+	let (hashes, errors) = hash_list(&all, false);
+	assert_eq!(hashes, walked);
+	assert_eq!(
+		hashes.keys().collect::<Vec<_>>(),
+		["b.tmp", "longer", "sub/c"]
+	);
+	assert!(errors.is_empty());
+
+	#[cfg(unix)]
+	{
+		use std::{os::unix::fs::symlink, path::Path};
+		symlink(Path::new("sub/c"), tree.join("inner")).unwrap();
+		symlink(Path::new("../outside/secret"), tree.join("escape")).unwrap();
+		symlink(Path::new("../outside"), tree.join("escaped")).unwrap();
+		let links = ["inner", "escape", "escaped/secret"];
+
+		let (hashes, errors) = hash_list(&links, false);
+		assert!(hashes.is_empty());
+		let errors: Vec<_> = errors.iter().map(|e| e.file.as_str()).collect();
+		assert_eq!(errors, ["escape", "escaped/secret", "inner"]);
+
+		// Followed symlinks still can't lead out of the tree
+		let (hashes, errors) = hash_list(&links, true);
+		assert_eq!(hashes.keys().collect::<Vec<_>>(), ["inner"]);
+		assert_eq!(hashes["inner"], walked["sub/c"]);
+		let errors: Vec<_> = errors.iter().map(|e| (e.file.as_str(), e.kind)).collect();
+		assert_eq!(
+			errors,
+			[
+				("escape", ErrorKind::InvalidInput),
+				("escaped/secret", ErrorKind::InvalidInput),
+			]
+		);
+	}
+}
@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, fs, io::Write, path::Path};
+
+use quickdash::{
+	operations::{
+		create_hashes_streaming,
+		ManifestFormat,
+		ManifestHeader,
+		ManifestReader,
+		ManifestWriter,
+	},
+	Algorithm,
+	Digests,
+};
+
+mod common;
+use common::scratch_dir;
+
+fn crc(digest: &str) -> Digests {
+	BTreeMap::from([(Algorithm::CRC32, digest.to_string())])
+}
+
+#[test]
+fn streamed_manifest_is_sorted() {
+	let dir = scratch_dir("manifest");
+	let manifest = dir.join("dir.hash");
+	let header = ManifestHeader::new(vec![Algorithm::CRC32], None, false, vec![]);
+
+	// More than a single sorted run's worth
+	let mut writer =
+		ManifestWriter::create(&manifest, &dir, ManifestFormat::Hash, &header).unwrap();
+	for i in (0..150_000).rev() {
+		writer
+			.append(&format!("file{:06}", i), &crc("AAAAAAAA"))
+			.unwrap();
+	}
+	writer.append("file000042", &crc("BBBBBBBB")).unwrap();
+	assert!(writer.journal_path().exists());
+	let journal = writer.journal_path().to_path_buf();
+	writer.finish().unwrap();
+	assert!(!journal.exists());
+
+	let reader = ManifestReader::open(&manifest, ManifestFormat::Hash, &[]).unwrap();
+	assert_eq!(reader.header().unwrap().algorithms, [Algorithm::CRC32]);
+	let entries: Vec<_> = reader.map(Result::unwrap).collect();
+	assert_eq!(entries.len(), 150_001);
+	assert!(entries.windows(2).all(|w| w[0].name < w[1].name));
+	// The manifest itself is listed as ignored
+	assert_eq!(entries[0].name, manifest.to_string_lossy());
+	assert_eq!(entries[1].name, "file000000");
+	assert_eq!(entries[43].digests, crc("BBBBBBBB"));
+}
+
+#[test]
+fn resumed_run_matches_uninterrupted_one() {
+	let dir = scratch_dir("resume");
+	let tree = dir.join("tree");
+	fs::create_dir_all(&tree).unwrap();
+	for name in ["kept", "changed", "deleted"] {
+		fs::write(tree.join(name), name).unwrap();
+	}
+	let header = ManifestHeader::new(vec![Algorithm::MD5], None, false, vec![]);
+	let create = |manifest: &Path, resume: bool| {
+		let mut writer = match resume {
+			true => ManifestWriter::resume(manifest, &tree, ManifestFormat::Hash, &header),
+			false => ManifestWriter::create(manifest, &tree, ManifestFormat::Hash, &header),
+		}
+		.unwrap();
+		create_hashes_streaming(&tree, &header, &[], None, 1, None, &mut writer).unwrap();
+		writer
+	};
+
+	// Interrupted, leaving the journal and an incomplete line behind
+	let manifest = dir.join("resumed.hash");
+	let journal = create(&manifest, false).journal_path().to_path_buf();
+	let mut cut_short = fs::OpenOptions::new().append(true).open(&journal).unwrap();
+	write!(cut_short, "D41D8CD98F00B204E9800998ECF8427E\t-\tke").unwrap();
+
+	fs::write(tree.join("changed"), "changed again").unwrap();
+	fs::remove_file(tree.join("deleted")).unwrap();
+	fs::write(tree.join("added"), "added").unwrap();
+	create(&manifest, true).finish().unwrap();
+	assert!(!journal.exists());
+
+	let expected = dir.join("expected.hash");
+	create(&expected, false).finish().unwrap();
+	let entries = |manifest: &Path| {
+		ManifestReader::open(manifest, ManifestFormat::Hash, &[])
+			.unwrap()
+			.map(|entry| {
+				let entry = entry.unwrap();
+				(entry.name, entry.digests)
+			})
+			.filter(|(name, _)| !name.ends_with(".hash"))
+			.collect::<Vec<_>>()
+	};
+	let resumed = entries(&manifest);
+	assert_eq!(resumed, entries(&expected));
+	assert_eq!(
+		resumed
+			.iter()
+			.map(|(name, _)| &name[..])
+			.collect::<Vec<_>>(),
+		["added", "changed", "kept"]
+	);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn resumed_run_drops_files_it_fails_to_rehash() {
+	let dir = scratch_dir("resume-unreadable");
+	let tree = dir.join("tree");
+	fs::create_dir_all(&tree).unwrap();
+	for name in ["kept", "changed"] {
+		fs::write(tree.join(name), name).unwrap();
+	}
+	let header = ManifestHeader::new(vec![Algorithm::MD5], None, true, vec![]);
+	let manifest = dir.join("resumed.hash");
+
+	let mut writer =
+		ManifestWriter::create(&manifest, &tree, ManifestFormat::Hash, &header).unwrap();
+	create_hashes_streaming(&tree, &header, &[], None, 1, None, &mut writer).unwrap();
+	drop(writer);
+
+	// Reading it fails even as root
+	fs::remove_file(tree.join("changed")).unwrap();
+	std::os::unix::fs::symlink("/proc/self/mem", tree.join("changed")).unwrap();
+	let mut writer =
+		ManifestWriter::resume(&manifest, &tree, ManifestFormat::Hash, &header).unwrap();
+	let errors = create_hashes_streaming(&tree, &header, &[], None, 1, None, &mut writer).unwrap();
+	writer.finish().unwrap();
+	assert_eq!(errors.len(), 1);
+	assert_eq!(errors[0].file, "changed");
+
+	let names: Vec<_> = ManifestReader::open(&manifest, ManifestFormat::Hash, &[])
+		.unwrap()
+		.map(|entry| entry.unwrap().name)
+		.filter(|name| !name.ends_with(".hash"))
+		.collect();
+	assert_eq!(names, ["kept"]);
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use quickdash::{
+	operations::{write_report, CompareFileResult, CompareResult, ReportDigests, ReportFormat},
+	Algorithm,
+	Digests,
+	Error,
+};
+
+fn crc(digest: &str) -> Digests {
+	BTreeMap::from([(Algorithm::CRC32, digest.to_string())])
+}
+
+#[test]
+fn ndjson_report() {
+	let results = vec![CompareResult::FileAdded("new \"file\"".to_string())];
+	let file_results = vec![
+		CompareFileResult::FileMatches("same".to_string()),
+		CompareFileResult::FileDiffers {
+			file: "changed".to_string(),
+			was_hash: "AAAAAAAA".to_string(),
+			new_hash: "BBBBBBBB".to_string(),
+		},
+	];
+
+	let digests = ReportDigests {
+		current: BTreeMap::from([
+			("new \"file\"".to_string(), crc("CCCCCCCC")),
+			("same".to_string(), crc("DDDDDDDD")),
+		]),
+		loaded: BTreeMap::from([("same".to_string(), crc("DDDDDDDD"))]),
+	};
+
+	let mut out = Vec::new();
+	let result = write_report(
+		&mut out,
+		ReportFormat::Ndjson,
+		&[Algorithm::CRC32],
+		Ok((results, file_results)),
+		&digests,
+		false,
+		None,
+	);
+	assert!(matches!(result, Err(Error::NFilesDiffer(1))));
+
+	let out = String::from_utf8(out).unwrap();
+	let lines: Vec<_> = out.lines().collect();
+	assert_eq!(lines.len(), 4);
+	assert!(lines[0].contains(
+		r#""path": "new \"file\"", "status": "added", "expected": null, "actual": "CCCCCCCC""#
+	));
+	assert!(
+		lines[1].contains(r#""status": "matches", "expected": "DDDDDDDD", "actual": "DDDDDDDD""#)
+	);
+	assert!(lines[2].contains(
+		r#""status": "differs", "expected": "AAAAAAAA", "actual": "BBBBBBBB", "algorithm": "CRC32""#
+	));
+	assert!(lines[3].starts_with(r#"{"type": "summary", "files": 3, "matches": 1, "differs": 1"#));
+	assert!(lines[3].ends_with(r#""exit_code": 4}"#));
+}
+
+#[test]
+fn junit_and_tap_reports() {
+	let results = || {
+		Ok((
+			vec![CompareResult::FileIgnored("skip<me>".to_string())],
+			vec![CompareFileResult::FileDiffers {
+				file: "changed".to_string(),
+				was_hash: "AAAAAAAA".to_string(),
+				new_hash: "BBBBBBBB".to_string(),
+			}],
+		))
+	};
+
+	let mut junit = Vec::new();
+	write_report(
+		&mut junit,
+		ReportFormat::Junit,
+		&[Algorithm::CRC32],
+		results(),
+		&ReportDigests::default(),
+		false,
+		None,
+	)
+	.unwrap_err();
+	let junit = String::from_utf8(junit).unwrap();
+	assert!(junit.contains(r#"tests="2" failures="1" errors="0" skipped="1""#));
+	assert!(junit.contains(r#"name="skip&lt;me&gt;""#));
+	assert!(junit.contains(r#"<failure message="File doesn&apos;t match" type="FileDiffers">Was: AAAAAAAA&#10;Is : BBBBBBBB</failure>"#));
+
+	let mut tap = Vec::new();
+	write_report(
+		&mut tap,
+		ReportFormat::Tap,
+		&[Algorithm::CRC32],
+		results(),
+		&ReportDigests::default(),
+		false,
+		None,
+	)
+	.unwrap_err();
+	let tap = String::from_utf8(tap).unwrap();
+	let lines: Vec<_> = tap.lines().collect();
+	assert_eq!(
+		lines[..4],
+		[
+			"TAP version 13",
+			"1..2",
+			"ok 1 - skip<me> # SKIP File ignored",
+			"not ok 2 - changed"
+		]
+	);
+}
+
+#[test]
+fn moves_only_fail_audits() {
+	let results = || {
+		Ok((
+			vec![CompareResult::FileMoved {
+				from: "old".to_string(),
+				to: "new".to_string(),
+			}],
+			vec![],
+		))
+	};
+	let report = |format, audit| {
+		let mut out = Vec::new();
+		let result = write_report(
+			&mut out,
+			format,
+			&[Algorithm::CRC32],
+			results(),
+			&ReportDigests::default(),
+			audit,
+			None,
+		);
+		(result.is_ok(), String::from_utf8(out).unwrap())
+	};
+
+	let (passed, junit) = report(ReportFormat::Junit, false);
+	assert!(passed);
+	assert!(junit.contains(r#"tests="1" failures="0" errors="0" skipped="0""#));
+	assert!(junit.contains("<system-out>File moved from &quot;old&quot;</system-out>"));
+	let (passed, tap) = report(ReportFormat::Tap, false);
+	assert!(passed);
+	assert!(tap.contains("\nok 1 - new\n  ---\n"));
+
+	let (passed, junit) = report(ReportFormat::Junit, true);
+	assert!(!passed);
+	assert!(junit.contains(r#"tests="1" failures="0" errors="1" skipped="0""#));
+	assert!(junit.contains(r#"type="FileMoved""#));
+	let (passed, tap) = report(ReportFormat::Tap, true);
+	assert!(!passed);
+	assert!(tap.contains("\nnot ok 1 - new\n"));
+}
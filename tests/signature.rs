@@ -0,0 +1,149 @@
+use std::{fs, path::Path};
+
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use ed25519_dalek::SigningKey;
+use quickdash::{
+	operations::{sign_manifest, signature_path, verify_manifest, PublicKey, SecretKey},
+	Error,
+};
+
+mod common;
+use common::scratch_dir;
+
+/// The libsodium operations and memory limits minisign encrypts a secret key
+/// with, and the scrypt output they make for `PASSWORD` and the salt 0..32,
+/// as computed by Python's `hashlib.scrypt()`.
+struct Kdf {
+	opslimit: u64,
+	memlimit: u64,
+	stream: &'static str,
+}
+
+const PASSWORD: &str = "correct horse";
+
+/// A minisign secret key, encrypted if `kdf` is specified, and its public key
+/// as `minisign -G` prints it.
+fn write_key(path: &Path, seed: u8, id: [u8; 8], kdf: Option<&Kdf>) -> String {
+	let key = SigningKey::from_bytes(&[seed; 32]);
+	let checksum = Blake2b::<U32>::new()
+		.chain_update(b"Ed")
+		.chain_update(id)
+		.chain_update(key.to_keypair_bytes())
+		.finalize();
+	let mut keynum_sk = id.to_vec();
+	keynum_sk.extend(key.to_keypair_bytes());
+	keynum_sk.extend(checksum);
+
+	let mut bytes = match kdf {
+		Some(kdf) => {
+			let stream = (0..kdf.stream.len())
+				.step_by(2)
+				.map(|i| u8::from_str_radix(&kdf.stream[i..i + 2], 16).unwrap());
+			keynum_sk
+				.iter_mut()
+				.zip(stream)
+				.for_each(|(byte, key)| *byte ^= key);
+			let mut bytes = b"EdScB2".to_vec();
+			bytes.extend((0..32).collect::<Vec<u8>>());
+			bytes.extend(kdf.opslimit.to_le_bytes());
+			bytes.extend(kdf.memlimit.to_le_bytes());
+			bytes
+		}
+		None => {
+			let mut bytes = b"Ed\0\0B2".to_vec();
+			bytes.extend([0; 32 + 8 + 8]);
+			bytes
+		}
+	};
+	bytes.extend(keynum_sk);
+	fs::write(
+		path,
+		format!("untrusted comment: test key\n{}\n", base64::encode(bytes)),
+	)
+	.unwrap();
+
+	let mut public = b"Ed".to_vec();
+	public.extend(id);
+	public.extend(key.verifying_key().to_bytes());
+	base64::encode(public)
+}
+
+#[test]
+fn signed_manifests() {
+	let dir = scratch_dir("signature");
+	let manifest = dir.join("tree.hash");
+	let signed = b"1A2B3C4D  file\n";
+	fs::write(&manifest, signed).unwrap();
+
+	let public = write_key(&dir.join("key.sec"), 1, *b"12345678", None);
+	let secret = SecretKey::open(&dir.join("key.sec")).unwrap();
+	let public = PublicKey::open(&public).unwrap();
+	assert_eq!(secret.public_key(), public);
+
+	let signature = signature_path(&manifest);
+	assert_eq!(signature, dir.join("tree.hash.minisig"));
+	let signer = sign_manifest(&manifest, &signature, &secret).unwrap();
+	assert_eq!(signer.key_id, "3837363534333231");
+	assert!(signer.trusted_comment.contains("file:tree.hash"));
+	assert_eq!(
+		verify_manifest(&manifest, signed, &signature, &public).unwrap(),
+		signer
+	);
+
+	let other = write_key(&dir.join("other.sec"), 2, *b"87654321", None);
+	let other = PublicKey::open(&other).unwrap();
+	assert!(matches!(
+		verify_manifest(&manifest, signed, &signature, &other),
+		Err(Error::Signature { .. })
+	));
+
+	let contents = fs::read_to_string(&signature).unwrap();
+	fs::write(&signature, contents.replace("hashed", "hashes")).unwrap();
+	assert!(matches!(
+		verify_manifest(&manifest, signed, &signature, &public),
+		Err(Error::Signature { .. })
+	));
+	fs::write(&signature, contents).unwrap();
+
+	// Only the contents read beforehand are checked
+	fs::write(&manifest, "1A2B3C4E  file\n").unwrap();
+	assert!(verify_manifest(&manifest, signed, &signature, &public).is_ok());
+	assert!(matches!(
+		verify_manifest(&manifest, b"1A2B3C4E  file\n", &signature, &public),
+		Err(Error::Signature { .. })
+	));
+}
+
+#[test]
+fn encrypted_secret_keys() {
+	let dir = scratch_dir("encrypted-key");
+	let path = dir.join("key.sec");
+
+	for kdf in [
+		// N = 2^10, r = 8, p = 1, bounded by the operations limit
+		Kdf {
+			opslimit: 32768,
+			memlimit: 33554432,
+			stream: "1FA1348854818A9E4E9037D04148D79BF80EE7CCC89564199957A2A783532F80BD3B976DD92585C4\
+			         6F779D4A5AF6E447965A343E9E173E6ACF1435C7CE1136CE9C1D4E32ACDA432B83CAC616984557E7\
+			         B4E4E4DE4C1C068BF26C1387E605023CC744DCA45CEBB9CB",
+		},
+		// N = 2^10, r = 8, p = 2, bounded by the memory limit
+		Kdf {
+			opslimit: 65536,
+			memlimit: 1048576,
+			stream: "205BCD6AACB4A49CB4DEF396564238BD203657C81A40A73714B2A52AA81C385840A8157E507AE5D0\
+			         26B7C7BD9E6975A46DA3329283A57F6B4E94794C1AEF0EA9901DA611D139E2936479BCE573676965\
+			         895CB6E09818C69917CEA7BC75427D1D2F5167A8816DCDCA",
+		},
+	] {
+		let public = write_key(&path, 3, *b"abcdefgh", Some(&kdf));
+		let secret = SecretKey::open_with(&path, || Ok(PASSWORD.to_string())).unwrap();
+		assert_eq!(secret.public_key(), PublicKey::open(&public).unwrap());
+
+		assert!(matches!(
+			SecretKey::open_with(&path, || Ok("wrong horse".to_string())),
+			Err(Error::Signature { .. })
+		));
+	}
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use quickdash::{
+	operations::{write_tree_comparison, ManifestHeader, MerkleTree, TreeDifference},
+	Algorithm,
+	Digests,
+	Error,
+};
+
+fn hashes(entries: &[(&str, &str)]) -> BTreeMap<String, Digests> {
+	entries
+		.iter()
+		.map(|(name, digest)| {
+			let digests = Digests::from([(Algorithm::CRC32, digest.to_string())]);
+			(name.to_string(), digests)
+		})
+		.collect()
+}
+
+#[test]
+fn merkle_roots() {
+	let was = hashes(&[
+		("a/b/c", "00000001"),
+		("a/d", "00000002"),
+		("e/f", "00000003"),
+		("g", "00000004"),
+		("ignored", "--------"),
+	]);
+	let tree = MerkleTree::new(Algorithm::CRC32, &was);
+	assert_eq!(tree.root().len(), Algorithm::CRC32.hexlen());
+
+	// Ignored files don't count
+	let mut unignored = was.clone();
+	unignored.remove("ignored");
+	assert_eq!(MerkleTree::new(Algorithm::CRC32, &unignored), tree);
+
+	let subtrees: Vec<_> = tree.subtrees().into_iter().map(|(path, _)| path).collect();
+	assert_eq!(subtrees, ["./", "a/", "a/b/", "e/"]);
+
+	// Moving a file changes the root even though no digest changed
+	let moved = hashes(&[
+		("a/b/c", "00000001"),
+		("a/d", "00000002"),
+		("e/h", "00000003"),
+		("g", "00000004"),
+	]);
+	assert_ne!(
+		MerkleTree::new(Algorithm::CRC32, &moved).root(),
+		tree.root()
+	);
+}
+
+#[test]
+fn tree_comparison() {
+	let was = MerkleTree::new(
+		Algorithm::CRC32,
+		&hashes(&[
+			("a/b/c", "00000001"),
+			("a/d", "00000002"),
+			("e/f", "00000003"),
+			("g", "00000004"),
+		]),
+	);
+	let is = MerkleTree::new(
+		Algorithm::CRC32,
+		&hashes(&[
+			("a/b/c", "00000001"),
+			("a/d", "00000005"),
+			("e", "00000003"),
+			("g", "00000004"),
+			("h/i", "00000006"),
+		]),
+	);
+
+	assert_eq!(was.compare(&was), []);
+	// `a/b/` matches, so it isn't descended into
+	assert_eq!(
+		is.compare(&was),
+		[
+			TreeDifference::SubtreeDiffers("a/".to_string()),
+			TreeDifference::FileDiffers("a/d".to_string()),
+			TreeDifference::Removed("e/".to_string()),
+			TreeDifference::Added("e".to_string()),
+			TreeDifference::Added("h/".to_string()),
+		]
+	);
+
+	let mut out = Vec::new();
+	assert!(write_tree_comparison(&mut out, &was, &was).is_ok());
+	assert_eq!(
+		String::from_utf8(out).unwrap(),
+		format!("Tree root {} matches\n", was.root())
+	);
+
+	let mut out = Vec::new();
+	assert!(matches!(
+		write_tree_comparison(&mut out, &is, &was),
+		Err(Error::NFilesDiffer(4))
+	));
+	let out = String::from_utf8(out).unwrap();
+	assert!(out.contains("Subtree \"a/\" doesn't match\nFile \"a/d\" doesn't match\n"));
+}
+
+#[test]
+fn header_records_the_root() {
+	let mut header = ManifestHeader::new(vec![Algorithm::CRC32], None, false, vec![]);
+	header.tree_root = Some((Algorithm::CRC32, "0A1B2C3D".to_string()));
+
+	let mut out = Vec::new();
+	header.write(&mut out).unwrap();
+	let text = String::from_utf8(out).unwrap();
+	assert!(text.contains("# tree-root: CRC32 0A1B2C3D\n"));
+
+	let lines: Vec<_> = text.lines().collect();
+	assert_eq!(ManifestHeader::parse(&lines).unwrap(), Some(header));
+}